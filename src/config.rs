@@ -0,0 +1,723 @@
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use crate::error::{Error, Result};
+use crate::store::file::LockFile;
+
+pub mod layered;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Line {
+    Section {
+        name: String,
+        subsection: Option<String>,
+        raw: String,
+    },
+    Entry {
+        name: String,
+        value: Option<String>,
+        raw: String,
+        // whether the entry shares a line with its section header, as in `[core] bare = true`
+        inline: bool,
+    },
+    Other(String),
+}
+
+/// A git config file like `.git/config`, made up of `[section "subsection"]` headers each
+/// followed by `name = value` entries.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Config {
+    lines: Vec<Line>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Key {
+    section: String,
+    subsection: Option<String>,
+    name: String,
+}
+
+impl Key {
+    fn parse(key: &str) -> Result<Self> {
+        let invalid = || Error::InvalidArgument(format!("{} is not a valid config key.", key));
+        let (section, rest) = key.split_once('.').ok_or_else(invalid)?;
+        let (subsection, name) = match rest.rsplit_once('.') {
+            Some((subsection, name)) => (Some(subsection.to_string()), name),
+            None => (None, rest),
+        };
+        if !valid_section(section) || !valid_name(name) {
+            return Err(invalid());
+        }
+        Ok(Self {
+            section: section.to_lowercase(),
+            subsection,
+            name: name.to_string(),
+        })
+    }
+
+    fn in_section(&self, name: &str, subsection: &Option<String>) -> bool {
+        name.eq_ignore_ascii_case(&self.section) && *subsection == self.subsection
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.subsection {
+            Some(subsection) => write!(
+                f,
+                "{}.{}.{}",
+                self.section,
+                subsection,
+                self.name.to_lowercase()
+            ),
+            None => write!(f, "{}.{}", self.section, self.name.to_lowercase()),
+        }
+    }
+}
+
+/// Writes a key the way git lists it, with its section and name lowercased.
+pub fn canonical_key(key: &str) -> Result<String> {
+    Key::parse(key).map(|key| key.to_string())
+}
+
+/// Reads a boolean the way git does, accepting true, yes, on or 1 and false, no, off, 0 or
+/// nothing at all.
+pub fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" | "" => Some(false),
+        _ => None,
+    }
+}
+
+/// Reads an integer the way git does, where a `k`, `m` or `g` suffix scales it by 1024, 1024^2
+/// or 1024^3.
+pub fn parse_int(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let (number, scale) = match value.chars().last()?.to_ascii_lowercase() {
+        'k' => (&value[..value.len() - 1], 1 << 10),
+        'm' => (&value[..value.len() - 1], 1 << 20),
+        'g' => (&value[..value.len() - 1], 1 << 30),
+        _ => (value, 1),
+    };
+    number.parse::<i64>().ok()?.checked_mul(scale)
+}
+
+impl Config {
+    pub fn deserialize(content: &str) -> Result<Self> {
+        let mut lines = vec![];
+        let mut in_section = false;
+        let mut physical = content.lines();
+        while let Some(raw) = physical.next() {
+            let corrupt = || Error::Corrupt(format!("Config line {:?} couldn't be parsed.", raw));
+            let trimmed = raw.trim_start();
+            if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with(';') {
+                lines.push(Line::Other(raw.to_string()));
+                continue;
+            }
+
+            let mut rest = raw;
+            let inline = trimmed.starts_with('[');
+            if inline {
+                let open = raw.len() - trimmed.len();
+                let close = open + header_end(trimmed).ok_or_else(corrupt)?;
+                let (name, subsection) = parse_header(&raw[open + 1..close]).ok_or_else(corrupt)?;
+                in_section = true;
+                // a comment after the header stays part of it
+                let after = raw[close + 1..].trim_start();
+                let end = if after.is_empty() || after.starts_with(['#', ';']) {
+                    raw.len()
+                } else {
+                    close + 1
+                };
+                lines.push(Line::Section {
+                    name,
+                    subsection,
+                    raw: raw[..end].to_string(),
+                });
+                rest = &raw[end..];
+                if rest.is_empty() {
+                    continue;
+                }
+            }
+
+            // a backslash at the very end of a line carries the value onto the next
+            let mut text = rest.to_string();
+            while ends_with_escape(&text) {
+                let next = physical.next().ok_or_else(corrupt)?;
+                text.push('\n');
+                text.push_str(next);
+            }
+            let (name, value) = parse_entry(&text).ok_or_else(corrupt)?;
+            if !in_section {
+                return Err(corrupt());
+            }
+            lines.push(Line::Entry {
+                name,
+                value,
+                raw: text,
+                inline,
+            });
+        }
+        Ok(Self { lines })
+    }
+
+    pub fn serialize(&self) -> String {
+        let mut content = String::new();
+        for line in &self.lines {
+            match line {
+                Line::Entry {
+                    raw, inline: true, ..
+                } => content.push_str(raw),
+                Line::Section { raw, .. } | Line::Entry { raw, .. } | Line::Other(raw) => {
+                    if !content.is_empty() {
+                        content.push('\n');
+                    }
+                    content.push_str(raw);
+                }
+            }
+        }
+        if !content.is_empty() {
+            content.push('\n');
+        }
+        content
+    }
+
+    /// Reads a config file, treating one that doesn't exist as empty.
+    pub fn read(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(content) => Config::deserialize(&content),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Replaces a config file while holding its lock, as git does.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        // the global config can live in a directory that doesn't exist yet
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut lock = LockFile::acquire(path.to_path_buf())?;
+        lock.write(self.serialize().as_bytes())?;
+        lock.commit()
+    }
+
+    /// Lists every entry in the order they appear, keyed the way git lists them, where a name
+    /// with no value has no value rather than `true`.
+    pub fn values(&self) -> Vec<(String, Option<String>)> {
+        let mut values = vec![];
+        let mut section = None;
+        for line in &self.lines {
+            match line {
+                Line::Section {
+                    name, subsection, ..
+                } => section = Some((name.to_lowercase(), subsection.clone())),
+                Line::Entry { name, value, .. } => {
+                    if let Some((section, subsection)) = &section {
+                        let key = Key {
+                            section: section.clone(),
+                            subsection: subsection.clone(),
+                            name: name.to_lowercase(),
+                        };
+                        values.push((key.to_string(), value.clone()));
+                    }
+                }
+                Line::Other(_) => {}
+            }
+        }
+        values
+    }
+
+    fn entries<'a>(&'a self, key: &'a Key) -> impl Iterator<Item = (usize, Option<&'a str>)> + 'a {
+        let mut matching = false;
+        self.lines
+            .iter()
+            .enumerate()
+            .filter_map(move |(i, line)| match line {
+                Line::Section {
+                    name, subsection, ..
+                } => {
+                    matching = key.in_section(name, subsection);
+                    None
+                }
+                Line::Entry { name, value, .. }
+                    if matching && name.eq_ignore_ascii_case(&key.name) =>
+                {
+                    Some((i, value.as_deref()))
+                }
+                _ => None,
+            })
+    }
+
+    /// Reads the last value set for a key like `core.bare`, where a name with no value reads
+    /// as `true`.
+    pub fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.get_all(key)?.pop())
+    }
+
+    /// Reads every value of a key that can be set more than once, like `remote.origin.fetch`.
+    pub fn get_all(&self, key: &str) -> Result<Vec<String>> {
+        let key = Key::parse(key)?;
+        Ok(self
+            .entries(&key)
+            .map(|(_, value)| value.unwrap_or("true").to_string())
+            .collect())
+    }
+
+    pub fn get_bool(&self, key: &str) -> Result<Option<bool>> {
+        self.get(key)?
+            .map(|value| {
+                parse_bool(&value).ok_or_else(|| {
+                    Error::Corrupt(format!(
+                        "Config value {} for {} is not a boolean.",
+                        value, key
+                    ))
+                })
+            })
+            .transpose()
+    }
+
+    pub fn get_int(&self, key: &str) -> Result<Option<i64>> {
+        self.get(key)?
+            .map(|value| {
+                parse_int(&value).ok_or_else(|| {
+                    Error::Corrupt(format!(
+                        "Config value {} for {} is not an integer.",
+                        value, key
+                    ))
+                })
+            })
+            .transpose()
+    }
+
+    /// Sets a key, replacing its last value if it has one or otherwise adding it to the end of
+    /// its section, which is created if needed.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let parsed = Key::parse(key)?;
+        match self.entries(&parsed).last() {
+            Some((i, _)) => self.lines[i] = entry(&parsed, value),
+            None => self.insert(&parsed, value),
+        }
+        Ok(())
+    }
+
+    /// Adds another value for a key, keeping any it already has.
+    pub fn add(&mut self, key: &str, value: &str) -> Result<()> {
+        self.insert(&Key::parse(key)?, value);
+        Ok(())
+    }
+
+    /// Replaces every value of a key with a single one, kept where the first of them was.
+    pub fn replace_all(&mut self, key: &str, value: &str) -> Result<()> {
+        let parsed = Key::parse(key)?;
+        let found: Vec<_> = self.entries(&parsed).map(|(i, _)| i).collect();
+        match found.split_first() {
+            Some((first, rest)) => {
+                for i in rest.iter().rev() {
+                    self.lines.remove(*i);
+                }
+                self.lines[*first] = entry(&parsed, value);
+            }
+            None => self.insert(&parsed, value),
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, key: &Key, value: &str) {
+        let mut end = None;
+        let mut matching = false;
+        for (i, line) in self.lines.iter().enumerate() {
+            match line {
+                Line::Section {
+                    name, subsection, ..
+                } => {
+                    matching = key.in_section(name, subsection);
+                    if matching {
+                        end = Some(i);
+                    }
+                }
+                Line::Entry { .. } if matching => end = Some(i),
+                _ => {}
+            }
+        }
+        match end {
+            Some(i) => self.lines.insert(i + 1, entry(key, value)),
+            None => {
+                self.lines
+                    .push(section_header(&key.section, key.subsection.clone()));
+                self.lines.push(entry(key, value));
+            }
+        }
+    }
+
+    /// Removes every value of a key, returning whether there were any.
+    pub fn unset(&mut self, key: &str) -> Result<bool> {
+        let parsed = Key::parse(key)?;
+        let found: Vec<_> = self.entries(&parsed).map(|(i, _)| i).collect();
+        for i in found.iter().rev() {
+            self.lines.remove(*i);
+        }
+        Ok(!found.is_empty())
+    }
+
+    /// Removes a section along with everything in it, returning whether it existed.
+    pub fn remove_section(&mut self, name: &str, subsection: Option<&str>) -> bool {
+        let subsection = subsection.map(str::to_string);
+        let count = self.lines.len();
+        let mut removing = false;
+        self.lines.retain(|line| {
+            if let Line::Section {
+                name: section,
+                subsection: sub,
+                ..
+            } = line
+            {
+                removing = section.eq_ignore_ascii_case(name) && *sub == subsection;
+            }
+            !removing
+        });
+        self.lines.len() != count
+    }
+
+    /// Renames a section's subsection, e.g. when `[branch "old"]` becomes `[branch "new"]`,
+    /// returning whether it existed.
+    pub fn rename_section(&mut self, name: &str, from: &str, to: &str) -> bool {
+        let mut renamed = false;
+        for line in &mut self.lines {
+            if let Line::Section {
+                name: section,
+                subsection: Some(sub),
+                ..
+            } = line
+            {
+                if section.eq_ignore_ascii_case(name) && sub == from {
+                    *line = section_header(name, Some(to.to_string()));
+                    renamed = true;
+                }
+            }
+        }
+        renamed
+    }
+}
+
+fn valid_section(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+}
+
+fn valid_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+fn ends_with_escape(text: &str) -> bool {
+    text.chars().rev().take_while(|c| *c == '\\').count() % 2 == 1
+}
+
+fn header_end(line: &str) -> Option<usize> {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ']' if !quoted => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_header(header: &str) -> Option<(String, Option<String>)> {
+    match header.split_once(char::is_whitespace) {
+        Some((name, quoted)) => {
+            let quoted = quoted.trim().strip_prefix('"')?.strip_suffix('"')?;
+            let mut subsection = String::new();
+            let mut chars = quoted.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => subsection.push(chars.next()?),
+                    '"' => return None,
+                    c => subsection.push(c),
+                }
+            }
+            Some((name.to_string(), Some(subsection))).filter(|_| valid_section(name))
+        }
+        None => match header.split_once('.') {
+            Some((name, sub)) => Some((name.to_string(), Some(sub.to_lowercase()))),
+            None => Some((header.to_string(), None)),
+        }
+        .filter(|(name, _)| valid_section(name)),
+    }
+}
+
+fn parse_entry(text: &str) -> Option<(String, Option<String>)> {
+    let end = text.find(['=', '#', ';']).unwrap_or(text.len());
+    let name = text[..end].trim();
+    if !valid_name(name) {
+        return None;
+    }
+    match text[end..].strip_prefix('=') {
+        Some(value) => Some((name.to_string(), Some(parse_value(value)?))),
+        None => Some((name.to_string(), None)),
+    }
+}
+
+fn parse_value(raw: &str) -> Option<String> {
+    let mut value = String::new();
+    // whitespace is only kept if it's quoted or followed by something that isn't
+    let mut pending = String::new();
+    let mut quoted = false;
+    let mut chars = raw.trim_start().chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                value.push_str(&pending);
+                pending.clear();
+                quoted = !quoted;
+            }
+            '\\' => {
+                let escaped = match chars.next()? {
+                    '\n' => continue,
+                    'n' => '\n',
+                    't' => '\t',
+                    'b' => '\u{8}',
+                    c @ ('\\' | '"') => c,
+                    _ => return None,
+                };
+                value.push_str(&pending);
+                pending.clear();
+                value.push(escaped);
+            }
+            '#' | ';' if !quoted => break,
+            c if c.is_whitespace() && !quoted => pending.push(c),
+            c => {
+                value.push_str(&pending);
+                pending.clear();
+                value.push(c);
+            }
+        }
+    }
+    Some(value).filter(|_| !quoted)
+}
+
+fn quote(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            c => escaped.push(c),
+        }
+    }
+    let needs_quotes = value.starts_with(char::is_whitespace)
+        || value.ends_with(char::is_whitespace)
+        || value.contains(['#', ';']);
+    if needs_quotes {
+        format!("\"{}\"", escaped)
+    } else {
+        escaped
+    }
+}
+
+fn entry(key: &Key, value: &str) -> Line {
+    Line::Entry {
+        name: key.name.clone(),
+        value: Some(value.to_string()),
+        raw: format!("\t{} = {}", key.name, quote(value)),
+        inline: false,
+    }
+}
+
+fn section_header(name: &str, subsection: Option<String>) -> Line {
+    let raw = match &subsection {
+        Some(sub) => format!(
+            "[{} \"{}\"]",
+            name,
+            sub.replace('\\', "\\\\").replace('"', "\\\"")
+        ),
+        None => format!("[{}]", name),
+    };
+    Line::Section {
+        name: name.to_string(),
+        subsection,
+        raw,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{canonical_key, parse_int, Config};
+    use crate::error::Error;
+
+    const CONFIG: &str = "# written by hand\n\
+                          [core]\n\
+                          \trepositoryformatversion = 0\n\
+                          \tBare\n\
+                          [branch \"feature/x\"]\n\
+                          \tremote = origin ; where it came from\n\
+                          \tmerge = refs/heads/x\n\
+                          [user]\n\
+                          \tname = \"  Rhys \\\"R\\\" \"\n";
+
+    #[test]
+    fn parses_values() {
+        let config = Config::deserialize(CONFIG).unwrap();
+        assert_eq!(
+            config.get("core.repositoryformatversion").unwrap().unwrap(),
+            "0"
+        );
+        assert_eq!(config.get("CORE.bare").unwrap().unwrap(), "true");
+        assert_eq!(
+            config.get("branch.feature/x.remote").unwrap().unwrap(),
+            "origin"
+        );
+        assert_eq!(config.get("branch.Feature/x.remote").unwrap(), None);
+        assert_eq!(config.get("user.name").unwrap().unwrap(), "  Rhys \"R\" ");
+        assert_eq!(config.get("user.email").unwrap(), None);
+        assert_eq!(config.get_bool("core.bare").unwrap(), Some(true));
+        assert!(matches!(
+            config.get_bool("branch.feature/x.remote"),
+            Err(Error::Corrupt(_))
+        ));
+        assert_eq!(config.serialize(), CONFIG);
+    }
+
+    #[test]
+    fn parses_multivars_and_continuations() {
+        let content = "[remote \"origin\"] url = https://example.com/repo\n\
+                       \tfetch = +refs/heads/*:refs/remotes/origin/*\n\
+                       \tfetch = +refs/tags/*:refs/tags/*\n\
+                       [core.Pager]\n\
+                       \tcommand = less \\\n\
+                       \t  -R\n\
+                       [pack]\n\
+                       \twindowMemory = 2k\n";
+        let config = Config::deserialize(content).unwrap();
+        assert_eq!(
+            config.get("remote.origin.url").unwrap().unwrap(),
+            "https://example.com/repo"
+        );
+        assert_eq!(
+            config.get_all("remote.origin.fetch").unwrap(),
+            vec![
+                "+refs/heads/*:refs/remotes/origin/*",
+                "+refs/tags/*:refs/tags/*"
+            ]
+        );
+        assert_eq!(
+            config.get("core.pager.command").unwrap().unwrap(),
+            "less \t  -R"
+        );
+        assert_eq!(config.get_int("pack.windowmemory").unwrap(), Some(2048));
+        assert_eq!(config.values()[0].0, "remote.origin.url");
+        assert_eq!(config.values()[4].0, "pack.windowmemory");
+        assert_eq!(config.serialize(), content);
+    }
+
+    #[test]
+    fn parses_integers() {
+        assert_eq!(parse_int("42"), Some(42));
+        assert_eq!(parse_int("-3"), Some(-3));
+        assert_eq!(parse_int("1M"), Some(1 << 20));
+        assert_eq!(parse_int("1g"), Some(1 << 30));
+        assert_eq!(parse_int("k"), None);
+        assert_eq!(parse_int("lots"), None);
+        assert_eq!(
+            canonical_key("Branch.Feature.Remote").unwrap(),
+            "branch.Feature.remote"
+        );
+    }
+
+    #[test]
+    fn edits_values_in_place() {
+        let mut config = Config::deserialize(CONFIG).unwrap();
+        config
+            .set("branch.feature/x.merge", "refs/heads/y")
+            .unwrap();
+        config.set("core.filemode", "true").unwrap();
+        config.set("branch.main.remote", "#origin").unwrap();
+        assert!(config.unset("core.bare").unwrap());
+        assert!(!config.unset("core.bare").unwrap());
+
+        let edited = config.serialize();
+        assert!(edited.starts_with(
+            "# written by hand\n\
+             [core]\n\
+             \trepositoryformatversion = 0\n\
+             \tfilemode = true\n\
+             [branch \"feature/x\"]\n\
+             \tremote = origin ; where it came from\n\
+             \tmerge = refs/heads/y\n"
+        ));
+        assert!(edited.ends_with("[branch \"main\"]\n\tremote = \"#origin\"\n"));
+        let reread = Config::deserialize(&edited).unwrap();
+        assert_eq!(
+            reread.get("branch.main.remote").unwrap().unwrap(),
+            "#origin"
+        );
+    }
+
+    #[test]
+    fn edits_multivars() {
+        let mut config = Config::deserialize("[core] bare = false\n").unwrap();
+        config.add("remote.origin.fetch", "one").unwrap();
+        config.add("remote.origin.fetch", "two").unwrap();
+        config.set("core.bare", "true").unwrap();
+        assert_eq!(
+            config.serialize(),
+            "[core]\n\tbare = true\n[remote \"origin\"]\n\tfetch = one\n\tfetch = two\n"
+        );
+        config.replace_all("remote.origin.fetch", "three").unwrap();
+        assert_eq!(
+            config.get_all("remote.origin.fetch").unwrap(),
+            vec!["three"]
+        );
+    }
+
+    #[test]
+    fn renames_and_removes_sections() {
+        let mut config = Config::deserialize(CONFIG).unwrap();
+        assert!(config.rename_section("branch", "feature/x", "feature/y"));
+        assert_eq!(config.get("branch.feature/x.merge").unwrap(), None);
+        assert_eq!(
+            config.get("branch.feature/y.merge").unwrap().unwrap(),
+            "refs/heads/x"
+        );
+        assert!(config.remove_section("branch", Some("feature/y")));
+        assert!(!config.remove_section("branch", Some("feature/y")));
+        assert_eq!(config.get("branch.feature/y.merge").unwrap(), None);
+        assert_eq!(config.get("user.name").unwrap().unwrap(), "  Rhys \"R\" ");
+    }
+
+    #[test]
+    fn fails_to_parse_malformed_config() {
+        for content in &[
+            "name = value\n",
+            "[core\n",
+            "[core]\n\t= value\n",
+            "[core]\n\tname = \"unterminated\n",
+            "[core]\n\tname = trailing \\",
+            "[core]\n\tname = \\q\n",
+            "[branch \"x]\n",
+        ] {
+            assert!(
+                matches!(Config::deserialize(content), Err(Error::Corrupt(_))),
+                "{}",
+                content
+            );
+        }
+        assert!(matches!(
+            Config::default().get("core"),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+}
@@ -0,0 +1,332 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+use crate::config::{canonical_key, parse_bool, parse_int, Config};
+use crate::error::{Error, Result};
+use crate::glob;
+
+// the same limit as git, which is enough to catch a file that includes itself
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+/// Where a config value was read from, from the least to the most specific.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// Shared by every user, usually `/etc/gitconfig`.
+    System,
+    /// The current user's, usually `~/.gitconfig`.
+    Global,
+    /// The repository's own `.git/config`.
+    Local,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 3] = [Scope::System, Scope::Global, Scope::Local];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scope::System => "system",
+            Scope::Global => "global",
+            Scope::Local => "local",
+        }
+    }
+
+    /// The file that changes to this scope are written to, honouring `GIT_CONFIG_SYSTEM` and
+    /// `GIT_CONFIG_GLOBAL` like git does.
+    pub fn path(&self, git_dir: Option<&Path>) -> Result<PathBuf> {
+        match self {
+            Scope::System => Ok(env::var_os("GIT_CONFIG_SYSTEM")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| PathBuf::from("/etc/gitconfig"))),
+            Scope::Global => match env::var_os("GIT_CONFIG_GLOBAL") {
+                Some(path) => Ok(PathBuf::from(path)),
+                None => home()
+                    .map(|home| home.join(".gitconfig"))
+                    .ok_or_else(|| Error::NotFound("Home directory".to_string())),
+            },
+            Scope::Local => git_dir
+                .map(|dir| dir.join("config"))
+                .ok_or_else(|| Error::NotFound("Repository for local config".to_string())),
+        }
+    }
+
+    fn files(&self, git_dir: Option<&Path>) -> Vec<PathBuf> {
+        match self {
+            Scope::System => {
+                let skipped = env::var("GIT_CONFIG_NOSYSTEM")
+                    .map(|v| parse_bool(&v).unwrap_or(false))
+                    .unwrap_or(false);
+                if skipped {
+                    return vec![];
+                }
+                self.path(git_dir).into_iter().collect()
+            }
+            // git reads the XDG config before `~/.gitconfig` unless told exactly where to look
+            Scope::Global if env::var_os("GIT_CONFIG_GLOBAL").is_none() => {
+                let xdg = env::var_os("XDG_CONFIG_HOME")
+                    .map(PathBuf::from)
+                    .or_else(|| home().map(|home| home.join(".config")))
+                    .map(|dir| dir.join("git").join("config"));
+                xdg.into_iter().chain(self.path(git_dir).ok()).collect()
+            }
+            _ => self.path(git_dir).into_iter().collect(),
+        }
+    }
+}
+
+fn home() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
+
+/// A single value along with where it came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigValue {
+    /// The key as git lists it, e.g. `branch.master.remote`.
+    pub key: String,
+    /// The value, or nothing for a name on its own.
+    pub value: Option<String>,
+    pub scope: Scope,
+    /// The file the value was read from, which may have been included by another.
+    pub origin: PathBuf,
+}
+
+/// Every config value that applies to a repository, read from each scope in turn with later
+/// values taking precedence and with any `include` or `includeIf` files read in place.
+#[derive(Debug, Default)]
+pub struct LayeredConfig {
+    pub values: Vec<ConfigValue>,
+}
+
+struct Context<'a> {
+    git_dir: Option<&'a Path>,
+    branch: Option<&'a str>,
+}
+
+impl LayeredConfig {
+    /// Reads the given scopes for the repository in `git_dir`, if there is one, whose current
+    /// branch is `branch`.
+    pub fn load(scopes: &[Scope], git_dir: Option<&Path>, branch: Option<&str>) -> Result<Self> {
+        let context = Context { git_dir, branch };
+        let mut config = LayeredConfig::default();
+        for scope in scopes {
+            for path in scope.files(git_dir) {
+                config.read(*scope, &path, &context, 0)?;
+            }
+        }
+        Ok(config)
+    }
+
+    fn read(&mut self, scope: Scope, path: &Path, context: &Context, depth: usize) -> Result<()> {
+        if depth > MAX_INCLUDE_DEPTH {
+            return Err(Error::Corrupt(format!(
+                "Config {} includes too many files to follow.",
+                path.display()
+            )));
+        }
+        for (key, value) in Config::read(path)?.values() {
+            let included = match (key.as_str(), &value) {
+                ("include.path", Some(value)) => Some(value.clone()),
+                (_, Some(value)) if key.starts_with("includeif.") && key.ends_with(".path") => {
+                    let condition = &key["includeif.".len()..key.len() - ".path".len()];
+                    Some(value.clone()).filter(|_| context.matches(condition, path))
+                }
+                _ => None,
+            };
+            self.values.push(ConfigValue {
+                key,
+                value,
+                scope,
+                origin: path.to_path_buf(),
+            });
+            // included values go where the include is, so anything after it still wins
+            if let Some(included) = included {
+                let target = resolve(&included, path)?;
+                self.read(scope, &target, context, depth + 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the last value set for a key, where a name with no value reads as `true`.
+    pub fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.get_all(key)?.pop())
+    }
+
+    /// Reads every value of a key across all the scopes, from the least specific.
+    pub fn get_all(&self, key: &str) -> Result<Vec<String>> {
+        let key = canonical_key(key)?;
+        Ok(self
+            .values
+            .iter()
+            .filter(|value| value.key == key)
+            .map(|value| value.value.clone().unwrap_or_else(|| "true".to_string()))
+            .collect())
+    }
+
+    pub fn get_bool(&self, key: &str) -> Result<Option<bool>> {
+        self.get(key)?
+            .map(|value| {
+                parse_bool(&value).ok_or_else(|| {
+                    Error::Corrupt(format!(
+                        "Config value {} for {} is not a boolean.",
+                        value, key
+                    ))
+                })
+            })
+            .transpose()
+    }
+
+    pub fn get_int(&self, key: &str) -> Result<Option<i64>> {
+        self.get(key)?
+            .map(|value| {
+                parse_int(&value).ok_or_else(|| {
+                    Error::Corrupt(format!(
+                        "Config value {} for {} is not an integer.",
+                        value, key
+                    ))
+                })
+            })
+            .transpose()
+    }
+}
+
+impl Context<'_> {
+    fn matches(&self, condition: &str, including: &Path) -> bool {
+        if let Some(pattern) = condition.strip_prefix("gitdir:") {
+            self.matches_git_dir(pattern, including, false)
+        } else if let Some(pattern) = condition.strip_prefix("gitdir/i:") {
+            self.matches_git_dir(pattern, including, true)
+        } else if let Some(pattern) = condition.strip_prefix("onbranch:") {
+            let pattern = match pattern.strip_suffix('/') {
+                Some(prefix) => format!("{}/**", prefix),
+                None => pattern.to_string(),
+            };
+            self.branch
+                .is_some_and(|branch| glob::matches(&pattern, branch, true))
+        } else {
+            false
+        }
+    }
+
+    fn matches_git_dir(&self, pattern: &str, including: &Path, fold_case: bool) -> bool {
+        let git_dir = match self.git_dir {
+            Some(dir) => dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf()),
+            None => return false,
+        };
+        let mut pattern = if let Some(rest) = pattern.strip_prefix("~/") {
+            match home() {
+                Some(home) => format!("{}/{}", home.display(), rest),
+                None => return false,
+            }
+        } else if let Some(rest) = pattern.strip_prefix("./") {
+            match including.parent() {
+                Some(dir) => format!("{}/{}", dir.display(), rest),
+                None => return false,
+            }
+        } else if !pattern.starts_with('/') {
+            format!("**/{}", pattern)
+        } else {
+            pattern.to_string()
+        };
+        // a trailing slash matches everything inside the directory
+        if pattern.ends_with('/') {
+            pattern.push_str("**");
+        }
+        let git_dir = git_dir.to_string_lossy();
+        if fold_case {
+            glob::matches(&pattern.to_lowercase(), &git_dir.to_lowercase(), true)
+        } else {
+            glob::matches(&pattern, &git_dir, true)
+        }
+    }
+}
+
+fn resolve(path: &str, including: &Path) -> Result<PathBuf> {
+    match path.strip_prefix("~/") {
+        Some(rest) => home()
+            .map(|home| home.join(rest))
+            .ok_or_else(|| Error::NotFound("Home directory".to_string())),
+        None => Ok(including
+            .parent()
+            .map(|dir| dir.join(path))
+            .unwrap_or_else(|| PathBuf::from(path))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use crate::config::layered::{LayeredConfig, Scope};
+    use crate::error::Error;
+
+    #[test]
+    fn reads_includes_in_place() {
+        let dir = env::temp_dir().join(format!("gitrs-includes-{}", std::process::id()));
+        let git_dir = dir.join("work").join("repo").join(".git");
+        fs::create_dir_all(&git_dir).unwrap();
+        fs::write(
+            git_dir.join("config"),
+            "[user]\n\
+             \tname = Local\n\
+             [include]\n\
+             \tpath = ../../shared\n\
+             [includeIf \"gitdir:work/\"]\n\
+             \tpath = ../../work\n\
+             [includeIf \"gitdir:elsewhere/\"]\n\
+             \tpath = ../../elsewhere\n\
+             [includeIf \"onbranch:feature/\"]\n\
+             \tpath = ../../feature\n\
+             [core]\n\
+             \tbare = false\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("work").join("shared"),
+            "[user]\n\temail = shared@example.com\n\tname = Shared\n",
+        )
+        .unwrap();
+        fs::write(dir.join("work").join("work"), "[core]\n\tbare = true\n").unwrap();
+        fs::write(dir.join("work").join("elsewhere"), "[core]\n\tabbrev = 9\n").unwrap();
+        fs::write(dir.join("work").join("feature"), "[pack]\n\tdepth = 1k\n").unwrap();
+
+        let config =
+            LayeredConfig::load(&[Scope::Local], Some(&git_dir), Some("feature/x")).unwrap();
+        assert_eq!(config.get("user.name").unwrap().unwrap(), "Shared");
+        assert_eq!(
+            config.get_all("user.name").unwrap(),
+            vec!["Local", "Shared"]
+        );
+        assert_eq!(
+            config.get("user.email").unwrap().unwrap(),
+            "shared@example.com"
+        );
+        // the local value comes after the include so it still wins
+        assert_eq!(config.get_bool("core.bare").unwrap(), Some(false));
+        assert_eq!(config.get("core.abbrev").unwrap(), None);
+        assert_eq!(config.get_int("pack.depth").unwrap(), Some(1024));
+        assert_eq!(config.values[2].origin, git_dir.join("../../shared"));
+        assert!(config
+            .values
+            .iter()
+            .all(|value| value.scope == Scope::Local));
+
+        let config = LayeredConfig::load(&[Scope::Local], Some(&git_dir), Some("main")).unwrap();
+        assert_eq!(config.get("pack.depth").unwrap(), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn fails_on_recursive_includes() {
+        let dir = env::temp_dir().join(format!("gitrs-recursive-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("config"), "[include]\n\tpath = config\n").unwrap();
+        assert!(matches!(
+            LayeredConfig::load(&[Scope::Local], Some(&dir), None),
+            Err(Error::Corrupt(_))
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
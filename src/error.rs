@@ -0,0 +1,69 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+/// Everything that can go wrong while reading or writing a repository.
+#[derive(Debug)]
+pub enum Error {
+    /// An object, reference or file that was asked for doesn't exist.
+    NotFound(String),
+    /// Data read from the repository couldn't be parsed.
+    Corrupt(String),
+    /// A reference couldn't be parsed or didn't point at what was expected.
+    InvalidRef(String),
+    /// An argument given by the caller isn't valid, e.g. an unknown object type.
+    InvalidArgument(String),
+    /// The operation was refused because it would overwrite existing data.
+    Refused(String),
+    Io(io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotFound(message) => write!(f, "not found: {}", message),
+            Error::Corrupt(message) => write!(f, "corrupt: {}", message),
+            Error::InvalidRef(message) => write!(f, "invalid reference: {}", message),
+            Error::InvalidArgument(message) => write!(f, "invalid argument: {}", message),
+            Error::Refused(message) => write!(f, "refused: {}", message),
+            Error::Io(error) => write!(f, "io: {}", error),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::error::Error;
+
+    #[test]
+    fn displays_messages() {
+        let error = Error::NotFound("refs/heads/master".to_string());
+        assert_eq!(error.to_string(), "not found: refs/heads/master");
+    }
+
+    #[test]
+    fn converts_io_errors() {
+        let error: Error = io::Error::other("disk on fire").into();
+        assert!(matches!(error, Error::Io(_)));
+        assert_eq!(error.to_string(), "io: disk on fire");
+    }
+}
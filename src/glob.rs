@@ -0,0 +1,127 @@
+/// Matches text against a shell-style pattern with `*`, `?`, `[...]` classes and `\` escapes, as
+/// git does for things like `git tag -l 'v1.*'`.
+///
+/// When `pathname` is set, `*` and `?` stop at slashes and only `**` can cross them, as in
+/// gitignore patterns and `includeIf` conditions.
+pub fn matches(pattern: &str, text: &str, pathname: bool) -> bool {
+    let pattern: Vec<_> = pattern.chars().collect();
+    let text: Vec<_> = text.chars().collect();
+    match_from(&pattern, &text, pathname)
+}
+
+fn match_from(pattern: &[char], text: &[char], pathname: bool) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') => {
+            let stars = pattern.iter().take_while(|c| **c == '*').count();
+            let rest = &pattern[stars..];
+            let crosses = !pathname || stars > 1;
+            // `**/` can also match no directories at all
+            if pathname
+                && stars > 1
+                && rest.first() == Some(&'/')
+                && match_from(&rest[1..], text, true)
+            {
+                return true;
+            }
+            for i in 0..=text.len() {
+                if match_from(rest, &text[i..], pathname) {
+                    return true;
+                }
+                if i < text.len() && text[i] == '/' && !crosses {
+                    break;
+                }
+            }
+            false
+        }
+        Some('?') => match text.first() {
+            Some('/') if pathname => false,
+            Some(_) => match_from(&pattern[1..], &text[1..], pathname),
+            None => false,
+        },
+        Some('[') => match (match_class(&pattern[1..]), text.first()) {
+            (Some((matched, len)), Some(c)) => {
+                matched(*c)
+                    && (!pathname || *c != '/')
+                    && match_from(&pattern[1 + len..], &text[1..], pathname)
+            }
+            (Some(_), None) => false,
+            // a `[` that's never closed is just a bracket
+            (None, _) => literal('[', pattern, text, pathname),
+        },
+        Some('\\') if pattern.len() > 1 => match text.first() {
+            Some(c) if *c == pattern[1] => match_from(&pattern[2..], &text[1..], pathname),
+            _ => false,
+        },
+        Some(c) => literal(*c, pattern, text, pathname),
+    }
+}
+
+fn literal(c: char, pattern: &[char], text: &[char], pathname: bool) -> bool {
+    text.first() == Some(&c) && match_from(&pattern[1..], &text[1..], pathname)
+}
+
+fn match_class(class: &[char]) -> Option<(impl Fn(char) -> bool, usize)> {
+    let negated = matches!(class.first(), Some('!') | Some('^'));
+    let start = usize::from(negated);
+    // a `]` straight after the opening bracket is part of the class
+    let close = class
+        .iter()
+        .enumerate()
+        .skip(start + 1)
+        .find(|(_, c)| **c == ']')
+        .map(|(i, _)| i)?;
+    let members = class[start..close].to_vec();
+    let contains = move |c: char| {
+        let mut i = 0;
+        while i < members.len() {
+            if i + 2 < members.len() && members[i + 1] == '-' {
+                if members[i] <= c && c <= members[i + 2] {
+                    return true;
+                }
+                i += 3;
+            } else {
+                if members[i] == c {
+                    return true;
+                }
+                i += 1;
+            }
+        }
+        false
+    };
+    Some((move |c| contains(c) != negated, close + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::glob::matches;
+
+    #[test]
+    fn matches_wildcards() {
+        assert!(matches("v1.*", "v1.0", false));
+        assert!(matches("v1.*", "v1.", false));
+        assert!(!matches("v1.*", "v2.0", false));
+        assert!(matches("*", "release/v1", false));
+        assert!(matches("v?.0", "v2.0", false));
+        assert!(!matches("v?.0", "v10.0", false));
+        assert!(matches("v\\*", "v*", false));
+        assert!(!matches("v\\*", "v1", false));
+    }
+
+    #[test]
+    fn matches_classes() {
+        assert!(matches("v[0-9].[!a-z]", "v1.2", false));
+        assert!(!matches("v[0-9].[!a-z]", "v1.x", false));
+        assert!(matches("[]]", "]", false));
+        assert!(matches("v[12", "v[12", false));
+    }
+
+    #[test]
+    fn matches_paths() {
+        assert!(!matches("src/*", "src/a/b", true));
+        assert!(matches("src/**", "src/a/b", true));
+        assert!(matches("**/b", "b", true));
+        assert!(matches("/home/**/repo/", "/home/me/work/repo/", true));
+        assert!(!matches("/home/?", "/home//", true));
+    }
+}
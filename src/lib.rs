@@ -1,10 +1,27 @@
+pub use error::{Error, Result};
+pub use subcommand::branch::branch;
 pub use subcommand::cat_file::cat_file;
 pub use subcommand::checkout::checkout;
+pub use subcommand::config::{config_get, config_list, config_set, config_unset};
+pub use subcommand::gc::gc;
 pub use subcommand::hash_object::hash_object;
 pub use subcommand::init::init;
 pub use subcommand::log::log;
+pub use subcommand::ls_files::ls_files;
+pub use subcommand::pack_refs::pack_refs;
+pub use subcommand::reflog::{reflog_expire, reflog_show};
+pub use subcommand::rev_parse::rev_parse;
+pub use subcommand::tag::tag;
+pub use subcommand::update_ref::update_ref;
 
-mod object;
-mod reference;
-mod repository;
+pub mod config;
+mod error;
+pub mod glob;
+pub mod object;
+mod pack;
+pub mod reference;
+pub mod repository;
+pub mod revision;
+pub mod store;
+pub mod stream;
 mod subcommand;
@@ -1,13 +1,22 @@
 use nom::{
-    bytes::complete::take_while, character::complete::char, combinator::map_res, sequence::tuple,
+    bytes::complete::take_while,
+    character::complete::char,
+    combinator::{map_opt, map_res},
+    sequence::tuple,
     IResult,
 };
 
+use crate::error::{Error, Result};
 use constant::*;
+use id::ObjectFormat;
 
 pub mod blob;
 pub mod commit;
 pub mod constant;
+mod header;
+pub mod id;
+pub mod index;
+pub mod signature;
 pub mod tag;
 pub mod tree;
 
@@ -20,31 +29,42 @@ pub enum Object {
 }
 
 impl Object {
-    pub fn new(kind: String, content: Vec<u8>) -> Self {
+    pub fn new(kind: String, content: Vec<u8>, format: ObjectFormat) -> Result<Self> {
         match kind.as_str() {
-            NAME_BLOB => Object::Blob(blob::Blob::deserialize(content)),
-            NAME_COMMIT => Object::Commit(commit::Commit::deserialize(content)),
-            NAME_TAG => Object::Tag(tag::Tag::deserialize(content)),
-            NAME_TREE => Object::Tree(tree::Tree::deserialize(content)),
-            other => panic!(format!("Object type {} is not valid.", other)),
+            NAME_BLOB => Ok(Object::Blob(blob::Blob::deserialize(content))),
+            NAME_COMMIT => commit::Commit::deserialize(content).map(Object::Commit),
+            NAME_TAG => tag::Tag::deserialize(content).map(Object::Tag),
+            NAME_TREE => tree::Tree::deserialize(content, format).map(Object::Tree),
+            other => Err(Error::InvalidArgument(format!(
+                "Object type {} is not valid.",
+                other
+            ))),
         }
     }
 
-    pub fn serialize(&self) -> Vec<u8> {
+    /// Serializes the object's content on its own, along with the kind that goes in its header.
+    pub fn serialize_body(&self) -> (&'static str, Vec<u8>) {
         // TODO[Rhys] figure out how to deduplicate this with the deserialization match
-        let (kind, content) = match self {
+        match self {
             Self::Blob(content) => (NAME_BLOB, content.serialize()),
             Self::Commit(content) => (NAME_COMMIT, content.serialize()),
             Self::Tag(content) => (NAME_TAG, content.serialize()),
             Self::Tree(content) => (NAME_TREE, content.serialize()),
-        };
-        let size = content.len().to_string();
+        }
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let (kind, content) = self.serialize_body();
+        [Object::serialize_header(kind, content.len()), content].concat()
+    }
+
+    /// Serializes the `<kind> <size>\0` prefix that's hashed along with every object's content.
+    pub fn serialize_header(kind: &str, size: usize) -> Vec<u8> {
         [
-            kind.as_bytes().to_vec(),
-            vec![ASCII_SPACE],
-            size.as_bytes().to_vec(),
-            vec![ASCII_NULL],
-            content.to_vec(),
+            kind.as_bytes(),
+            &[ASCII_SPACE],
+            size.to_string().as_bytes(),
+            &[ASCII_NULL],
         ]
         .concat()
     }
@@ -56,33 +76,45 @@ impl Object {
     }
 
     fn parse_size(input: &[u8]) -> IResult<&[u8], usize> {
-        map_res(take_while(|c| c != ASCII_NULL), |c: &[u8]| {
-            String::from_utf8(c.to_vec()).unwrap().parse()
+        map_opt(take_while(|c| c != ASCII_NULL), |c: &[u8]| {
+            std::str::from_utf8(c).ok().and_then(|s| s.parse().ok())
         })(input)
     }
 
-    pub fn deserialize(bytes: &[u8]) -> Self {
+    /// Parses an object's kind and encoded size, returning whatever follows the header untouched.
+    pub fn parse_header(bytes: &[u8]) -> Result<(String, usize, &[u8])> {
         let (remainder, (kind, _, size, _)) = tuple((
             Object::parse_kind,
             char(ASCII_SPACE_CHAR),
             Object::parse_size,
             char(ASCII_NULL_CHAR),
         ))(bytes)
-        .unwrap();
+        .map_err(|_| Error::Corrupt("Object header couldn't be parsed.".to_string()))?;
+        Ok((kind, size, remainder))
+    }
 
-        assert_eq!(
-            size,
-            remainder.len(),
-            "Content length was not equal to the encoded size."
-        );
+    /// Splits a serialized object into its kind and content, validating the encoded size.
+    pub fn deserialize_header(bytes: &[u8]) -> Result<(String, &[u8])> {
+        let (kind, size, remainder) = Object::parse_header(bytes)?;
+        if size != remainder.len() {
+            return Err(Error::Corrupt(
+                "Content length was not equal to the encoded size.".to_string(),
+            ));
+        }
+        Ok((kind, remainder))
+    }
 
-        Self::new(kind, remainder.to_vec())
+    pub fn deserialize(bytes: &[u8], format: ObjectFormat) -> Result<Self> {
+        let (kind, content) = Object::deserialize_header(bytes)?;
+        Self::new(kind, content.to_vec(), format)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::error::Error;
     use crate::object::constant::{ASCII_NULL, ASCII_SPACE};
+    use crate::object::id::ObjectFormat;
     use crate::object::{blob, Object, Object::Blob};
 
     #[test]
@@ -104,27 +136,43 @@ mod tests {
     #[test]
     fn serializes() {
         let blob = Blob(blob::Blob {
-            content: "some blob".to_string(),
+            content: b"some blob".to_vec(),
         });
         let serialized = String::from_utf8(blob.serialize()).unwrap();
         let expected = "blob 9\u{0}some blob";
         assert_eq!(serialized, expected)
     }
 
+    #[test]
+    fn parses_header_without_content() {
+        let (kind, size, remainder) = Object::parse_header(b"blob 9\0").unwrap();
+        assert_eq!(kind, "blob");
+        assert_eq!(size, 9);
+        assert!(remainder.is_empty());
+        assert_eq!(Object::serialize_header("blob", 9), b"blob 9\0".to_vec());
+    }
+
     #[test]
     fn deserializes() {
         let serialized = "blob 9\u{0}some blob";
         let expected = Blob(blob::Blob {
-            content: "some blob".to_string(),
+            content: b"some blob".to_vec(),
         });
-        let blob = Object::deserialize(serialized.as_bytes());
+        let blob = Object::deserialize(serialized.as_bytes(), ObjectFormat::Sha1).unwrap();
         assert_eq!(blob, expected)
     }
 
     #[test]
-    #[should_panic]
-    fn panics_when_size_is_incorrect() {
+    fn fails_when_size_is_incorrect() {
         let serialized = "blob 8\u{0}some blob";
-        Object::deserialize(serialized.as_bytes());
+        let result = Object::deserialize(serialized.as_bytes(), ObjectFormat::Sha1);
+        assert!(matches!(result, Err(Error::Corrupt(_))));
+    }
+
+    #[test]
+    fn fails_when_kind_is_unknown() {
+        let serialized = "blub 9\u{0}some blob";
+        let result = Object::deserialize(serialized.as_bytes(), ObjectFormat::Sha1);
+        assert!(matches!(result, Err(Error::InvalidArgument(_))));
     }
 }
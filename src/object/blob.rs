@@ -1,17 +1,26 @@
-#[derive(Debug, PartialEq)]
+use std::fmt;
+
+#[derive(PartialEq)]
 pub struct Blob {
-    pub content: String,
+    pub content: Vec<u8>,
 }
 
 impl Blob {
     pub fn serialize(&self) -> Vec<u8> {
-        self.content.clone().into_bytes()
+        self.content.clone()
     }
 
     pub fn deserialize(bytes: Vec<u8>) -> Self {
-        Self {
-            content: String::from_utf8(bytes).unwrap(),
-        }
+        Self { content: bytes }
+    }
+}
+
+impl fmt::Debug for Blob {
+    // blobs can be arbitrary binary data so we render them lossily rather than as a byte list
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Blob")
+            .field("content", &String::from_utf8_lossy(&self.content))
+            .finish()
     }
 }
 
@@ -23,10 +32,18 @@ mod tests {
     fn round_trips_tags() {
         let serialized = "blob";
         let deserialized = Blob {
-            content: serialized.to_string(),
+            content: serialized.as_bytes().to_vec(),
         };
         let blob = Blob::deserialize(Vec::from(serialized));
         assert_eq!(blob, deserialized);
         assert_eq!(String::from_utf8(blob.serialize()).unwrap(), serialized)
     }
+
+    #[test]
+    fn round_trips_binary_content() {
+        let serialized: Vec<u8> = vec![0x89, 0x50, 0x4e, 0x47, 0x00, 0xff, 0xfe, 0x0a];
+        let blob = Blob::deserialize(serialized.clone());
+        assert_eq!(blob.content, serialized);
+        assert_eq!(blob.serialize(), serialized)
+    }
 }
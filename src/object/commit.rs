@@ -1,93 +1,113 @@
-use regex::RegexBuilder;
+use std::fmt::Display;
 
-#[derive(Debug, PartialEq)]
+use crate::error::{Error, Result};
+use crate::object::header::{self, Headers};
+use crate::object::id::ObjectId;
+use crate::object::signature::Signature;
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct Commit {
-    pub tree: String,
-    // TODO[Rhys] this should actually be a vec as commits can have zero to many parents
-    pub parent: Option<String>,
-    author: String,
-    committer: String,
-    gpgsig: Option<String>,
+    pub tree: ObjectId,
+    pub parents: Vec<ObjectId>,
+    author: Signature,
+    committer: Signature,
+    // anything after the committer, e.g. `encoding`, `mergetag` or `gpgsig`, kept in order
+    headers: Headers,
     pub message: String,
 }
 
 impl Commit {
-    fn serialize_field(field_name: &str, obj: &str) -> String {
-        format!("{} {}\n", field_name, obj)
+    pub fn author(&self) -> &Signature {
+        &self.author
+    }
+
+    pub fn committer(&self) -> &Signature {
+        &self.committer
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
     }
 
-    fn serialize_optional_field(field_name: &str, obj: &Option<String>) -> String {
-        obj.as_ref()
-            .map(|sig| format!("{} {}\n", field_name, sig))
-            .unwrap_or("".into())
+    fn serialize_repeated_field<T: Display>(field_name: &str, objs: &[T]) -> String {
+        objs.iter()
+            .map(|obj| header::serialize_field(field_name, &obj.to_string()))
+            .collect()
     }
 
     pub fn serialize(&self) -> Vec<u8> {
         // TODO[Rhys] find a way to get rid of the unnecessary cloning here
-        let fields = vec![
-            Self::serialize_field("tree", &self.tree),
-            Self::serialize_optional_field("parent", &self.parent),
-            Self::serialize_field("author", &self.author),
-            Self::serialize_field("committer", &self.committer),
-            Self::serialize_optional_field("gpgsig", &self.gpgsig),
+        let fields = [
+            header::serialize_field("tree", &self.tree.to_string()),
+            Self::serialize_repeated_field("parent", &self.parents),
+            header::serialize_field("author", &self.author.serialize()),
+            header::serialize_field("committer", &self.committer.serialize()),
+            self.headers
+                .iter()
+                .map(|(key, value)| header::serialize_field(key, value))
+                .collect(),
             "\n".to_string(),
             self.message.clone(),
         ];
         fields.iter().flat_map(|f| f.as_bytes()).cloned().collect()
     }
 
-    pub fn deserialize(bytes: Vec<u8>) -> Self {
-        let content = String::from_utf8(bytes).unwrap();
-        // TODO[Rhys] this could use some much cleverer parsing
-        let regex = RegexBuilder::new(
-            r"(?x)
-            tree\ (?P<tree>[a-zA-Z0-9]*)\n
-            (parent\ (?P<parent>[a-zA-Z0-9]*)\n)?
-            author\ (?P<author>.*)\n
-            committer\ (?P<committer>.*)\n
-            (gpgsig\ (?P<gpgsig>
-                -----BEGIN\ PGP\ SIGNATURE-----[\w\d\s+/=]*-----END\ PGP\ SIGNATURE-----
-            )\n)?
-            \n
-            (?P<message>.*)
-        ",
-        )
-        .multi_line(true)
-        .build()
-        .unwrap();
-        let captures = regex.captures(&*content).unwrap();
-
-        Self {
-            tree: captures.name("tree").unwrap().as_str().into(),
-            parent: captures.name("parent").map(|cap| cap.as_str().into()),
-            author: captures.name("author").unwrap().as_str().into(),
-            committer: captures.name("committer").unwrap().as_str().into(),
-            gpgsig: captures.name("gpgsig").map(|cap| cap.as_str().into()),
-            message: captures.name("message").unwrap().as_str().into(),
+    fn parse_id(hex: &str) -> Result<ObjectId> {
+        ObjectId::from_hex(hex)
+            .map_err(|_| Error::Corrupt(format!("Commit referenced invalid object id {}.", hex)))
+    }
+
+    pub fn deserialize(bytes: Vec<u8>) -> Result<Self> {
+        let (headers, message) = header::parse(&bytes, "Commit")?;
+        let message = std::str::from_utf8(message)
+            .map_err(|_| Error::Corrupt("Commit was not valid utf-8.".to_string()))?;
+
+        let mut fields = headers.into_iter().peekable();
+        let tree = Self::parse_id(&header::expect_field(&mut fields, "tree", "Commit")?)?;
+        let mut parents = vec![];
+        while let Some((_, parent)) = fields.next_if(|(key, _)| key == "parent") {
+            parents.push(Self::parse_id(&parent)?);
         }
+        let author =
+            Signature::deserialize(&header::expect_field(&mut fields, "author", "Commit")?)?;
+        let committer =
+            Signature::deserialize(&header::expect_field(&mut fields, "committer", "Commit")?)?;
+
+        Ok(Self {
+            tree,
+            parents,
+            author,
+            committer,
+            headers: fields.collect(),
+            message: message.to_string(),
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::error::Error;
     use crate::object::commit::Commit;
+    use crate::object::id::ObjectId;
+    use crate::object::signature::Signature;
 
     #[test]
-    fn serializes_fields() {
-        assert_eq!(Commit::serialize_field(&"field", "value"), "field value\n");
-    }
-
-    #[test]
-    fn serializes_optional_field_when_defined() {
+    fn serializes_repeated_fields_in_order() {
         assert_eq!(
-            Commit::serialize_optional_field(&"field", &Some("value".to_string())),
-            "field value\n"
+            Commit::serialize_repeated_field("field", &["a".to_string(), "b".to_string()]),
+            "field a\nfield b\n"
         );
     }
 
     #[test]
-    fn doesnt_serialize_optional_field_when_not_defined() {
-        assert_eq!(Commit::serialize_optional_field(&"field", &None), "");
+    fn doesnt_serialize_repeated_field_when_empty() {
+        assert_eq!(
+            Commit::serialize_repeated_field::<ObjectId>("field", &[]),
+            ""
+        );
     }
 
     #[test]
@@ -100,75 +120,165 @@ mod tests {
             Initial revision of \"git\", the information manager from hell\
             ";
         let deserialized = Commit {
-            tree: "2b5bfdf7798569e0b59b16eb9602d5fa572d6038".to_string(),
-            parent: None,
-            author: "Linus Torvalds <torvalds@ppc970.osdl.org> 1112911993 -0700".to_string(),
-            committer: "Linus Torvalds <torvalds@ppc970.osdl.org> 1112911993 -0700".to_string(),
-            gpgsig: None,
+            tree: "2b5bfdf7798569e0b59b16eb9602d5fa572d6038".parse().unwrap(),
+            parents: vec![],
+            author: Signature::deserialize(
+                "Linus Torvalds <torvalds@ppc970.osdl.org> 1112911993 -0700",
+            )
+            .unwrap(),
+            committer: Signature::deserialize(
+                "Linus Torvalds <torvalds@ppc970.osdl.org> 1112911993 -0700",
+            )
+            .unwrap(),
+            headers: vec![],
             message: "Initial revision of \"git\", the information manager from hell".to_string(),
         };
-        let commit = Commit::deserialize(Vec::from(serialized));
+        let commit = Commit::deserialize(Vec::from(serialized)).unwrap();
         assert_eq!(commit, deserialized);
         assert_eq!(String::from_utf8(commit.serialize()).unwrap(), serialized)
     }
 
     #[test]
     fn round_trips_commits_with_all_fields() {
-        let serialized = "\
+        let signature = [
+            "-----BEGIN PGP SIGNATURE-----",
+            "",
+            "iQIzBAABCAAdFiEEdnvMMujyElTR0B8vmoIqIpWBpgYFAl80frQACgkQmoIqIpWB",
+            "pgaghQ/+NLPMK0UjuZM0Spp2W5t7yqAczySyYQJOG1gAnkpgiPKeTmXrBFEKBWO4",
+            "JaAZlOp5Ds9fRjro/rYG6eTwBXnE09UKYZY6kBTWmIi8JInQnA/9eJdcnxR0z2aK",
+            "pkdptU41BZZyLoYnDfN0hOJD4V2mGqRxWY1HXlpz3KIPBlhfYbaMss9z+5c2U4gf",
+            "HLPbB2wsAt5uIWfXufdpsJm94wniQBprl6MtWjdnjRwbLQXLh61vXaKgghAwJZO+",
+            "LqIAF5tUzrAqXTxRCZ7dL0gcuuV76FFEubrJwb+sGHWqtlLB4f9XWAnuVRql0EAJ",
+            "1n3OgGP3cnNeznGdZZJcEoZtJAsUnZ18yO9CeTcZ+EavtiaomPWOlCmzoBRV3HZu",
+            "B2nYKAWhNbu645iaZ7x73xBMxR3AmGmOOrE5TT41Kjfhw3JQ7risr5YfjFj88h3r",
+            "tCHtHig8f8foNR5ClQJoryjqDQCR4DluJQbeOU4PXRsJwuJZ8FdyHvc6wIdcrneQ",
+            "6P5L2ktGH740m22/bCf0M3zXl3A79Jz5FxmN0Oh+VUpMh5r4Q56csc8tg/0PSPKO",
+            "K1Eb+gTBqNlHvQSNizQR0rP5MLSp+o0YE364uvYF4imGCLOSQCTb8hNbIy31t1ah",
+            "IOTSJoM985ubIYxonwcFDUfJ3jZGZxqulu3fSbeYa31ZRVwsCbM=",
+            "=By2v",
+            "-----END PGP SIGNATURE-----",
+        ];
+        let serialized = format!(
+            "\
             tree c171921c5c0f2e02f7243c13d331e96f149fd653\n\
             parent 4478b9c55808657544198529c58e29888d31e677\n\
             author rhysormond <email> 1597275816 -0700\n\
             committer rhysormond <email> 1597275816 -0700\n\
-            gpgsig -----BEGIN PGP SIGNATURE-----\n\
-            \n\
-             iQIzBAABCAAdFiEEdnvMMujyElTR0B8vmoIqIpWBpgYFAl80frQACgkQmoIqIpWB\n\
-             pgaghQ/+NLPMK0UjuZM0Spp2W5t7yqAczySyYQJOG1gAnkpgiPKeTmXrBFEKBWO4\n\
-             JaAZlOp5Ds9fRjro/rYG6eTwBXnE09UKYZY6kBTWmIi8JInQnA/9eJdcnxR0z2aK\n\
-             pkdptU41BZZyLoYnDfN0hOJD4V2mGqRxWY1HXlpz3KIPBlhfYbaMss9z+5c2U4gf\n\
-             HLPbB2wsAt5uIWfXufdpsJm94wniQBprl6MtWjdnjRwbLQXLh61vXaKgghAwJZO+\n\
-             LqIAF5tUzrAqXTxRCZ7dL0gcuuV76FFEubrJwb+sGHWqtlLB4f9XWAnuVRql0EAJ\n\
-             1n3OgGP3cnNeznGdZZJcEoZtJAsUnZ18yO9CeTcZ+EavtiaomPWOlCmzoBRV3HZu\n\
-             B2nYKAWhNbu645iaZ7x73xBMxR3AmGmOOrE5TT41Kjfhw3JQ7risr5YfjFj88h3r\n\
-             tCHtHig8f8foNR5ClQJoryjqDQCR4DluJQbeOU4PXRsJwuJZ8FdyHvc6wIdcrneQ\n\
-             6P5L2ktGH740m22/bCf0M3zXl3A79Jz5FxmN0Oh+VUpMh5r4Q56csc8tg/0PSPKO\n\
-             K1Eb+gTBqNlHvQSNizQR0rP5MLSp+o0YE364uvYF4imGCLOSQCTb8hNbIy31t1ah\n\
-             IOTSJoM985ubIYxonwcFDUfJ3jZGZxqulu3fSbeYa31ZRVwsCbM=\n\
-             =By2v\n\
-             -----END PGP SIGNATURE-----\n\
+            gpgsig {}\n\
             \n\
             refactor: clean up init and add todos\
-        ";
+            ",
+            signature.join("\n ")
+        );
 
         let deserialized = Commit {
-            tree: "c171921c5c0f2e02f7243c13d331e96f149fd653".to_string(),
-            parent: Some("4478b9c55808657544198529c58e29888d31e677".to_string()),
-            author: "rhysormond <email> 1597275816 -0700".to_string(),
-            committer: "rhysormond <email> 1597275816 -0700".to_string(),
-            gpgsig: Some(
-                "\
-                -----BEGIN PGP SIGNATURE-----\n\
-                \n\
-                 iQIzBAABCAAdFiEEdnvMMujyElTR0B8vmoIqIpWBpgYFAl80frQACgkQmoIqIpWB\n\
-                 pgaghQ/+NLPMK0UjuZM0Spp2W5t7yqAczySyYQJOG1gAnkpgiPKeTmXrBFEKBWO4\n\
-                 JaAZlOp5Ds9fRjro/rYG6eTwBXnE09UKYZY6kBTWmIi8JInQnA/9eJdcnxR0z2aK\n\
-                 pkdptU41BZZyLoYnDfN0hOJD4V2mGqRxWY1HXlpz3KIPBlhfYbaMss9z+5c2U4gf\n\
-                 HLPbB2wsAt5uIWfXufdpsJm94wniQBprl6MtWjdnjRwbLQXLh61vXaKgghAwJZO+\n\
-                 LqIAF5tUzrAqXTxRCZ7dL0gcuuV76FFEubrJwb+sGHWqtlLB4f9XWAnuVRql0EAJ\n\
-                 1n3OgGP3cnNeznGdZZJcEoZtJAsUnZ18yO9CeTcZ+EavtiaomPWOlCmzoBRV3HZu\n\
-                 B2nYKAWhNbu645iaZ7x73xBMxR3AmGmOOrE5TT41Kjfhw3JQ7risr5YfjFj88h3r\n\
-                 tCHtHig8f8foNR5ClQJoryjqDQCR4DluJQbeOU4PXRsJwuJZ8FdyHvc6wIdcrneQ\n\
-                 6P5L2ktGH740m22/bCf0M3zXl3A79Jz5FxmN0Oh+VUpMh5r4Q56csc8tg/0PSPKO\n\
-                 K1Eb+gTBqNlHvQSNizQR0rP5MLSp+o0YE364uvYF4imGCLOSQCTb8hNbIy31t1ah\n\
-                 IOTSJoM985ubIYxonwcFDUfJ3jZGZxqulu3fSbeYa31ZRVwsCbM=\n\
-                 =By2v\n\
-                 -----END PGP SIGNATURE-----\
-                "
-                .to_string(),
-            ),
+            tree: "c171921c5c0f2e02f7243c13d331e96f149fd653".parse().unwrap(),
+            parents: vec!["4478b9c55808657544198529c58e29888d31e677".parse().unwrap()],
+            author: Signature::deserialize("rhysormond <email> 1597275816 -0700").unwrap(),
+            committer: Signature::deserialize("rhysormond <email> 1597275816 -0700").unwrap(),
+            headers: vec![("gpgsig".to_string(), signature.join("\n"))],
             message: "refactor: clean up init and add todos".to_string(),
         };
-        let commit = Commit::deserialize(Vec::from(serialized));
+        let commit = Commit::deserialize(Vec::from(serialized.as_str())).unwrap();
         assert_eq!(commit, deserialized);
+        assert_eq!(commit.header("gpgsig"), Some(signature.join("\n").as_str()));
         assert_eq!(String::from_utf8(commit.serialize()).unwrap(), serialized)
     }
+
+    #[test]
+    fn round_trips_commits_with_extra_headers_and_long_messages() {
+        let serialized = "\
+            tree 5b13ed4ba6db8bdd2af5e3b2ddc94cf5a4d0a7e7\n\
+            parent 0a8dfc7fa3d2fc2e4c1c8d1e1a0ac56dd4e4c5c4\n\
+            parent 7c5d2b5e06ea4f7fca1b36da5e9c1e0b2c1f6cfa\n\
+            author rhysormond <email> 1597275816 -0700\n\
+            committer rhysormond <email> 1597275816 -0700\n\
+            encoding ISO-8859-1\n\
+            mergetag object 7c5d2b5e06ea4f7fca1b36da5e9c1e0b2c1f6cfa\n \
+            type commit\n \
+            tag v1.0\n \
+            tagger rhysormond <email> 1597275816 -0700\n \
+            \n \
+            First release\n\
+            gpgsig -----BEGIN SSH SIGNATURE-----\n \
+            U1NIU0lHAAAAAQAAADMAAAALc3NoLWVkMjU1MTkAAAAg\n \
+            -----END SSH SIGNATURE-----\n\
+            \n\
+            Merge tag 'v1.0'\n\
+            \n\
+            With a body that spans\n\
+            several lines.\n\
+        ";
+        let commit = Commit::deserialize(Vec::from(serialized)).unwrap();
+        assert_eq!(commit.parents.len(), 2);
+        assert_eq!(commit.header("encoding"), Some("ISO-8859-1"));
+        assert_eq!(
+            commit.header("mergetag"),
+            Some(
+                "object 7c5d2b5e06ea4f7fca1b36da5e9c1e0b2c1f6cfa\ntype commit\ntag v1.0\n\
+                 tagger rhysormond <email> 1597275816 -0700\n\nFirst release"
+            )
+        );
+        assert_eq!(
+            commit.message,
+            "Merge tag 'v1.0'\n\nWith a body that spans\nseveral lines.\n"
+        );
+        assert_eq!(String::from_utf8(commit.serialize()).unwrap(), serialized)
+    }
+
+    #[test]
+    fn round_trips_octopus_merges() {
+        let serialized = "\
+            tree 5b13ed4ba6db8bdd2af5e3b2ddc94cf5a4d0a7e7\n\
+            parent 0a8dfc7fa3d2fc2e4c1c8d1e1a0ac56dd4e4c5c4\n\
+            parent 7c5d2b5e06ea4f7fca1b36da5e9c1e0b2c1f6cfa\n\
+            parent e1f5bb9be4f4e3fe8f4fe1a4e0e1a26a3f4fc2a3\n\
+            author rhysormond <email> 1597275816 -0700\n\
+            committer rhysormond <email> 1597275816 -0700\n\
+            \n\
+            Merge branches 'a' and 'b'\
+        ";
+        let deserialized = Commit {
+            tree: "5b13ed4ba6db8bdd2af5e3b2ddc94cf5a4d0a7e7".parse().unwrap(),
+            parents: vec![
+                "0a8dfc7fa3d2fc2e4c1c8d1e1a0ac56dd4e4c5c4".parse().unwrap(),
+                "7c5d2b5e06ea4f7fca1b36da5e9c1e0b2c1f6cfa".parse().unwrap(),
+                "e1f5bb9be4f4e3fe8f4fe1a4e0e1a26a3f4fc2a3".parse().unwrap(),
+            ],
+            author: Signature::deserialize("rhysormond <email> 1597275816 -0700").unwrap(),
+            committer: Signature::deserialize("rhysormond <email> 1597275816 -0700").unwrap(),
+            headers: vec![],
+            message: "Merge branches 'a' and 'b'".to_string(),
+        };
+        let commit = Commit::deserialize(Vec::from(serialized)).unwrap();
+        assert_eq!(commit, deserialized);
+        assert_eq!(String::from_utf8(commit.serialize()).unwrap(), serialized)
+    }
+
+    #[test]
+    fn fails_when_parent_is_malformed() {
+        let serialized = "\
+            tree 2b5bfdf7798569e0b59b16eb9602d5fa572d6038\n\
+            parent 2b5bfdf\n\
+            author rhysormond <email> 1597275816 -0700\n\
+            committer rhysormond <email> 1597275816 -0700\n\
+            \n\
+            Abbreviated parent\
+        ";
+        let result = Commit::deserialize(Vec::from(serialized));
+        assert!(matches!(result, Err(Error::Corrupt(_))));
+    }
+
+    #[test]
+    fn fails_when_headers_are_out_of_order() {
+        let serialized = "\
+            tree 2b5bfdf7798569e0b59b16eb9602d5fa572d6038\n\
+            committer rhysormond <email> 1597275816 -0700\n\
+            author rhysormond <email> 1597275816 -0700\n\
+            \n\
+            Swapped signatures\
+        ";
+        let result = Commit::deserialize(Vec::from(serialized));
+        assert!(matches!(result, Err(Error::Corrupt(_))));
+    }
 }
@@ -0,0 +1,84 @@
+use crate::error::{Error, Result};
+
+/// The name and value of each header line, in order.
+pub(crate) type Headers = Vec<(String, String)>;
+
+/// Writes one header line of a commit or tag, e.g. `tree <id>`.
+pub(crate) fn serialize_field(name: &str, value: &str) -> String {
+    // values that span lines continue on lines starting with a space
+    format!("{} {}\n", name, value.replace('\n', "\n "))
+}
+
+/// Splits a commit or tag into its headers, in order, and the message after the blank line that
+/// ends them, which is left as bytes since it needn't be utf-8.
+pub(crate) fn parse<'a>(content: &'a [u8], kind: &str) -> Result<(Headers, &'a [u8])> {
+    let end = content
+        .windows(2)
+        .position(|pair| pair == b"\n\n")
+        .ok_or_else(|| Error::Corrupt(format!("{} has no message.", kind)))?;
+    let header = std::str::from_utf8(&content[..end])
+        .map_err(|_| Error::Corrupt(format!("{} headers were not valid utf-8.", kind)))?;
+
+    let mut headers: Headers = vec![];
+    for line in header.lines() {
+        match (line.strip_prefix(' '), headers.last_mut()) {
+            (Some(continued), Some((_, value))) => {
+                value.push('\n');
+                value.push_str(continued);
+            }
+            _ => {
+                let (key, value) = line.split_once(' ').ok_or_else(|| {
+                    Error::Corrupt(format!("{} header {:?} couldn't be parsed.", kind, line))
+                })?;
+                headers.push((key.to_string(), value.to_string()));
+            }
+        }
+    }
+    Ok((headers, &content[end + 2..]))
+}
+
+/// Takes the next header, which has to have the given name.
+pub(crate) fn expect_field(
+    fields: &mut impl Iterator<Item = (String, String)>,
+    name: &str,
+    kind: &str,
+) -> Result<String> {
+    match fields.next() {
+        Some((key, value)) if key == name => Ok(value),
+        _ => Err(Error::Corrupt(format!("{} is missing its {}.", kind, name))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::object::header::{parse, serialize_field};
+
+    #[test]
+    fn serializes_fields() {
+        assert_eq!(serialize_field("field", "value"), "field value\n");
+    }
+
+    #[test]
+    fn serializes_fields_over_several_lines() {
+        assert_eq!(serialize_field("field", "a\n\nb"), "field a\n \n b\n");
+    }
+
+    #[test]
+    fn parses_continued_fields() {
+        let (headers, message) = parse(b"a 1\nb x\n \n y\n\nmessage", "Commit").unwrap();
+        assert_eq!(
+            headers,
+            vec![
+                ("a".to_string(), "1".to_string()),
+                ("b".to_string(), "x\n\ny".to_string())
+            ]
+        );
+        assert_eq!(message, b"message");
+    }
+
+    #[test]
+    fn fails_without_a_message() {
+        assert!(matches!(parse(b"a 1\n", "Tag"), Err(Error::Corrupt(_))));
+    }
+}
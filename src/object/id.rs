@@ -0,0 +1,250 @@
+use std::convert::TryInto;
+use std::fmt;
+use std::str::FromStr;
+
+use crypto::digest::Digest;
+use crypto::sha1::Sha1;
+use crypto::sha2::Sha256;
+
+use crate::error::{Error, Result};
+
+const SHA1_SIZE: usize = 20;
+const SHA256_SIZE: usize = 32;
+
+/// The hash algorithm a repository names its objects with, as set by `extensions.objectFormat`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectFormat {
+    Sha1,
+    Sha256,
+}
+
+impl ObjectFormat {
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name.to_lowercase().as_str() {
+            "sha1" => Ok(ObjectFormat::Sha1),
+            "sha256" => Ok(ObjectFormat::Sha256),
+            other => Err(Error::InvalidArgument(format!(
+                "Object format {} is not valid.",
+                other
+            ))),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ObjectFormat::Sha1 => "sha1",
+            ObjectFormat::Sha256 => "sha256",
+        }
+    }
+
+    /// The number of bytes in an id of this format.
+    pub fn size(&self) -> usize {
+        match self {
+            ObjectFormat::Sha1 => SHA1_SIZE,
+            ObjectFormat::Sha256 => SHA256_SIZE,
+        }
+    }
+
+    pub fn hex_size(&self) -> usize {
+        self.size() * 2
+    }
+
+    /// Creates a hasher for content that's too large to hash in one go.
+    pub fn hasher(&self) -> Box<dyn Digest> {
+        match self {
+            ObjectFormat::Sha1 => Box::new(Sha1::new()),
+            ObjectFormat::Sha256 => Box::new(Sha256::new()),
+        }
+    }
+
+    pub fn digest(&self, bytes: &[u8]) -> Vec<u8> {
+        let mut hasher = self.hasher();
+        hasher.input(bytes);
+        let mut hash = vec![0; self.size()];
+        hasher.result(&mut hash);
+        hash
+    }
+
+    pub fn hash(&self, bytes: &[u8]) -> ObjectId {
+        // the digest is always the right size for its format so this can't fail
+        ObjectId::from_bytes(&self.digest(bytes)).unwrap()
+    }
+}
+
+/// The binary form of an object hash.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ObjectId {
+    Sha1([u8; SHA1_SIZE]),
+    Sha256([u8; SHA256_SIZE]),
+}
+
+impl ObjectId {
+    /// Reads an id from raw bytes, taking its format from the number of bytes given.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        match bytes.len() {
+            SHA1_SIZE => Ok(ObjectId::Sha1(bytes.try_into().unwrap())),
+            SHA256_SIZE => Ok(ObjectId::Sha256(bytes.try_into().unwrap())),
+            other => Err(Error::InvalidArgument(format!(
+                "{} bytes is not a valid object id.",
+                other
+            ))),
+        }
+    }
+
+    /// Parses a full length hex id, rejecting upper case so every object has exactly one spelling.
+    pub fn from_hex(hex: &str) -> Result<Self> {
+        let invalid = || Error::InvalidArgument(format!("{} is not a valid object id.", hex));
+        let is_lower_hex = |c: char| c.is_ascii_digit() || ('a'..='f').contains(&c);
+        if !hex.chars().all(is_lower_hex) {
+            return Err(invalid());
+        }
+        let bytes = hex::decode(hex).map_err(|_| invalid())?;
+        ObjectId::from_bytes(&bytes).map_err(|_| invalid())
+    }
+
+    /// The all zero id that stands in for a reference that doesn't exist, e.g. in reflogs.
+    pub fn null(format: ObjectFormat) -> Self {
+        match format {
+            ObjectFormat::Sha1 => ObjectId::Sha1([0; SHA1_SIZE]),
+            ObjectFormat::Sha256 => ObjectId::Sha256([0; SHA256_SIZE]),
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.as_bytes().iter().all(|b| *b == 0)
+    }
+
+    pub fn format(&self) -> ObjectFormat {
+        match self {
+            ObjectId::Sha1(_) => ObjectFormat::Sha1,
+            ObjectId::Sha256(_) => ObjectFormat::Sha256,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            ObjectId::Sha1(bytes) => bytes,
+            ObjectId::Sha256(bytes) => bytes,
+        }
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.as_bytes())
+    }
+}
+
+impl FromStr for ObjectId {
+    type Err = Error;
+
+    fn from_str(hex: &str) -> Result<Self> {
+        ObjectId::from_hex(hex)
+    }
+}
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+impl fmt::Debug for ObjectId {
+    // ids are far easier to read as hex than as a list of bytes
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ObjectId({})", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::object::id::{ObjectFormat, ObjectId};
+
+    #[test]
+    fn round_trips_hex() {
+        let hex = "00dbfaedec47a5a923e496466c3fdf4cc875f74a";
+        let id = ObjectId::from_hex(hex).unwrap();
+        assert_eq!(
+            id.as_bytes(),
+            &[
+                0, 219, 250, 237, 236, 71, 165, 169, 35, 228, 150, 70, 108, 63, 223, 76, 200, 117,
+                247, 74
+            ]
+        );
+        assert_eq!(id.to_string(), hex)
+    }
+
+    #[test]
+    fn round_trips_bytes() {
+        let bytes = [0xab; 20];
+        let id = ObjectId::from_bytes(&bytes).unwrap();
+        assert_eq!(id.as_bytes(), &bytes);
+        assert_eq!(id.to_hex(), "ab".repeat(20))
+    }
+
+    #[test]
+    fn round_trips_sha256_hex() {
+        let hex = "ab".repeat(32);
+        let id = ObjectId::from_hex(&hex).unwrap();
+        assert_eq!(id.format(), ObjectFormat::Sha256);
+        assert_eq!(id.as_bytes(), &[0xab; 32]);
+        assert_eq!(id.to_string(), hex)
+    }
+
+    #[test]
+    fn orders_by_bytes() {
+        let low: ObjectId = "01".repeat(20).parse().unwrap();
+        let high: ObjectId = "10".repeat(20).parse().unwrap();
+        assert!(low < high);
+    }
+
+    #[test]
+    fn hashes_with_sha1() {
+        let hash = ObjectFormat::Sha1.hash(b"test");
+        assert_eq!(hash.to_string(), "a94a8fe5ccb19ba61c4c0873d391e987982fbbd3")
+    }
+
+    #[test]
+    fn hashes_with_sha256() {
+        let hash = ObjectFormat::Sha256.hash(b"test");
+        assert_eq!(
+            hash.to_string(),
+            "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+        )
+    }
+
+    #[test]
+    fn parses_format_names() {
+        assert_eq!(ObjectFormat::from_name("sha1").unwrap(), ObjectFormat::Sha1);
+        assert_eq!(
+            ObjectFormat::from_name("SHA256").unwrap(),
+            ObjectFormat::Sha256
+        );
+        assert!(matches!(
+            ObjectFormat::from_name("md5"),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn fails_to_parse_malformed_hex() {
+        for hex in &[
+            "00dbfaedec47a5a923e496466c3fdf4cc875f74",
+            "00dbfaedec47a5a923e496466c3fdf4cc875f74a0",
+            "00DBFAEDEC47A5A923E496466C3FDF4CC875F74A",
+            "00dbfaedec47a5a923e496466c3fdf4cc875f74g",
+        ] {
+            assert!(matches!(
+                ObjectId::from_hex(hex),
+                Err(Error::InvalidArgument(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn fails_to_parse_wrong_number_of_bytes() {
+        assert!(matches!(
+            ObjectId::from_bytes(&[0; 19]),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+}
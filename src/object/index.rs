@@ -0,0 +1,499 @@
+use std::convert::TryInto;
+
+use crate::error::{Error, Result};
+use crate::object::constant::ASCII_NULL;
+use crate::object::id::{ObjectFormat, ObjectId};
+use crate::object::tree::Mode;
+
+const SIGNATURE: &[u8; 4] = b"DIRC";
+const HEADER_SIZE: usize = 12;
+const MIN_VERSION: u32 = 2;
+const MAX_VERSION: u32 = 4;
+// ten 32-bit fields: ctime, mtime, dev, ino, mode, uid, gid and size
+const STAT_SIZE: usize = 40;
+const ASSUME_VALID_FLAG: u16 = 0x8000;
+const EXTENDED_FLAG: u16 = 0x4000;
+const STAGE_SHIFT: u16 = 12;
+const STAGE_MASK: u16 = 0x3000;
+// longer paths are still stored in full, the length just saturates
+const NAME_MASK: u16 = 0x0fff;
+const SKIP_WORKTREE_FLAG: u16 = 0x4000;
+const INTENT_TO_ADD_FLAG: u16 = 0x2000;
+
+/// The staging area kept in `.git/index`, listing every path in the next commit along with the
+/// stat data used to tell whether the file in the work tree has changed since.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Index {
+    /// Which version of the format to write, from 2 to 4, where 4 compresses paths.
+    pub version: u32,
+    pub entries: Vec<Entry>,
+    /// Extensions like the cached tree, kept as they were read so they survive being written.
+    pub extensions: Vec<Extension>,
+}
+
+/// What was last seen of a file in the work tree, truncated to 32 bits like git does.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Stat {
+    pub ctime: (u32, u32),
+    pub mtime: (u32, u32),
+    pub dev: u32,
+    pub ino: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u32,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Entry {
+    pub stat: Stat,
+    pub mode: Mode,
+    pub hash: ObjectId,
+    /// 0 normally, or 1 to 3 for the base, ours and theirs sides of a conflicted merge.
+    pub stage: u8,
+    pub assume_valid: bool,
+    pub skip_worktree: bool,
+    pub intent_to_add: bool,
+    pub path: String,
+}
+
+/// An optional section after the entries, identified by its signature, e.g. `TREE`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Extension {
+    pub signature: [u8; 4],
+    pub data: Vec<u8>,
+}
+
+impl Default for Index {
+    fn default() -> Self {
+        Self {
+            version: MIN_VERSION,
+            entries: vec![],
+            extensions: vec![],
+        }
+    }
+}
+
+impl Index {
+    pub fn deserialize(bytes: &[u8], format: ObjectFormat) -> Result<Self> {
+        if bytes.len() < HEADER_SIZE + format.size() || &bytes[..4] != SIGNATURE {
+            return Err(Error::Corrupt("Index header is invalid.".to_string()));
+        }
+        let (content, checksum) = bytes.split_at(bytes.len() - format.size());
+        // git leaves the checksum zeroed when `index.skipHash` is set
+        if checksum.iter().any(|b| *b != 0) && format.digest(content) != checksum {
+            return Err(Error::Corrupt(
+                "Index checksum doesn't match its content.".to_string(),
+            ));
+        }
+        let version = read_u32(content, 4)?;
+        if !(MIN_VERSION..=MAX_VERSION).contains(&version) {
+            return Err(Error::Corrupt(format!(
+                "Index version {} is not supported.",
+                version
+            )));
+        }
+
+        let count = read_u32(content, 8)?;
+        let mut at = HEADER_SIZE;
+        let mut entries = vec![];
+        for _ in 0..count {
+            let previous = entries.last().map_or("", |e: &Entry| e.path.as_str());
+            let (entry, next) = Entry::deserialize(content, at, version, previous, format)?;
+            entries.push(entry);
+            at = next;
+        }
+
+        let mut extensions = vec![];
+        while at < content.len() {
+            let signature = content
+                .get(at..at + 4)
+                .ok_or_else(truncated)?
+                .try_into()
+                .unwrap();
+            let size = read_u32(content, at + 4)? as usize;
+            let data = content
+                .get(at + 8..at + 8 + size)
+                .ok_or_else(truncated)?
+                .to_vec();
+            extensions.push(Extension { signature, data });
+            at += 8 + size;
+        }
+
+        Ok(Self {
+            version,
+            entries,
+            extensions,
+        })
+    }
+
+    /// Serializes the index with its entries in git's order, by path and then by stage.
+    pub fn serialize(&self, format: ObjectFormat) -> Vec<u8> {
+        let mut entries: Vec<&Entry> = self.entries.iter().collect();
+        entries.sort_by(|a, b| (a.path.as_bytes(), a.stage).cmp(&(b.path.as_bytes(), b.stage)));
+        // like git, version 2 is bumped to 3 when an entry needs the extended flags
+        let version = match self.version {
+            2 if entries.iter().any(|e| e.is_extended()) => 3,
+            version => version,
+        };
+
+        let mut bytes = SIGNATURE.to_vec();
+        bytes.extend_from_slice(&version.to_be_bytes());
+        bytes.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+        let mut previous = "";
+        for entry in entries {
+            entry.serialize(&mut bytes, version, previous);
+            previous = &entry.path;
+        }
+        for extension in &self.extensions {
+            bytes.extend_from_slice(&extension.signature);
+            bytes.extend_from_slice(&(extension.data.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(&extension.data);
+        }
+        let checksum = format.digest(&bytes);
+        bytes.extend(checksum);
+        bytes
+    }
+}
+
+impl Entry {
+    fn is_extended(&self) -> bool {
+        self.skip_worktree || self.intent_to_add
+    }
+
+    fn deserialize(
+        bytes: &[u8],
+        at: usize,
+        version: u32,
+        previous: &str,
+        format: ObjectFormat,
+    ) -> Result<(Self, usize)> {
+        let field = |i: usize| read_u32(bytes, at + i * 4);
+        let stat = Stat {
+            ctime: (field(0)?, field(1)?),
+            mtime: (field(2)?, field(3)?),
+            dev: field(4)?,
+            ino: field(5)?,
+            uid: field(7)?,
+            gid: field(8)?,
+            size: field(9)?,
+        };
+        let mode = field(6)?;
+        let mode = Mode::deserialize(&format!("{:o}", mode))
+            .filter(|mode| *mode != Mode::Directory)
+            .ok_or_else(|| Error::Corrupt(format!("Index entry mode {:o} is invalid.", mode)))?;
+
+        let mut cursor = at + STAT_SIZE;
+        let hash = bytes
+            .get(cursor..cursor + format.size())
+            .ok_or_else(truncated)
+            .and_then(ObjectId::from_bytes)?;
+        cursor += format.size();
+        let flags = read_u16(bytes, cursor)?;
+        cursor += 2;
+        let extended = match flags & EXTENDED_FLAG {
+            0 => 0,
+            _ if version < 3 => {
+                return Err(Error::Corrupt(format!(
+                    "Index version {} can't have extended flags.",
+                    version
+                )))
+            }
+            _ => {
+                cursor += 2;
+                read_u16(bytes, cursor - 2)?
+            }
+        };
+
+        let (path, next) = if version >= 4 {
+            let (strip, length) = read_varint(bytes, cursor)?;
+            cursor += length;
+            let keep = previous.len().checked_sub(strip).ok_or_else(|| {
+                Error::Corrupt("Index path strips more than the previous path.".to_string())
+            })?;
+            let (suffix, end) = read_path(bytes, cursor)?;
+            let path = [&previous.as_bytes()[..keep], suffix].concat();
+            (path, end + 1)
+        } else {
+            let (path, end) = read_path(bytes, cursor)?;
+            // entries are padded with NULs to a multiple of 8 bytes, with at least one
+            let length = (end - at + 8) & !7;
+            (path.to_vec(), at + length)
+        };
+        let path = String::from_utf8(path)
+            .map_err(|_| Error::Corrupt("Index path is not valid UTF-8.".to_string()))?;
+
+        let entry = Self {
+            stat,
+            mode,
+            hash,
+            stage: ((flags & STAGE_MASK) >> STAGE_SHIFT) as u8,
+            assume_valid: flags & ASSUME_VALID_FLAG != 0,
+            skip_worktree: extended & SKIP_WORKTREE_FLAG != 0,
+            intent_to_add: extended & INTENT_TO_ADD_FLAG != 0,
+            path,
+        };
+        Ok((entry, next))
+    }
+
+    fn serialize(&self, bytes: &mut Vec<u8>, version: u32, previous: &str) {
+        let start = bytes.len();
+        let mode = u32::from_str_radix(self.mode.serialize(), 8).unwrap();
+        for field in &[
+            self.stat.ctime.0,
+            self.stat.ctime.1,
+            self.stat.mtime.0,
+            self.stat.mtime.1,
+            self.stat.dev,
+            self.stat.ino,
+            mode,
+            self.stat.uid,
+            self.stat.gid,
+            self.stat.size,
+        ] {
+            bytes.extend_from_slice(&field.to_be_bytes());
+        }
+        bytes.extend_from_slice(self.hash.as_bytes());
+
+        let mut flags = (self.path.len().min(NAME_MASK as usize) as u16)
+            | (((self.stage as u16) << STAGE_SHIFT) & STAGE_MASK);
+        if self.assume_valid {
+            flags |= ASSUME_VALID_FLAG;
+        }
+        if self.is_extended() {
+            flags |= EXTENDED_FLAG;
+        }
+        bytes.extend_from_slice(&flags.to_be_bytes());
+        if self.is_extended() {
+            let mut extended = 0;
+            if self.skip_worktree {
+                extended |= SKIP_WORKTREE_FLAG;
+            }
+            if self.intent_to_add {
+                extended |= INTENT_TO_ADD_FLAG;
+            }
+            bytes.extend_from_slice(&u16::to_be_bytes(extended));
+        }
+
+        if version >= 4 {
+            let common = previous
+                .bytes()
+                .zip(self.path.bytes())
+                .take_while(|(a, b)| a == b)
+                .count();
+            bytes.extend(serialize_varint(previous.len() - common));
+            bytes.extend_from_slice(&self.path.as_bytes()[common..]);
+            bytes.push(ASCII_NULL);
+        } else {
+            bytes.extend_from_slice(self.path.as_bytes());
+            let length = (bytes.len() - start + 8) & !7;
+            bytes.resize(start + length, ASCII_NULL);
+        }
+    }
+}
+
+fn truncated() -> Error {
+    Error::Corrupt("Index data was truncated.".to_string())
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> Result<u32> {
+    bytes
+        .get(at..at + 4)
+        .map(|b| u32::from_be_bytes(b.try_into().unwrap()))
+        .ok_or_else(truncated)
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> Result<u16> {
+    bytes
+        .get(at..at + 2)
+        .map(|b| u16::from_be_bytes(b.try_into().unwrap()))
+        .ok_or_else(truncated)
+}
+
+fn read_path(bytes: &[u8], at: usize) -> Result<(&[u8], usize)> {
+    let rest = bytes.get(at..).ok_or_else(truncated)?;
+    let length = rest
+        .iter()
+        .position(|b| *b == ASCII_NULL)
+        .ok_or_else(truncated)?;
+    Ok((&rest[..length], at + length))
+}
+
+fn read_varint(bytes: &[u8], at: usize) -> Result<(usize, usize)> {
+    let mut length = 0;
+    let mut value = 0;
+    loop {
+        let byte = *bytes.get(at + length).ok_or_else(truncated)?;
+        if length > 0 {
+            value += 1;
+        }
+        value = (value << 7) | (byte & 0x7f) as usize;
+        length += 1;
+        if byte & 0x80 == 0 {
+            return Ok((value, length));
+        }
+    }
+}
+
+fn serialize_varint(value: usize) -> Vec<u8> {
+    let mut bytes = vec![(value & 0x7f) as u8];
+    let mut value = value >> 7;
+    while value != 0 {
+        value -= 1;
+        bytes.push(0x80 | (value & 0x7f) as u8);
+        value >>= 7;
+    }
+    bytes.reverse();
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::object::id::ObjectFormat;
+    use crate::object::index::{read_varint, serialize_varint, Entry, Extension, Index, Stat};
+    use crate::object::tree::Mode;
+
+    fn entry(path: &str, mode: Mode, stage: u8) -> Entry {
+        Entry {
+            stat: Stat {
+                ctime: (1_600_000_000, 1),
+                mtime: (1_600_000_001, 2),
+                dev: 3,
+                ino: 4,
+                uid: 1000,
+                gid: 1000,
+                size: 12,
+            },
+            mode,
+            hash: "ea8c4bf7f35f6f77f75d92ad8ce8349f6e81ddba".parse().unwrap(),
+            stage,
+            assume_valid: false,
+            skip_worktree: false,
+            intent_to_add: false,
+            path: path.to_string(),
+        }
+    }
+
+    fn index(version: u32) -> Index {
+        let mut conflicted = entry("src/lib.rs", Mode::File, 2);
+        conflicted.assume_valid = true;
+        let mut sparse = entry("src/main.rs", Mode::Executable, 0);
+        sparse.skip_worktree = version >= 3;
+        Index {
+            version,
+            entries: vec![
+                entry("README.md", Mode::File, 0),
+                entry("src/lib.rs", Mode::File, 1),
+                conflicted,
+                entry("src/link", Mode::Symlink, 0),
+                sparse,
+            ],
+            extensions: vec![Extension {
+                signature: *b"TREE",
+                data: b"\x001 0\n".to_vec(),
+            }],
+        }
+    }
+
+    #[test]
+    fn round_trips_every_version() {
+        for version in 2..=4 {
+            let index = index(version);
+            let bytes = index.serialize(ObjectFormat::Sha1);
+            assert_eq!(
+                Index::deserialize(&bytes, ObjectFormat::Sha1).unwrap(),
+                index,
+                "{}",
+                version
+            );
+        }
+    }
+
+    #[test]
+    fn pads_entries_to_eight_bytes() {
+        // this matches what git writes after `git add README.md` with the same stat data
+        let index = Index {
+            version: 2,
+            entries: vec![entry("README.md", Mode::File, 0)],
+            extensions: vec![],
+        };
+        let bytes = index.serialize(ObjectFormat::Sha1);
+        assert_eq!(bytes.len(), 12 + 72 + 20);
+        assert_eq!(&bytes[..12], b"DIRC\0\0\0\x02\0\0\0\x01");
+        assert_eq!(&bytes[12 + 60..12 + 62], &[0, 9]);
+        assert_eq!(&bytes[12 + 62..12 + 72], b"README.md\0");
+    }
+
+    #[test]
+    fn compresses_paths_in_version_four() {
+        let bytes = index(4).serialize(ObjectFormat::Sha1);
+        // `src/main.rs` follows `src/link` so is stored as strip 4 then `main.rs`
+        let needle = [&[4][..], b"main.rs\0"].concat();
+        assert!(bytes.windows(needle.len()).any(|w| w == needle.as_slice()));
+        assert!(bytes.len() < index(3).serialize(ObjectFormat::Sha1).len());
+    }
+
+    #[test]
+    fn sorts_entries_by_path_and_stage() {
+        let mut unsorted = index(2);
+        unsorted.entries.reverse();
+        let bytes = unsorted.serialize(ObjectFormat::Sha1);
+        let index = Index::deserialize(&bytes, ObjectFormat::Sha1).unwrap();
+        let paths: Vec<_> = index
+            .entries
+            .iter()
+            .map(|e| (e.path.as_str(), e.stage))
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                ("README.md", 0),
+                ("src/lib.rs", 1),
+                ("src/lib.rs", 2),
+                ("src/link", 0),
+                ("src/main.rs", 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn upgrades_to_version_three_for_extended_flags() {
+        let mut index = index(2);
+        index.entries[0].intent_to_add = true;
+        let bytes = index.serialize(ObjectFormat::Sha1);
+        let read = Index::deserialize(&bytes, ObjectFormat::Sha1).unwrap();
+        assert_eq!(read.version, 3);
+        assert!(read.entries[0].intent_to_add);
+    }
+
+    #[test]
+    fn round_trips_varints() {
+        for value in &[0, 1, 127, 128, 16511, 16512, 1 << 20] {
+            let bytes = serialize_varint(*value);
+            assert_eq!(read_varint(&bytes, 0).unwrap(), (*value, bytes.len()));
+        }
+    }
+
+    #[test]
+    fn fails_to_read_corrupt_indexes() {
+        let bytes = index(2).serialize(ObjectFormat::Sha1);
+        let mut flipped = bytes.clone();
+        flipped[20] ^= 1;
+        let mut wrong_version = bytes.clone();
+        wrong_version[7] = 5;
+        let mut truncated = bytes[..bytes.len() - 30].to_vec();
+        truncated.extend(ObjectFormat::Sha1.digest(&truncated));
+        for bytes in &[
+            flipped,
+            wrong_version,
+            truncated,
+            b"DIRC".to_vec(),
+            vec![0; 40],
+        ] {
+            assert!(matches!(
+                Index::deserialize(bytes, ObjectFormat::Sha1),
+                Err(Error::Corrupt(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,506 @@
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+use crate::error::{Error, Result};
+
+const SECONDS_PER_MINUTE: i64 = 60;
+const SECONDS_PER_HOUR: i64 = 60 * SECONDS_PER_MINUTE;
+const SECONDS_PER_DAY: i64 = 24 * SECONDS_PER_HOUR;
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// The identity and time attached to commits and tags, e.g. `Linus <x@y> 1112911993 -0700`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Signature {
+    pub name: String,
+    pub email: String,
+    /// Seconds since the unix epoch.
+    pub time: i64,
+    /// Minutes east of UTC.
+    pub offset: i32,
+    /// Whether the offset is written with a `-`, kept apart from `offset` so that `-0000`, which
+    /// git uses for an unknown offset, survives.
+    pub negative: bool,
+    /// The spacing between the name and email, which is normally a single space.
+    pub separator: String,
+}
+
+/// The ways a signature's date can be rendered, matching git's `--date` modes.
+#[derive(Debug, PartialEq)]
+pub enum DateFormat {
+    Default,
+    Iso,
+    Rfc2822,
+    Relative,
+    Unix,
+}
+
+impl DateFormat {
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "default" => Ok(DateFormat::Default),
+            "iso" => Ok(DateFormat::Iso),
+            "rfc2822" => Ok(DateFormat::Rfc2822),
+            "relative" => Ok(DateFormat::Relative),
+            "unix" => Ok(DateFormat::Unix),
+            other => Err(Error::InvalidArgument(format!(
+                "Date format {} is not valid.",
+                other
+            ))),
+        }
+    }
+}
+
+impl Signature {
+    pub fn new(name: String, email: String, time: i64, offset: i32) -> Self {
+        Self {
+            name,
+            email,
+            time,
+            offset,
+            negative: offset < 0,
+            separator: " ".to_string(),
+        }
+    }
+
+    pub fn serialize(&self) -> String {
+        format!(
+            "{}{}<{}> {} {}",
+            self.name,
+            self.separator,
+            self.email,
+            self.time,
+            self.serialize_offset()
+        )
+    }
+
+    pub fn deserialize(content: &str) -> Result<Self> {
+        static REGEX: OnceLock<Regex> = OnceLock::new();
+        let regex = REGEX.get_or_init(|| {
+            Regex::new(
+                r"^(?P<name>[^<]*?)(?P<separator> *)<(?P<email>[^>]*)> (?P<time>-?\d+) (?P<sign>[+-])(?P<hours>\d{2})(?P<minutes>\d{2})$",
+            )
+            .unwrap()
+        });
+        let captures = regex
+            .captures(content)
+            .ok_or_else(|| Error::Corrupt(format!("Signature {} couldn't be parsed.", content)))?;
+        let number = |name: &str| {
+            captures[name]
+                .parse::<i64>()
+                .map_err(|_| Error::Corrupt(format!("Signature {} couldn't be parsed.", content)))
+        };
+
+        let offset = number("hours")? * 60 + number("minutes")?;
+        let negative = &captures["sign"] == "-";
+        let sign = if negative { -1 } else { 1 };
+        Ok(Self {
+            name: captures["name"].to_string(),
+            email: captures["email"].to_string(),
+            time: number("time")?,
+            offset: (sign * offset) as i32,
+            negative,
+            separator: captures["separator"].to_string(),
+        })
+    }
+
+    fn serialize_offset(&self) -> String {
+        let sign = if self.negative { '-' } else { '+' };
+        let offset = self.offset.abs();
+        format!("{}{:02}{:02}", sign, offset / 60, offset % 60)
+    }
+
+    /// Renders the date, using `now` as the reference point for relative dates.
+    pub fn format_date(&self, format: &DateFormat, now: i64) -> String {
+        let local = self.time + self.offset as i64 * SECONDS_PER_MINUTE;
+        let days = local.div_euclid(SECONDS_PER_DAY);
+        let seconds = local.rem_euclid(SECONDS_PER_DAY);
+        let (year, month, day) = civil_from_days(days);
+        let weekday = WEEKDAYS[(days + 4).rem_euclid(7) as usize];
+        let time = format!(
+            "{:02}:{:02}:{:02}",
+            seconds / SECONDS_PER_HOUR,
+            seconds % SECONDS_PER_HOUR / SECONDS_PER_MINUTE,
+            seconds % SECONDS_PER_MINUTE
+        );
+        let month_name = MONTHS[month as usize - 1];
+
+        match format {
+            DateFormat::Default => format!(
+                "{} {} {} {} {} {}",
+                weekday,
+                month_name,
+                day,
+                time,
+                year,
+                self.serialize_offset()
+            ),
+            DateFormat::Iso => format!(
+                "{}-{:02}-{:02} {} {}",
+                year,
+                month,
+                day,
+                time,
+                self.serialize_offset()
+            ),
+            DateFormat::Rfc2822 => format!(
+                "{}, {} {} {} {} {}",
+                weekday,
+                day,
+                month_name,
+                year,
+                time,
+                self.serialize_offset()
+            ),
+            DateFormat::Relative => format_relative(now - self.time),
+            DateFormat::Unix => self.time.to_string(),
+        }
+    }
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    // this is Howard Hinnant's algorithm which works in 400 year eras starting in March
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    // the inverse of `civil_from_days`, again counting years from March
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let shifted_month = (month as i64 + 9) % 12;
+    let day_of_year = (153 * shifted_month + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Parses the dates git accepts in places like `master@{yesterday}`, returning seconds since the
+/// unix epoch.
+///
+/// This covers `now`, `yesterday`, relative dates like `2.weeks.ago` or `3 days ago`, ISO dates
+/// like `2020-01-31` or `2020-01-31 12:30:00` in UTC, and raw timestamps like `@1600000000`.
+pub fn parse_date(text: &str, now: i64) -> Result<i64> {
+    let invalid = || Error::InvalidArgument(format!("Date {} is not valid.", text));
+    let text = text.trim().to_lowercase();
+    match text.as_str() {
+        "now" => return Ok(now),
+        "yesterday" => return Ok(now - SECONDS_PER_DAY),
+        _ => {}
+    }
+    if let Some(timestamp) = text.strip_prefix('@') {
+        return timestamp.parse().map_err(|_| invalid());
+    }
+
+    let words: Vec<&str> = text.split(['.', ' ']).collect();
+    if let [count, unit, "ago"] = words.as_slice() {
+        let count: i64 = count.parse().map_err(|_| invalid())?;
+        let seconds = match unit.trim_end_matches('s') {
+            "second" => 1,
+            "minute" => SECONDS_PER_MINUTE,
+            "hour" => SECONDS_PER_HOUR,
+            "day" => SECONDS_PER_DAY,
+            "week" => 7 * SECONDS_PER_DAY,
+            // git approximates months and years the same way
+            "month" => 30 * SECONDS_PER_DAY,
+            "year" => 365 * SECONDS_PER_DAY,
+            _ => return Err(invalid()),
+        };
+        return Ok(now - count * seconds);
+    }
+
+    let regex = Regex::new(
+        r"^(?P<year>\d{4})-(?P<month>\d{2})-(?P<day>\d{2})(?:[ t](?P<hour>\d{2}):(?P<minute>\d{2})(?::(?P<second>\d{2}))?)?$",
+    )
+    .unwrap();
+    let captures = regex.captures(&text).ok_or_else(invalid)?;
+    let number = |name: &str| {
+        captures
+            .name(name)
+            .map_or(Ok(0), |m| m.as_str().parse::<i64>())
+            .map_err(|_| invalid())
+    };
+    let (month, day) = (number("month")?, number("day")?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(invalid());
+    }
+    let days = days_from_civil(number("year")?, month as u32, day as u32);
+    Ok(days * SECONDS_PER_DAY
+        + number("hour")? * SECONDS_PER_HOUR
+        + number("minute")? * SECONDS_PER_MINUTE
+        + number("second")?)
+}
+
+/// The local timezone's offset from UTC at the given time, in minutes east.
+#[cfg(unix)]
+pub fn local_offset(time: i64) -> i32 {
+    let time = time as libc::time_t;
+    let mut tm = std::mem::MaybeUninit::<libc::tm>::uninit();
+    // localtime_r only writes to the tm it's given, unlike localtime which shares a static one
+    let tm = unsafe {
+        if libc::localtime_r(&time, tm.as_mut_ptr()).is_null() {
+            return 0;
+        }
+        tm.assume_init()
+    };
+    (tm.tm_gmtoff / 60) as i32
+}
+
+/// The local timezone's offset from UTC at the given time, in minutes east.
+#[cfg(not(unix))]
+pub fn local_offset(_time: i64) -> i32 {
+    0
+}
+
+/// Parses the date given for a new signature through `GIT_COMMITTER_DATE`, either raw like
+/// `1112911993 -0700` or anything `parse_date` accepts followed by an optional offset, returning
+/// the time along with its offset, which is the local one unless one is given.
+pub fn parse_signature_date(text: &str, now: i64) -> Result<(i64, i32)> {
+    let text = text.trim();
+    let (date, offset) = match text.rsplit_once(' ') {
+        Some((date, zone)) => match parse_offset(zone) {
+            Some(offset) => (date.trim_end(), Some(offset)),
+            None => (text, None),
+        },
+        None => (text, None),
+    };
+    let time = match date.trim_start_matches('@').parse::<i64>() {
+        Ok(time) => time,
+        // dates are read as UTC so one with an offset is shifted back by it
+        Err(_) => parse_date(date, now)? - offset.unwrap_or(0) as i64 * SECONDS_PER_MINUTE,
+    };
+    Ok((time, offset.unwrap_or_else(|| local_offset(time))))
+}
+
+fn parse_offset(zone: &str) -> Option<i32> {
+    let sign = match zone.get(..1)? {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    let digits = zone
+        .get(1..)
+        .filter(|d| d.len() == 4 && d.chars().all(|c| c.is_ascii_digit()))?;
+    let (hours, minutes): (i32, i32) = (digits[..2].parse().ok()?, digits[2..].parse().ok()?);
+    Some(sign * (hours * 60 + minutes))
+}
+
+fn format_relative(elapsed: i64) -> String {
+    let plural = |count: i64, unit: &str| {
+        format!(
+            "{} {}{} ago",
+            count,
+            unit,
+            if count == 1 { "" } else { "s" }
+        )
+    };
+
+    if elapsed < 0 {
+        return "in the future".to_string();
+    }
+    if elapsed < 90 {
+        return plural(elapsed, "second");
+    }
+    let minutes = (elapsed + 30) / SECONDS_PER_MINUTE;
+    if minutes < 90 {
+        return plural(minutes, "minute");
+    }
+    let hours = (minutes + 30) / 60;
+    if hours < 36 {
+        return plural(hours, "hour");
+    }
+    let days = (hours + 12) / 24;
+    if days < 14 {
+        return plural(days, "day");
+    }
+    if days < 70 {
+        return plural((days + 3) / 7, "week");
+    }
+    if days < 365 {
+        return plural((days + 15) / 30, "month");
+    }
+    plural((days + 183) / 365, "year")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::object::signature::{
+        civil_from_days, days_from_civil, format_relative, local_offset, parse_date,
+        parse_signature_date, DateFormat, Signature,
+    };
+
+    fn linus() -> Signature {
+        Signature::new(
+            "Linus Torvalds".to_string(),
+            "torvalds@ppc970.osdl.org".to_string(),
+            1112911993,
+            -420,
+        )
+    }
+
+    #[test]
+    fn round_trips_signatures() {
+        let serialized = "Linus Torvalds <torvalds@ppc970.osdl.org> 1112911993 -0700";
+        let signature = Signature::deserialize(serialized).unwrap();
+        assert_eq!(signature, linus());
+        assert_eq!(signature.serialize(), serialized)
+    }
+
+    #[test]
+    fn round_trips_signatures_with_positive_offsets() {
+        let serialized = "rhysormond <email> 1597275816 +0530";
+        let signature = Signature::deserialize(serialized).unwrap();
+        assert_eq!(signature.offset, 330);
+        assert_eq!(signature.serialize(), serialized)
+    }
+
+    #[test]
+    fn round_trips_signatures_exactly() {
+        for (serialized, name) in &[
+            ("A <a@b> 1 -0000", "A"),
+            ("A<a@b> 1 +0000", "A"),
+            ("<a@b> 1 +0000", ""),
+            ("A  <a@b> 1 +0100", "A"),
+        ] {
+            let signature = Signature::deserialize(serialized).unwrap();
+            assert_eq!(&signature.name, name);
+            assert_eq!(&signature.serialize(), serialized);
+        }
+    }
+
+    #[test]
+    fn fails_when_signature_is_malformed() {
+        let result = Signature::deserialize("Linus Torvalds torvalds@ppc970.osdl.org 1112911993");
+        assert!(matches!(result, Err(Error::Corrupt(_))));
+    }
+
+    #[test]
+    fn converts_days_to_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(11059), (2000, 4, 12));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(19417), (2023, 3, 1));
+    }
+
+    #[test]
+    fn formats_dates() {
+        let signature = linus();
+        let now = signature.time + 3 * 24 * 60 * 60;
+        assert_eq!(
+            signature.format_date(&DateFormat::Default, now),
+            "Thu Apr 7 15:13:13 2005 -0700"
+        );
+        assert_eq!(
+            signature.format_date(&DateFormat::Iso, now),
+            "2005-04-07 15:13:13 -0700"
+        );
+        assert_eq!(
+            signature.format_date(&DateFormat::Rfc2822, now),
+            "Thu, 7 Apr 2005 15:13:13 -0700"
+        );
+        assert_eq!(
+            signature.format_date(&DateFormat::Relative, now),
+            "3 days ago"
+        );
+        assert_eq!(signature.format_date(&DateFormat::Unix, now), "1112911993");
+    }
+
+    #[test]
+    fn formats_relative_dates() {
+        assert_eq!(format_relative(1), "1 second ago");
+        assert_eq!(format_relative(600), "10 minutes ago");
+        assert_eq!(format_relative(5 * 60 * 60), "5 hours ago");
+        assert_eq!(format_relative(21 * 24 * 60 * 60), "3 weeks ago");
+        assert_eq!(format_relative(100 * 24 * 60 * 60), "3 months ago");
+        assert_eq!(format_relative(800 * 24 * 60 * 60), "2 years ago");
+        assert_eq!(format_relative(-5), "in the future");
+    }
+
+    #[test]
+    fn parses_date_formats() {
+        assert_eq!(DateFormat::from_name("iso").unwrap(), DateFormat::Iso);
+        assert_eq!(
+            DateFormat::from_name("relative").unwrap(),
+            DateFormat::Relative
+        );
+        assert!(matches!(
+            DateFormat::from_name("sometime"),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn round_trips_civil_days() {
+        for days in &[-719_468, -1, 0, 11_016, 18_262, 2_932_896] {
+            let (year, month, day) = civil_from_days(*days);
+            assert_eq!(days_from_civil(year, month, day), *days);
+        }
+    }
+
+    #[test]
+    fn parses_dates() {
+        let now = 1_600_000_000;
+        assert_eq!(parse_date("now", now).unwrap(), now);
+        assert_eq!(parse_date("yesterday", now).unwrap(), now - 86_400);
+        assert_eq!(parse_date("2.weeks.ago", now).unwrap(), now - 14 * 86_400);
+        assert_eq!(parse_date("1 hour ago", now).unwrap(), now - 3_600);
+        assert_eq!(parse_date("@1234", now).unwrap(), 1234);
+        assert_eq!(parse_date("2020-09-13", now).unwrap(), 1_599_955_200);
+        assert_eq!(
+            parse_date("2020-09-13 12:26:40", now).unwrap(),
+            1_600_000_000
+        );
+    }
+
+    #[test]
+    fn parses_signature_dates() {
+        let now = 1_600_000_000;
+        assert_eq!(
+            parse_signature_date("1112911993 -0700", now).unwrap(),
+            (1_112_911_993, -420)
+        );
+        assert_eq!(
+            parse_signature_date("@1112911993 +0530", now).unwrap(),
+            (1_112_911_993, 330)
+        );
+        assert_eq!(
+            parse_signature_date("2020-09-13 14:26:40 +0200", now).unwrap(),
+            (1_600_000_000, 120)
+        );
+        assert_eq!(
+            parse_signature_date("1112911993", now).unwrap(),
+            (1_112_911_993, local_offset(1_112_911_993))
+        );
+        assert!(matches!(
+            parse_signature_date("soon +0000", now),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn fails_to_parse_invalid_dates() {
+        for date in &["tomorrow", "2.fortnights.ago", "2020-13-01", "2020-1-1"] {
+            assert!(matches!(
+                parse_date(date, 0),
+                Err(Error::InvalidArgument(_))
+            ));
+        }
+    }
+}
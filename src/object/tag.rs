@@ -1,33 +1,209 @@
-#[derive(Debug, PartialEq)]
+use crate::error::{Error, Result};
+use crate::object::header::{self, Headers};
+use crate::object::id::ObjectId;
+use crate::object::signature::Signature;
+
+const SIGNATURE_STARTS: [&[u8]; 2] = [
+    b"-----BEGIN PGP SIGNATURE-----",
+    b"-----BEGIN SSH SIGNATURE-----",
+];
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct Tag {
-    // TODO[Rhys] figure out how tags are actually structured
-    content: String,
+    pub object: ObjectId,
+    pub kind: String,
+    pub name: String,
+    // very old tags were created before git started recording a tagger
+    pub tagger: Option<Signature>,
+    // anything after the tagger, e.g. `encoding`, kept in order
+    headers: Headers,
+    // the message is in whatever `encoding` says, which needn't be utf-8
+    pub message: Vec<u8>,
+    pub signature: Option<String>,
 }
 
 impl Tag {
-    pub fn serialize(&self) -> Vec<u8> {
-        self.content.clone().into_bytes()
+    pub fn new(
+        object: ObjectId,
+        kind: String,
+        name: String,
+        tagger: Option<Signature>,
+        message: Vec<u8>,
+    ) -> Self {
+        Self {
+            object,
+            kind,
+            name,
+            tagger,
+            headers: vec![],
+            message,
+            signature: None,
+        }
     }
 
-    pub fn deserialize(bytes: Vec<u8>) -> Self {
-        Self {
-            content: String::from_utf8(bytes).unwrap(),
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let tagger = self
+            .tagger
+            .as_ref()
+            .map(|t| header::serialize_field("tagger", &t.serialize()))
+            .unwrap_or_default();
+        let fields = [
+            header::serialize_field("object", &self.object.to_string()),
+            header::serialize_field("type", &self.kind),
+            header::serialize_field("tag", &self.name),
+            tagger,
+            self.headers
+                .iter()
+                .map(|(key, value)| header::serialize_field(key, value))
+                .collect(),
+            "\n".to_string(),
+        ];
+        let mut bytes: Vec<u8> = fields.iter().flat_map(|f| f.as_bytes()).cloned().collect();
+        bytes.extend_from_slice(&self.message);
+        if let Some(signature) = &self.signature {
+            bytes.extend_from_slice(signature.as_bytes());
         }
+        bytes
+    }
+
+    pub fn deserialize(bytes: Vec<u8>) -> Result<Self> {
+        let (headers, body) = header::parse(&bytes, "Tag")?;
+        let mut fields = headers.into_iter().peekable();
+        let hex = header::expect_field(&mut fields, "object", "Tag")?;
+        let object = ObjectId::from_hex(&hex)
+            .map_err(|_| Error::Corrupt(format!("Tag referenced invalid object id {}.", hex)))?;
+        let kind = header::expect_field(&mut fields, "type", "Tag")?;
+        let name = header::expect_field(&mut fields, "tag", "Tag")?;
+        let tagger = fields
+            .next_if(|(key, _)| key == "tagger")
+            .map(|(_, tagger)| Signature::deserialize(&tagger))
+            .transpose()?;
+
+        // the signature runs from the first line that starts one to the end
+        let start = (0..body.len())
+            .filter(|&i| i == 0 || body[i - 1] == b'\n')
+            .find(|&i| SIGNATURE_STARTS.iter().any(|s| body[i..].starts_with(s)));
+        let (message, signature) = match start {
+            Some(start) => {
+                let signature = std::str::from_utf8(&body[start..]).map_err(|_| {
+                    Error::Corrupt("Tag signature was not valid utf-8.".to_string())
+                })?;
+                (&body[..start], Some(signature.to_string()))
+            }
+            None => (body, None),
+        };
+
+        Ok(Self {
+            object,
+            kind,
+            name,
+            tagger,
+            headers: fields.collect(),
+            message: message.to_vec(),
+            signature,
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::error::Error;
+    use crate::object::signature::Signature;
     use crate::object::tag::Tag;
 
     #[test]
-    fn can_roundtrip_tags() {
-        let serialized = "tag";
-        let deserialized = Tag {
-            content: serialized.to_string(),
-        };
-        let tag = Tag::deserialize(Vec::from(serialized));
+    fn round_trips_tags() {
+        let serialized = "\
+            object 99d235f56d415bed9c1992fea544f54cb05dda5e\n\
+            type commit\n\
+            tag v1.0\n\
+            tagger rhysormond <email> 1597275816 -0700\n\
+            \n\
+            First release\n\
+            \n\
+            With some notes.\n\
+        ";
+        let deserialized = Tag::new(
+            "99d235f56d415bed9c1992fea544f54cb05dda5e".parse().unwrap(),
+            "commit".to_string(),
+            "v1.0".to_string(),
+            Some(Signature::deserialize("rhysormond <email> 1597275816 -0700").unwrap()),
+            b"First release\n\nWith some notes.\n".to_vec(),
+        );
+        let tag = Tag::deserialize(Vec::from(serialized)).unwrap();
         assert_eq!(tag, deserialized);
         assert_eq!(String::from_utf8(tag.serialize()).unwrap(), serialized)
     }
+
+    #[test]
+    fn round_trips_tags_without_tagger() {
+        let serialized = "\
+            object 5dc01c595e6c6ec9ccda4f6f69c131c0dd945f8c\n\
+            type commit\n\
+            tag v2.6.11-tree\n\
+            \n\
+            This is the 2.6.11 tree object.\n\
+        ";
+        let tag = Tag::deserialize(Vec::from(serialized)).unwrap();
+        assert_eq!(tag.tagger, None);
+        assert_eq!(tag.name, "v2.6.11-tree");
+        assert_eq!(String::from_utf8(tag.serialize()).unwrap(), serialized)
+    }
+
+    #[test]
+    fn round_trips_signed_tags() {
+        let serialized = "\
+            object 99d235f56d415bed9c1992fea544f54cb05dda5e\n\
+            type tree\n\
+            tag signed\n\
+            tagger rhysormond <email> 1597275816 -0700\n\
+            \n\
+            Signed tag\n\
+            -----BEGIN PGP SIGNATURE-----\n\
+            \n\
+            iQIzBAABCAAdFiEEdnvMMujyElTR0B8vmoIqIpWBpgYFAl80frQACgkQmoIqIpWB\n\
+            =By2v\n\
+            -----END PGP SIGNATURE-----\n\
+        ";
+        let tag = Tag::deserialize(Vec::from(serialized)).unwrap();
+        assert_eq!(tag.kind, "tree");
+        assert_eq!(tag.message, b"Signed tag\n");
+        assert!(tag
+            .signature
+            .as_ref()
+            .unwrap()
+            .starts_with("-----BEGIN PGP SIGNATURE-----\n"));
+        assert_eq!(String::from_utf8(tag.serialize()).unwrap(), serialized)
+    }
+
+    #[test]
+    fn round_trips_tags_with_extra_headers_and_other_encodings() {
+        let mut serialized = b"\
+            object 99d235f56d415bed9c1992fea544f54cb05dda5e\n\
+            type commit\n\
+            tag v1.0\n\
+            tagger rhysormond <email> 1597275816 -0700\n\
+            encoding ISO-8859-1\n\
+            \n\
+            Caf"
+        .to_vec();
+        serialized.extend_from_slice(b"\xe9\n");
+        let tag = Tag::deserialize(serialized.clone()).unwrap();
+        assert_eq!(tag.header("encoding"), Some("ISO-8859-1"));
+        assert_eq!(tag.message, b"Caf\xe9\n");
+        assert_eq!(tag.serialize(), serialized)
+    }
+
+    #[test]
+    fn fails_when_tag_is_malformed() {
+        let result = Tag::deserialize(Vec::from("tag"));
+        assert!(matches!(result, Err(Error::Corrupt(_))));
+    }
 }
@@ -1,14 +1,14 @@
-use std::num::ParseIntError;
-
 use nom::{
     bytes::complete::{take, take_while, take_while_m_n},
     character::complete::char,
-    combinator::map_res,
+    combinator::{map_opt, map_res},
     sequence::tuple,
     IResult,
 };
 
+use crate::error::{Error, Result};
 use crate::object::constant::*;
+use crate::object::id::{ObjectFormat, ObjectId};
 
 #[derive(Debug, PartialEq)]
 pub struct Tree {
@@ -16,59 +16,98 @@ pub struct Tree {
 }
 
 impl Tree {
+    /// Serializes leaves in git's canonical order so that equivalent trees always hash identically.
     pub fn serialize(&self) -> Vec<u8> {
-        self.leaves.iter().flat_map(|l| l.serialize()).collect()
+        let mut leaves: Vec<&Leaf> = self.leaves.iter().collect();
+        leaves.sort_by_key(|l| l.sort_key());
+        leaves.iter().flat_map(|l| l.serialize()).collect()
     }
 
-    fn deserialize_leaves(bytes: &[u8]) -> Vec<Leaf> {
-        if bytes.is_empty() {
-            vec![]
-        } else {
-            let (remainder, leaf) = Leaf::deserialize(&bytes);
-            let mut other = Self::deserialize_leaves(remainder);
-            other.push(leaf);
-            other
+    fn deserialize_leaves(bytes: &[u8], format: ObjectFormat) -> Result<Vec<Leaf>> {
+        let mut leaves = vec![];
+        let mut remainder = bytes;
+        while !remainder.is_empty() {
+            let (rest, leaf) = Leaf::deserialize(remainder, format)?;
+            leaves.push(leaf);
+            remainder = rest;
         }
+        Ok(leaves)
+    }
+
+    /// Deserializes a tree, which needs to know the object format as entries hold raw ids.
+    pub fn deserialize(bytes: Vec<u8>, format: ObjectFormat) -> Result<Self> {
+        Ok(Self {
+            leaves: Self::deserialize_leaves(&bytes, format)?,
+        })
     }
+}
 
-    pub fn deserialize(bytes: Vec<u8>) -> Self {
-        Self {
-            leaves: Self::deserialize_leaves(&bytes),
+/// The kinds of entries a tree can hold, as identified by their octal mode.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Mode {
+    File,
+    Executable,
+    Symlink,
+    Directory,
+    /// A commit in another repository, so it can't be read from this one.
+    Submodule,
+}
+
+impl Mode {
+    pub fn serialize(&self) -> &'static str {
+        match self {
+            Mode::File => "100644",
+            Mode::Executable => "100755",
+            Mode::Symlink => "120000",
+            // git drops the leading 0 for directories when writing trees
+            Mode::Directory => "40000",
+            Mode::Submodule => "160000",
+        }
+    }
+
+    pub fn deserialize(mode: &str) -> Option<Self> {
+        match mode {
+            "100644" => Some(Mode::File),
+            "100755" => Some(Mode::Executable),
+            "120000" => Some(Mode::Symlink),
+            "40000" | "040000" => Some(Mode::Directory),
+            "160000" => Some(Mode::Submodule),
+            _ => None,
         }
     }
 }
 
 #[derive(Debug, PartialEq)]
 pub struct Leaf {
-    mode: String,
+    pub mode: Mode,
     pub path: String,
-    pub hash: String,
+    pub hash: ObjectId,
 }
 
 impl Leaf {
-    fn encode_hash(hash: &str) -> Vec<u8> {
-        hex::decode(hash).expect("Unable to encode hash.")
-    }
-
-    fn decode_hash(bytes: &[u8]) -> String {
-        hex::encode(bytes)
+    fn sort_key(&self) -> Vec<u8> {
+        let mut key = self.path.as_bytes().to_vec();
+        if self.mode == Mode::Directory {
+            key.push(b'/');
+        }
+        key
     }
 
     pub fn serialize(&self) -> Vec<u8> {
-        vec![
-            self.mode.as_bytes(),
-            &vec![ASCII_SPACE],
+        [
+            self.mode.serialize().as_bytes(),
+            &[ASCII_SPACE],
             self.path.as_bytes(),
-            &vec![ASCII_NULL],
-            &Self::encode_hash(&self.hash),
+            &[ASCII_NULL],
+            self.hash.as_bytes(),
         ]
         .concat()
     }
 
-    fn parse_mode(input: &[u8]) -> IResult<&[u8], String> {
+    fn parse_mode(input: &[u8]) -> IResult<&[u8], Mode> {
         // NOTE[Rhys] modes don't have to be 6 characters as leading 0s are dropped
-        map_res(take_while_m_n(0, 6, |c| c != ASCII_SPACE), |c: &[u8]| {
-            String::from_utf8(c.to_vec())
+        map_opt(take_while_m_n(0, 6, |c| c != ASCII_SPACE), |c: &[u8]| {
+            std::str::from_utf8(c).ok().and_then(Mode::deserialize)
         })(input)
     }
 
@@ -78,57 +117,53 @@ impl Leaf {
         })(input)
     }
 
-    fn parse_hash(input: &[u8]) -> IResult<&[u8], String> {
-        // Note[Rhys] this ParseIntError is a lie but nom expects there to be some error type here
-        map_res(take(20 as usize), |h: &[u8]| {
-            Ok::<String, ParseIntError>(Leaf::decode_hash(h))
-        })(input)
+    fn parse_hash(format: ObjectFormat) -> impl Fn(&[u8]) -> IResult<&[u8], ObjectId> {
+        move |input| map_res(take(format.size()), ObjectId::from_bytes)(input)
     }
 
-    fn deserialize(bytes: &[u8]) -> (&[u8], Self) {
+    fn deserialize(bytes: &[u8], format: ObjectFormat) -> Result<(&[u8], Self)> {
         let (remainder, (mode, _, path, _, hash)) = tuple((
             Leaf::parse_mode,
             char(ASCII_SPACE_CHAR),
             Leaf::parse_path,
             char(ASCII_NULL_CHAR),
-            Leaf::parse_hash,
+            Leaf::parse_hash(format),
         ))(bytes)
-        .unwrap();
+        .map_err(|_| Error::Corrupt("Tree entry couldn't be parsed.".to_string()))?;
+        Leaf::check_path(&path)?;
+
+        Ok((remainder, Self { mode, path, hash }))
+    }
 
-        (remainder, Self { mode, path, hash })
+    /// Checks that an entry names something directly inside its tree, so checking it out can't
+    /// escape the work tree or write into the git directory.
+    pub fn check_path(path: &str) -> Result<()> {
+        let reserved = [".", "..", ".git"];
+        if path.is_empty()
+            || path.contains('/')
+            || reserved.iter().any(|name| path.eq_ignore_ascii_case(name))
+        {
+            return Err(Error::Corrupt(format!(
+                "Tree entry {:?} is not a valid name.",
+                path
+            )));
+        }
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::error::Error;
     use crate::object::constant::{ASCII_NULL, ASCII_SPACE};
-    use crate::object::tree::{Leaf, Tree};
-
-    #[test]
-    fn decodes_hash() {
-        let raw = vec![
-            0, 219, 250, 237, 236, 71, 165, 169, 35, 228, 150, 70, 108, 63, 223, 76, 200, 117, 247,
-            74,
-        ];
-        let parsed = "00dbfaedec47a5a923e496466c3fdf4cc875f74a";
-        assert_eq!(Leaf::decode_hash(&raw), parsed)
-    }
-
-    #[test]
-    fn encodes_hash() {
-        let raw = "00dbfaedec47a5a923e496466c3fdf4cc875f74a";
-        let parsed = vec![
-            0, 219, 250, 237, 236, 71, 165, 169, 35, 228, 150, 70, 108, 63, 223, 76, 200, 117, 247,
-            74,
-        ];
-        assert_eq!(Leaf::encode_hash(&raw), parsed)
-    }
+    use crate::object::id::ObjectFormat;
+    use crate::object::tree::{Leaf, Mode, Tree};
 
     #[test]
     fn parses_mode() {
         let raw = [49, 48, 48, 54, 52, 52, ASCII_SPACE];
         let (remainder, mode) = Leaf::parse_mode(&raw).unwrap();
-        assert_eq!(mode, "100644");
+        assert_eq!(mode, Mode::File);
         assert_eq!(remainder, [ASCII_SPACE]);
     }
 
@@ -136,10 +171,31 @@ mod tests {
     fn parses_mode_with_implicit_leading_zero() {
         let raw = [52, 48, 48, 48, 48, ASCII_SPACE];
         let (remainder, mode) = Leaf::parse_mode(&raw).unwrap();
-        assert_eq!(mode, "40000");
+        assert_eq!(mode, Mode::Directory);
         assert_eq!(remainder, [ASCII_SPACE]);
     }
 
+    #[test]
+    fn parses_every_mode() {
+        for mode in &[
+            Mode::File,
+            Mode::Executable,
+            Mode::Symlink,
+            Mode::Directory,
+            Mode::Submodule,
+        ] {
+            assert_eq!(Mode::deserialize(mode.serialize()), Some(*mode));
+        }
+        assert_eq!(Mode::deserialize("040000"), Some(Mode::Directory));
+    }
+
+    #[test]
+    fn doesnt_parse_invalid_modes() {
+        let raw = [49, 48, 48, 54, 54, 52, ASCII_SPACE];
+        assert!(Leaf::parse_mode(&raw).is_err());
+        assert_eq!(Mode::deserialize("100666"), None);
+    }
+
     #[test]
     fn parses_path() {
         let raw = [82, 69, 65, 68, 77, 69, 46, 109, 100, ASCII_NULL];
@@ -148,6 +204,19 @@ mod tests {
         assert_eq!(remainder, [ASCII_NULL]);
     }
 
+    #[test]
+    fn fails_to_parse_unsafe_paths() {
+        for path in &["", ".", "..", ".git", ".GIT", "a/b", "/etc"] {
+            let raw: &[u8] = &[b"100644 ", path.as_bytes(), &[ASCII_NULL], &[0; 20]].concat();
+            assert!(matches!(
+                Leaf::deserialize(raw, ObjectFormat::Sha1),
+                Err(Error::Corrupt(_))
+            ));
+        }
+        assert!(Leaf::check_path(".gitignore").is_ok());
+        assert!(Leaf::check_path("...").is_ok());
+    }
+
     #[test]
     fn parses_hash() {
         let expected_remainder: u8 = 100;
@@ -174,8 +243,8 @@ mod tests {
             74,
             expected_remainder,
         ];
-        let (remainder, mode) = Leaf::parse_hash(&raw).unwrap();
-        assert_eq!(mode, "00dbfaedec47a5a923e496466c3fdf4cc875f74a");
+        let (remainder, mode) = Leaf::parse_hash(ObjectFormat::Sha1)(&raw).unwrap();
+        assert_eq!(mode.to_string(), "00dbfaedec47a5a923e496466c3fdf4cc875f74a");
         assert_eq!(remainder, [expected_remainder]);
     }
 
@@ -197,11 +266,11 @@ mod tests {
         .concat();
 
         let deserialized = Leaf {
-            mode: "100644".to_string(),
+            mode: Mode::File,
             path: ".gitignore".to_string(),
-            hash: "ea8c4bf7f35f6f77f75d92ad8ce8349f6e81ddba".to_string(),
+            hash: "ea8c4bf7f35f6f77f75d92ad8ce8349f6e81ddba".parse().unwrap(),
         };
-        let (remainder, leaf) = Leaf::deserialize(&serialized);
+        let (remainder, leaf) = Leaf::deserialize(serialized, ObjectFormat::Sha1).unwrap();
         assert!(remainder.is_empty());
         assert_eq!(leaf, deserialized);
         assert_eq!(leaf.serialize(), serialized)
@@ -210,8 +279,85 @@ mod tests {
     #[test]
     fn round_trips_empty_tree() {
         let empty = Tree { leaves: vec![] };
-        let tree = Tree::deserialize(vec![]);
+        let tree = Tree::deserialize(vec![], ObjectFormat::Sha1).unwrap();
         assert_eq!(tree, empty);
         assert_eq!(tree.serialize(), vec![]);
     }
+
+    #[test]
+    fn serializes_leaves_in_canonical_order() {
+        let leaf = |mode: Mode, path: &str| Leaf {
+            mode,
+            path: path.to_string(),
+            hash: "ea8c4bf7f35f6f77f75d92ad8ce8349f6e81ddba".parse().unwrap(),
+        };
+        let tree = Tree {
+            leaves: vec![
+                leaf(Mode::Directory, "foo"),
+                leaf(Mode::File, "foo.txt"),
+                leaf(Mode::Executable, "bar"),
+                leaf(Mode::File, "foo-bar"),
+            ],
+        };
+        let paths: Vec<String> = Tree::deserialize(tree.serialize(), ObjectFormat::Sha1)
+            .unwrap()
+            .leaves
+            .into_iter()
+            .map(|l| l.path)
+            .collect();
+        assert_eq!(paths, vec!["bar", "foo-bar", "foo.txt", "foo"]);
+    }
+
+    #[test]
+    fn round_trips_trees_in_order() {
+        let leaf = |mode: Mode, path: &str, hash: &str| Leaf {
+            mode,
+            path: path.to_string(),
+            hash: hash.parse().unwrap(),
+        };
+        let tree = Tree {
+            leaves: vec![
+                leaf(Mode::File, "a", "ea8c4bf7f35f6f77f75d92ad8ce8349f6e81ddba"),
+                leaf(
+                    Mode::Symlink,
+                    "b",
+                    "00dbfaedec47a5a923e496466c3fdf4cc875f74a",
+                ),
+                leaf(
+                    Mode::Directory,
+                    "c",
+                    "c171921c5c0f2e02f7243c13d331e96f149fd653",
+                ),
+            ],
+        };
+        let deserialized = Tree::deserialize(tree.serialize(), ObjectFormat::Sha1).unwrap();
+        assert_eq!(deserialized, tree);
+        assert_eq!(deserialized.serialize(), tree.serialize())
+    }
+
+    #[test]
+    fn fails_when_tree_is_truncated() {
+        let result = Tree::deserialize(
+            vec![49, 48, 48, 54, 52, 52, ASCII_SPACE, 97, ASCII_NULL, 1],
+            ObjectFormat::Sha1,
+        );
+        assert!(matches!(result, Err(Error::Corrupt(_))));
+    }
+
+    #[test]
+    fn round_trips_sha256_trees() {
+        let tree = Tree {
+            leaves: vec![Leaf {
+                mode: Mode::File,
+                path: "a".to_string(),
+                hash: "ab".repeat(32).parse().unwrap(),
+            }],
+        };
+        let serialized = tree.serialize();
+        assert_eq!(serialized.len(), "100644 a\0".len() + 32);
+        assert_eq!(
+            Tree::deserialize(serialized, ObjectFormat::Sha256).unwrap(),
+            tree
+        );
+    }
 }
@@ -0,0 +1,1093 @@
+use std::cmp::Reverse;
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryInto;
+use std::fs;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crypto::digest::Digest;
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+
+use crate::error::{Error, Result};
+use crate::object::constant::*;
+use crate::object::id::{ObjectFormat, ObjectId};
+use crate::store::file::LockFile;
+
+const IDX_SIGNATURE: [u8; 4] = [0xff, 0x74, 0x4f, 0x63];
+const IDX_VERSION: u32 = 2;
+const PACK_SIGNATURE: &[u8] = b"PACK";
+const FANOUT_ENTRIES: usize = 256;
+const LARGE_OFFSET_FLAG: u32 = 0x8000_0000;
+const PACK_VERSION: u32 = 2;
+// sizes come from untrusted headers so buffers only reserve up to this much in advance
+const MAX_PREALLOCATION: usize = 1 << 20;
+
+// the longest delta chain git will write, so anything longer has to be a loop
+const MAX_DELTA_CHAIN: usize = 4095;
+// packs on disk are self-contained so bases found elsewhere are rare and never chain far, and
+// as each one is read through the store this keeps the stack from growing without bound
+const MAX_EXTERNAL_BASES: usize = 16;
+// these mirror git's defaults for `pack.window` and `pack.depth`
+const DELTA_WINDOW: usize = 10;
+const DELTA_DEPTH: usize = 50;
+const DELTA_BLOCK_SIZE: usize = 16;
+const DELTA_MAX_INSERT: usize = 0x7f;
+const DELTA_MAX_COPY: usize = 0xff_ffff;
+
+const KIND_COMMIT: u8 = 1;
+const KIND_TREE: u8 = 2;
+const KIND_BLOB: u8 = 3;
+const KIND_TAG: u8 = 4;
+const KIND_OFS_DELTA: u8 = 6;
+const KIND_REF_DELTA: u8 = 7;
+
+/// Looks up the kind and content of an object by hash, given how many deltas deep it's being read.
+pub type ResolveBase<'a> = dyn Fn(&ObjectId, usize) -> Result<(String, Vec<u8>)> + 'a;
+
+/// Reads the content of an object being packed, which only happens as it's written.
+pub type ReadContent<'a> = dyn Fn(&ObjectId) -> Result<Vec<u8>> + 'a;
+
+/// A packfile along with its version 2 index.
+#[derive(Debug)]
+pub struct Pack {
+    pub path: PathBuf,
+    index: Index,
+}
+
+/// An object to be written into a new pack.
+///
+/// `name` is the path the object was reached through and is only used to group similar objects
+/// together when searching for delta bases. The content is read as the entry is written so only
+/// the entries in the delta window are ever held at once.
+pub struct Entry {
+    pub hash: ObjectId,
+    pub kind: String,
+    pub size: usize,
+    pub name: String,
+}
+
+impl Pack {
+    /// Opens the pack that sits alongside the given `.idx` file.
+    ///
+    /// Version 2 indexes don't record their hash algorithm so it has to come from the repository.
+    pub fn open(index_path: &Path, format: ObjectFormat) -> Result<Self> {
+        let path = index_path.with_extension("pack");
+        let mut header = [0; 8];
+        File::open(&path)?.read_exact(&mut header)?;
+        if &header[..4] != PACK_SIGNATURE {
+            return Err(corrupt(&path, "invalid signature"));
+        }
+        let version = read_u32(&header, 4)?;
+        if version != 2 && version != 3 {
+            return Err(corrupt(&path, &format!("unsupported version {}", version)));
+        }
+
+        let index = Index::deserialize(&fs::read(index_path)?, format)
+            .map_err(|_| corrupt(index_path, "index couldn't be parsed"))?;
+        Ok(Self { path, index })
+    }
+
+    /// Opens every pack in a repository's `objects/pack` directory.
+    pub fn open_all(dir: &Path, format: ObjectFormat) -> Result<Vec<Self>> {
+        if !dir.is_dir() {
+            return Ok(vec![]);
+        }
+        let mut packs = vec![];
+        for entry in dir.read_dir()? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "idx") {
+                packs.push(Pack::open(&path, format)?);
+            }
+        }
+        Ok(packs)
+    }
+
+    /// Writes the entries into a new pack and index in the given directory.
+    pub fn write(
+        dir: &Path,
+        entries: Vec<Entry>,
+        format: ObjectFormat,
+        read: &ReadContent,
+    ) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        // the pack is named after its checksum so it's streamed aside until that's known
+        let (mut temp, file) = TempPack::create(dir)?;
+        let (file, checksum, index) = serialize(entries, format, read, BufWriter::new(file))?;
+        file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+
+        let path = dir.join(format!("pack-{}.pack", hex::encode(&checksum)));
+        // the index is moved in last so readers never find an index without its pack
+        let pack_lock = LockFile::acquire(path.clone())?;
+        let mut index_file = LockFile::acquire(path.with_extension("idx"))?;
+        index_file.write(&index)?;
+        temp.persist(&path)?;
+        drop(pack_lock);
+        index_file.commit()?;
+        Pack::open(&path.with_extension("idx"), format)
+    }
+
+    pub fn remove(&self) -> Result<()> {
+        fs::remove_file(self.path.with_extension("idx"))?;
+        fs::remove_file(&self.path)?;
+        Ok(())
+    }
+
+    pub fn find(&self, hash: &ObjectId) -> Option<u64> {
+        self.index.find(hash)
+    }
+
+    pub fn hashes(&self) -> impl Iterator<Item = &ObjectId> + '_ {
+        self.index.hashes.iter()
+    }
+
+    /// Lists the ids in the pack that start with the given lower case hex prefix.
+    pub fn find_prefix(&self, prefix: &str) -> Vec<ObjectId> {
+        self.index.find_prefix(prefix)
+    }
+
+    /// Reads the object at the given offset, reconstructing it if it was stored as a delta.
+    ///
+    /// `resolve` is used to look up the bases of REF_DELTA entries that live outside the pack, and
+    /// `depth` is how many of those lookups it took to get here.
+    pub fn read(
+        &self,
+        offset: u64,
+        depth: usize,
+        resolve: &ResolveBase,
+    ) -> Result<(String, Vec<u8>)> {
+        if depth > MAX_EXTERNAL_BASES {
+            return Err(corrupt(&self.path, "a delta chain too long to follow"));
+        }
+        // deltas are collected on the way down to the base so long chains don't need deep stacks
+        let mut deltas = vec![];
+        let mut offset = offset;
+        let (kind, mut content) = loop {
+            if deltas.len() > MAX_DELTA_CHAIN {
+                return Err(corrupt(&self.path, "a delta chain too long to follow"));
+            }
+            let mut file = File::open(&self.path)?;
+            file.seek(SeekFrom::Start(offset))?;
+            let mut reader = BufReader::new(file);
+
+            let (kind, size) = read_entry_header(&mut reader)?;
+            match kind {
+                KIND_OFS_DELTA => {
+                    let distance = read_base_distance(&mut reader)?;
+                    deltas.push(inflate(&mut reader, size)?);
+                    offset = offset
+                        .checked_sub(distance)
+                        .filter(|_| distance != 0)
+                        .ok_or_else(|| corrupt(&self.path, "delta base is out of bounds"))?;
+                }
+                KIND_REF_DELTA => {
+                    let mut base_hash = vec![0; self.index.format.size()];
+                    reader.read_exact(&mut base_hash)?;
+                    deltas.push(inflate(&mut reader, size)?);
+                    let base_hash = ObjectId::from_bytes(&base_hash)?;
+                    match self.index.find(&base_hash) {
+                        Some(base_offset) => offset = base_offset,
+                        None => break resolve(&base_hash, depth + 1)?,
+                    }
+                }
+                other => break (kind_name(other)?.to_string(), inflate(&mut reader, size)?),
+            }
+        };
+        for delta in deltas.iter().rev() {
+            content = apply_delta(&content, delta)?;
+        }
+        Ok((kind, content))
+    }
+
+    /// Opens a stream over the content of the object at the given offset without inflating it all.
+    ///
+    /// Deltas can only be rebuilt in memory so `None` is returned for them.
+    pub fn stream(&self, offset: u64) -> Result<Option<(String, usize, impl Read)>> {
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut reader = BufReader::new(file);
+
+        let (kind, size) = read_entry_header(&mut reader)?;
+        match kind {
+            KIND_OFS_DELTA | KIND_REF_DELTA => Ok(None),
+            other => Ok(Some((
+                kind_name(other)?.to_string(),
+                size,
+                ZlibDecoder::new(reader),
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Index {
+    format: ObjectFormat,
+    hashes: Vec<ObjectId>,
+    crcs: Vec<u32>,
+    offsets: Vec<u64>,
+}
+
+impl Index {
+    fn find(&self, hash: &ObjectId) -> Option<u64> {
+        self.hashes
+            .binary_search(hash)
+            .ok()
+            .map(|i| self.offsets[i])
+    }
+
+    fn find_prefix(&self, prefix: &str) -> Vec<ObjectId> {
+        // hex sorts the same way as the bytes it encodes so the sorted ids can be searched
+        let start = self
+            .hashes
+            .partition_point(|h| h.to_hex().as_str() < prefix);
+        self.hashes[start..]
+            .iter()
+            .take_while(|h| h.to_hex().starts_with(prefix))
+            .cloned()
+            .collect()
+    }
+
+    fn deserialize(bytes: &[u8], format: ObjectFormat) -> Result<Self> {
+        let hash_size = format.size();
+        if bytes.get(..4) != Some(&IDX_SIGNATURE[..]) {
+            return Err(Error::Corrupt(
+                "Pack index had an invalid signature.".to_string(),
+            ));
+        }
+        let version = read_u32(bytes, 4)?;
+        if version != IDX_VERSION {
+            return Err(Error::Corrupt(format!(
+                "Pack index version {} is not supported.",
+                version
+            )));
+        }
+
+        let fanout_start = 8;
+        let count = read_u32(bytes, fanout_start + (FANOUT_ENTRIES - 1) * 4)? as usize;
+        let hashes_start = fanout_start + FANOUT_ENTRIES * 4;
+        let crcs_start = hashes_start + count * hash_size;
+        let offsets_start = crcs_start + count * 4;
+        let large_offsets_start = offsets_start + count * 4;
+
+        let hashes = (0..count)
+            .map(|i| {
+                let start = hashes_start + i * hash_size;
+                bytes
+                    .get(start..start + hash_size)
+                    .ok_or_else(truncated)
+                    .and_then(ObjectId::from_bytes)
+            })
+            .collect::<Result<_>>()?;
+        let crcs = (0..count)
+            .map(|i| read_u32(bytes, crcs_start + i * 4))
+            .collect::<Result<_>>()?;
+        let offsets = (0..count)
+            .map(|i| {
+                let offset = read_u32(bytes, offsets_start + i * 4)?;
+                if offset & LARGE_OFFSET_FLAG == 0 {
+                    Ok(offset as u64)
+                } else {
+                    let large = (offset & !LARGE_OFFSET_FLAG) as usize;
+                    read_u64(bytes, large_offsets_start + large * 8)
+                }
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            format,
+            hashes,
+            crcs,
+            offsets,
+        })
+    }
+
+    fn serialize(&self, pack_checksum: &[u8]) -> Vec<u8> {
+        let mut index: Vec<u8> = [&IDX_SIGNATURE[..], &IDX_VERSION.to_be_bytes()].concat();
+        for i in 0..FANOUT_ENTRIES {
+            let count = self
+                .hashes
+                .iter()
+                .take_while(|h| h.as_bytes()[0] as usize <= i)
+                .count();
+            index.extend_from_slice(&(count as u32).to_be_bytes());
+        }
+        self.hashes
+            .iter()
+            .for_each(|h| index.extend_from_slice(h.as_bytes()));
+        self.crcs
+            .iter()
+            .for_each(|c| index.extend_from_slice(&c.to_be_bytes()));
+
+        let mut large_offsets = vec![];
+        for &offset in &self.offsets {
+            let small = if offset < LARGE_OFFSET_FLAG as u64 {
+                offset as u32
+            } else {
+                large_offsets.extend_from_slice(&offset.to_be_bytes());
+                (large_offsets.len() / 8 - 1) as u32 | LARGE_OFFSET_FLAG
+            };
+            index.extend_from_slice(&small.to_be_bytes());
+        }
+        index.extend(large_offsets);
+        index.extend_from_slice(pack_checksum);
+        index.extend(self.format.digest(&index));
+        index
+    }
+}
+
+fn deflate(bytes: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+    encoder.write_all(bytes).unwrap();
+    encoder.finish().unwrap()
+}
+
+// A pack being written that's removed if it's abandoned before being moved into place.
+struct TempPack {
+    path: Option<PathBuf>,
+}
+
+impl TempPack {
+    fn create(dir: &Path) -> Result<(Self, File)> {
+        let path = dir.join(format!("tmp_pack_{}", std::process::id()));
+        let file = File::create(&path)?;
+        Ok((Self { path: Some(path) }, file))
+    }
+
+    fn persist(&mut self, to: &Path) -> Result<()> {
+        fs::rename(self.path.as_ref().unwrap(), to)?;
+        self.path = None;
+        Ok(())
+    }
+}
+
+impl Drop for TempPack {
+    fn drop(&mut self) {
+        if let Some(path) = self.path.take() {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+// Hashes and counts everything written to the pack, which gives each entry its offset and the
+// pack its checksum without having to hold onto it.
+struct PackOutput<W> {
+    inner: W,
+    hasher: Box<dyn Digest>,
+    offset: u64,
+}
+
+impl<W: Write> PackOutput<W> {
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        self.inner.write_all(bytes)?;
+        self.hasher.input(bytes);
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+// An entry that's recently been written and can still be used as a delta base.
+struct Candidate {
+    kind: String,
+    content: Vec<u8>,
+    offset: u64,
+    depth: usize,
+}
+
+// Writes a pack to `out`, returning it along with the pack's checksum and serialized index.
+fn serialize<W: Write>(
+    mut entries: Vec<Entry>,
+    format: ObjectFormat,
+    read: &ReadContent,
+    out: W,
+) -> Result<(W, Vec<u8>, Vec<u8>)> {
+    let mut kinds = HashMap::new();
+    for entry in &entries {
+        if entry.hash.format() != format {
+            return Err(Error::InvalidArgument(format!(
+                "{} is not a {} object id.",
+                entry.hash,
+                format.name()
+            )));
+        }
+        kinds.insert(entry.kind.clone(), kind_code(&entry.kind)?);
+    }
+    entries.sort_by(|a, b| {
+        let key = |e: &Entry| (kinds[&e.kind], e.name.clone(), Reverse(e.size));
+        key(a).cmp(&key(b))
+    });
+
+    let mut out = PackOutput {
+        inner: out,
+        hasher: format.hasher(),
+        offset: 0,
+    };
+    out.write(PACK_SIGNATURE)?;
+    out.write(&PACK_VERSION.to_be_bytes())?;
+    out.write(&(entries.len() as u32).to_be_bytes())?;
+    let mut window: VecDeque<Candidate> = VecDeque::with_capacity(DELTA_WINDOW + 1);
+    let mut records: Vec<(ObjectId, u32, u64)> = Vec::with_capacity(entries.len());
+
+    for entry in entries {
+        let content = read(&entry.hash)?;
+        let offset = out.offset;
+        let (record, depth) = match find_delta_base(&window, &entry.kind, &content) {
+            Some((base, delta)) => (
+                [
+                    serialize_entry_header(KIND_OFS_DELTA, delta.len()),
+                    serialize_base_distance(offset - base.offset),
+                    deflate(&delta),
+                ]
+                .concat(),
+                base.depth + 1,
+            ),
+            None => (
+                [
+                    serialize_entry_header(kinds[&entry.kind], content.len()),
+                    deflate(&content),
+                ]
+                .concat(),
+                0,
+            ),
+        };
+
+        let mut crc = Crc::new();
+        crc.update(&record);
+        records.push((entry.hash, crc.sum(), offset));
+        out.write(&record)?;
+        window.push_back(Candidate {
+            kind: entry.kind,
+            content,
+            offset,
+            depth,
+        });
+        if window.len() > DELTA_WINDOW {
+            window.pop_front();
+        }
+    }
+
+    let mut checksum = vec![0; format.size()];
+    out.hasher.result(&mut checksum);
+    out.inner.write_all(&checksum)?;
+
+    records.sort();
+    let index = Index {
+        format,
+        hashes: records.iter().map(|r| r.0).collect(),
+        crcs: records.iter().map(|r| r.1).collect(),
+        offsets: records.iter().map(|r| r.2).collect(),
+    };
+    let index = index.serialize(&checksum);
+    Ok((out.inner, checksum, index))
+}
+
+fn find_delta_base<'a>(
+    window: &'a VecDeque<Candidate>,
+    kind: &str,
+    content: &[u8],
+) -> Option<(&'a Candidate, Vec<u8>)> {
+    window
+        .iter()
+        .filter(|base| base.kind == kind && base.depth < DELTA_DEPTH)
+        .map(|base| (base, create_delta(&base.content, content)))
+        .filter(|(_, delta)| delta.len() < content.len() / 2)
+        .min_by_key(|(_, delta)| delta.len())
+}
+
+fn corrupt(path: &Path, reason: &str) -> Error {
+    Error::Corrupt(format!("Pack {} has {}.", path.display(), reason))
+}
+
+fn truncated() -> Error {
+    Error::Corrupt("Pack data was truncated.".to_string())
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> Result<u32> {
+    bytes
+        .get(at..at + 4)
+        .map(|b| u32::from_be_bytes(b.try_into().unwrap()))
+        .ok_or_else(truncated)
+}
+
+fn read_u64(bytes: &[u8], at: usize) -> Result<u64> {
+    bytes
+        .get(at..at + 8)
+        .map(|b| u64::from_be_bytes(b.try_into().unwrap()))
+        .ok_or_else(truncated)
+}
+
+fn overflowed() -> Error {
+    Error::Corrupt("Pack varint was too large.".to_string())
+}
+
+fn push_varint_bits(value: usize, byte: u8, shift: u32) -> Result<usize> {
+    let bits = (byte & 0x7f) as usize;
+    if shift >= usize::BITS || (bits << shift) >> shift != bits {
+        return Err(overflowed());
+    }
+    Ok(value | bits << shift)
+}
+
+fn read_byte(reader: &mut impl Read) -> Result<u8> {
+    let mut byte = [0];
+    reader.read_exact(&mut byte).map_err(|_| truncated())?;
+    Ok(byte[0])
+}
+
+fn kind_code(name: &str) -> Result<u8> {
+    match name {
+        NAME_COMMIT => Ok(KIND_COMMIT),
+        NAME_TREE => Ok(KIND_TREE),
+        NAME_BLOB => Ok(KIND_BLOB),
+        NAME_TAG => Ok(KIND_TAG),
+        other => Err(Error::InvalidArgument(format!(
+            "Object type {} is not valid.",
+            other
+        ))),
+    }
+}
+
+fn kind_name(kind: u8) -> Result<&'static str> {
+    match kind {
+        KIND_COMMIT => Ok(NAME_COMMIT),
+        KIND_TREE => Ok(NAME_TREE),
+        KIND_BLOB => Ok(NAME_BLOB),
+        KIND_TAG => Ok(NAME_TAG),
+        other => Err(Error::Corrupt(format!(
+            "Pack entry type {} is not valid.",
+            other
+        ))),
+    }
+}
+
+fn read_entry_header(reader: &mut impl Read) -> Result<(u8, usize)> {
+    let mut byte = read_byte(reader)?;
+    let kind = (byte >> 4) & 0x07;
+    let mut size = (byte & 0x0f) as usize;
+    let mut shift = 4;
+    while byte & 0x80 != 0 {
+        byte = read_byte(reader)?;
+        size = push_varint_bits(size, byte, shift)?;
+        shift += 7;
+    }
+    Ok((kind, size))
+}
+
+fn serialize_entry_header(kind: u8, size: usize) -> Vec<u8> {
+    let mut byte = (kind << 4) | (size & 0x0f) as u8;
+    let mut size = size >> 4;
+    let mut bytes = vec![];
+    while size != 0 {
+        bytes.push(byte | 0x80);
+        byte = (size & 0x7f) as u8;
+        size >>= 7;
+    }
+    bytes.push(byte);
+    bytes
+}
+
+fn read_base_distance(reader: &mut impl Read) -> Result<u64> {
+    let mut byte = read_byte(reader)?;
+    let mut distance = (byte & 0x7f) as u64;
+    while byte & 0x80 != 0 {
+        byte = read_byte(reader)?;
+        if distance >= u64::MAX >> 7 {
+            return Err(overflowed());
+        }
+        distance = ((distance + 1) << 7) | (byte & 0x7f) as u64;
+    }
+    Ok(distance)
+}
+
+fn serialize_base_distance(distance: u64) -> Vec<u8> {
+    let mut bytes = vec![(distance & 0x7f) as u8];
+    let mut distance = distance >> 7;
+    while distance != 0 {
+        distance -= 1;
+        bytes.push(0x80 | (distance & 0x7f) as u8);
+        distance >>= 7;
+    }
+    bytes.reverse();
+    bytes
+}
+
+fn inflate(reader: &mut impl Read, size: usize) -> Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(size.min(MAX_PREALLOCATION));
+    ZlibDecoder::new(reader)
+        .take(size as u64 + 1)
+        .read_to_end(&mut bytes)
+        .map_err(|_| Error::Corrupt("Unable to inflate pack entry.".to_string()))?;
+    if size != bytes.len() {
+        return Err(Error::Corrupt(
+            "Pack entry length was not equal to the encoded size.".to_string(),
+        ));
+    }
+    Ok(bytes)
+}
+
+fn read_delta_size(delta: &[u8]) -> Result<(usize, &[u8])> {
+    let mut size = 0;
+    let mut shift = 0;
+    for (i, &byte) in delta.iter().enumerate() {
+        size = push_varint_bits(size, byte, shift)?;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok((size, &delta[i + 1..]));
+        }
+    }
+    Err(truncated())
+}
+
+fn serialize_delta_size(size: usize) -> Vec<u8> {
+    let mut bytes = vec![];
+    let mut size = size;
+    while size >= 0x80 {
+        bytes.push(0x80 | (size & 0x7f) as u8);
+        size >>= 7;
+    }
+    bytes.push(size as u8);
+    bytes
+}
+
+fn push_insert(delta: &mut Vec<u8>, insert: &mut Vec<u8>) {
+    if !insert.is_empty() {
+        delta.push(insert.len() as u8);
+        delta.append(insert);
+    }
+}
+
+fn push_copy(delta: &mut Vec<u8>, offset: usize, size: usize) {
+    let mut instruction = 0x80;
+    let mut arguments = vec![];
+    let fields = (0..4)
+        .map(|i| (offset >> (8 * i)) as u8)
+        .chain((0..3).map(|i| (size >> (8 * i)) as u8));
+    for (i, byte) in fields.enumerate() {
+        if byte != 0 {
+            instruction |= 1 << i;
+            arguments.push(byte);
+        }
+    }
+    delta.push(instruction);
+    delta.append(&mut arguments);
+}
+
+/// Creates a git delta that rebuilds the target from the base.
+///
+/// The base is indexed in fixed size blocks and any block found in the target is extended as far as
+/// it matches and emitted as a copy, with everything else emitted as inserts.
+pub fn create_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut blocks: HashMap<&[u8], usize> = HashMap::new();
+    for (i, block) in base.chunks_exact(DELTA_BLOCK_SIZE).enumerate() {
+        blocks.entry(block).or_insert(i * DELTA_BLOCK_SIZE);
+    }
+
+    let mut delta = [
+        serialize_delta_size(base.len()),
+        serialize_delta_size(target.len()),
+    ]
+    .concat();
+    let mut insert = vec![];
+    let mut position = 0;
+    while position < target.len() {
+        let copy = target
+            .get(position..position + DELTA_BLOCK_SIZE)
+            .and_then(|block| blocks.get(block))
+            .map(|&start| {
+                let size = base[start..]
+                    .iter()
+                    .zip(&target[position..])
+                    .take_while(|(a, b)| a == b)
+                    .take(DELTA_MAX_COPY)
+                    .count();
+                (start, size)
+            });
+        match copy {
+            Some((start, size)) => {
+                push_insert(&mut delta, &mut insert);
+                push_copy(&mut delta, start, size);
+                position += size;
+            }
+            None => {
+                insert.push(target[position]);
+                if insert.len() == DELTA_MAX_INSERT {
+                    push_insert(&mut delta, &mut insert);
+                }
+                position += 1;
+            }
+        }
+    }
+    push_insert(&mut delta, &mut insert);
+    delta
+}
+
+/// Rebuilds an object from its base and a git delta of copy and insert instructions.
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>> {
+    let (base_size, delta) = read_delta_size(delta)?;
+    if base_size != base.len() {
+        return Err(Error::Corrupt(
+            "Delta base length was not equal to the encoded size.".to_string(),
+        ));
+    }
+    let (target_size, mut delta) = read_delta_size(delta)?;
+    let mut target = Vec::with_capacity(target_size.min(MAX_PREALLOCATION));
+
+    while let Some((&instruction, rest)) = delta.split_first() {
+        delta = rest;
+        if instruction & 0x80 != 0 {
+            let mut offset = 0;
+            let mut size = 0;
+            for i in 0..7 {
+                if instruction & (1 << i) != 0 {
+                    let (&byte, rest) = delta.split_first().ok_or_else(truncated)?;
+                    delta = rest;
+                    if i < 4 {
+                        offset |= (byte as usize) << (8 * i);
+                    } else {
+                        size |= (byte as usize) << (8 * (i - 4));
+                    }
+                }
+            }
+            if size == 0 {
+                size = 0x10000;
+            }
+            let copy = base.get(offset..offset + size).ok_or_else(truncated)?;
+            target.extend_from_slice(copy);
+        } else if instruction != 0 {
+            if delta.len() < instruction as usize {
+                return Err(truncated());
+            }
+            let (insert, rest) = delta.split_at(instruction as usize);
+            target.extend_from_slice(insert);
+            delta = rest;
+        } else {
+            return Err(Error::Corrupt(
+                "Delta contained a reserved instruction.".to_string(),
+            ));
+        }
+    }
+
+    if target_size != target.len() {
+        return Err(Error::Corrupt(
+            "Delta target length was not equal to the encoded size.".to_string(),
+        ));
+    }
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::error::Error;
+    use crate::object::id::{ObjectFormat, ObjectId};
+    use crate::pack::{
+        apply_delta, create_delta, deflate, read_base_distance, read_entry_header, serialize,
+        serialize_base_distance, serialize_entry_header, Entry, Index, Pack, IDX_SIGNATURE,
+        KIND_BLOB, KIND_OFS_DELTA, KIND_REF_DELTA, PACK_SIGNATURE,
+    };
+
+    #[test]
+    fn reads_single_byte_entry_header() {
+        let raw: &[u8] = &[0b0011_0101];
+        let (kind, size) = read_entry_header(&mut &raw[..]).unwrap();
+        assert_eq!(kind, KIND_BLOB);
+        assert_eq!(size, 5);
+    }
+
+    #[test]
+    fn reads_multi_byte_entry_header() {
+        let raw: &[u8] = &[0b1110_1010, 0b0000_0011];
+        let (kind, size) = read_entry_header(&mut &raw[..]).unwrap();
+        assert_eq!(kind, KIND_OFS_DELTA);
+        assert_eq!(size, 10 + (3 << 4));
+    }
+
+    #[test]
+    fn reads_base_distance() {
+        let single: &[u8] = &[0x05];
+        assert_eq!(read_base_distance(&mut &single[..]).unwrap(), 5);
+        let multiple: &[u8] = &[0x81, 0x00];
+        assert_eq!(
+            read_base_distance(&mut &multiple[..]).unwrap(),
+            (1 + 1) << 7
+        );
+    }
+
+    #[test]
+    fn fails_when_varints_overflow() {
+        let raw = [0xff; 16];
+        assert!(matches!(
+            read_entry_header(&mut &raw[..]),
+            Err(Error::Corrupt(_))
+        ));
+        assert!(matches!(
+            read_base_distance(&mut &raw[..]),
+            Err(Error::Corrupt(_))
+        ));
+        assert!(matches!(
+            apply_delta(b"", &[&raw[..], &[0x00, 0x00]].concat()),
+            Err(Error::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn fails_without_allocating_for_oversized_deltas() {
+        let delta = [
+            0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f, 0x01, b'a',
+        ];
+        assert!(matches!(apply_delta(b"", &delta), Err(Error::Corrupt(_))));
+    }
+
+    #[test]
+    fn applies_delta() {
+        let base = b"the quick brown fox";
+        // copies "the quick ", inserts "red ", copies "fox" and finally inserts "!"
+        let delta: Vec<u8> = [
+            &[19, 18][..],
+            &[0b1001_0000, 10],
+            &[4],
+            b"red ",
+            &[0b1001_0001, 16, 3],
+            &[1],
+            b"!",
+        ]
+        .concat();
+        assert_eq!(
+            apply_delta(base, &delta).unwrap(),
+            b"the quick red fox!".to_vec()
+        );
+    }
+
+    #[test]
+    fn fails_when_delta_base_size_is_incorrect() {
+        let result = apply_delta(b"abc", &[4, 1, 1, b"x"[0]]);
+        assert!(matches!(result, Err(Error::Corrupt(_))));
+    }
+
+    #[test]
+    fn fails_when_delta_copies_out_of_bounds() {
+        let result = apply_delta(b"abc", &[3, 4, 0b1001_0000, 4]);
+        assert!(matches!(result, Err(Error::Corrupt(_))));
+    }
+
+    #[test]
+    fn finds_offsets_in_index() {
+        let first = [0x00; 20];
+        let second = [0xab; 20];
+        let fanout: Vec<u32> = (0..256).map(|i| if i < 0xab { 1 } else { 2 }).collect();
+        let raw: Vec<u8> = [
+            &IDX_SIGNATURE[..],
+            &2u32.to_be_bytes(),
+            &fanout
+                .iter()
+                .flat_map(|f| f.to_be_bytes().to_vec())
+                .collect::<Vec<u8>>(),
+            &first,
+            &second,
+            &[0; 8],
+            &12u32.to_be_bytes(),
+            &0x8000_0000u32.to_be_bytes(),
+            &0x1_0000_0000u64.to_be_bytes(),
+        ]
+        .concat();
+
+        let id = |bytes: [u8; 20]| ObjectId::from_bytes(&bytes).unwrap();
+        let index = Index::deserialize(&raw, ObjectFormat::Sha1).unwrap();
+        assert_eq!(index.find(&id(first)), Some(12));
+        assert_eq!(index.find(&id(second)), Some(0x1_0000_0000));
+        assert_eq!(index.find(&id([0x01; 20])), None);
+        assert_eq!(index.find_prefix("abab"), vec![id(second)]);
+        assert_eq!(index.find_prefix("0"), vec![id(first)]);
+        assert_eq!(index.find_prefix(""), vec![id(first), id(second)]);
+        assert!(index.find_prefix("ac").is_empty());
+    }
+
+    #[test]
+    fn round_trips_entry_headers() {
+        for &size in &[0, 15, 16, 1000, 1 << 20] {
+            let raw = serialize_entry_header(KIND_BLOB, size);
+            assert_eq!(
+                read_entry_header(&mut raw.as_slice()).unwrap(),
+                (KIND_BLOB, size)
+            );
+        }
+    }
+
+    #[test]
+    fn round_trips_base_distances() {
+        for &distance in &[0, 127, 128, 16511, 16512, 1 << 40] {
+            let raw = serialize_base_distance(distance);
+            assert_eq!(read_base_distance(&mut raw.as_slice()).unwrap(), distance);
+        }
+    }
+
+    #[test]
+    fn round_trips_deltas() {
+        let base: Vec<u8> = (0..2000).map(|i| (i % 251) as u8).collect();
+        let target: Vec<u8> = [&base[100..900], b"something new", &base[1000..]].concat();
+        let delta = create_delta(&base, &target);
+        assert!(delta.len() < target.len() / 10);
+        assert_eq!(apply_delta(&base, &delta).unwrap(), target);
+    }
+
+    #[test]
+    fn round_trips_deltas_without_shared_content() {
+        let delta = create_delta(b"abc", b"something completely different");
+        assert_eq!(
+            apply_delta(b"abc", &delta).unwrap(),
+            b"something completely different".to_vec()
+        );
+    }
+
+    #[test]
+    fn serializes_index_for_every_entry() {
+        let content: Vec<u8> = (0..4000).map(|i| (i % 253) as u8).collect();
+        let appended = [&content[..], b"appended"].concat();
+        let first: ObjectId = "ab".repeat(20).parse().unwrap();
+        let second: ObjectId = "01".repeat(20).parse().unwrap();
+        let entries = vec![
+            Entry {
+                hash: first,
+                kind: "blob".to_string(),
+                size: content.len(),
+                name: "file".to_string(),
+            },
+            Entry {
+                hash: second,
+                kind: "blob".to_string(),
+                size: appended.len(),
+                name: "file".to_string(),
+            },
+        ];
+        let read = |hash: &ObjectId| {
+            Ok(if *hash == first {
+                content.clone()
+            } else {
+                appended.clone()
+            })
+        };
+
+        let (pack, checksum, raw_index) =
+            serialize(entries, ObjectFormat::Sha1, &read, vec![]).unwrap();
+        let index = Index::deserialize(&raw_index, ObjectFormat::Sha1).unwrap();
+        let delta_offset = index.find(&first).unwrap() as usize;
+        let base_offset = index.find(&second).unwrap() as usize;
+        let (kind, _) = read_entry_header(&mut &pack[delta_offset..]).unwrap();
+        assert_eq!(kind, KIND_OFS_DELTA);
+        let (kind, size) = read_entry_header(&mut &pack[base_offset..]).unwrap();
+        assert_eq!(kind, KIND_BLOB);
+        assert_eq!(size, content.len() + 8);
+        assert_eq!(&pack[pack.len() - 20..], &checksum[..]);
+        assert_eq!(
+            ObjectFormat::Sha1.digest(&pack[..pack.len() - 20]),
+            checksum
+        );
+        assert_eq!(
+            &raw_index[raw_index.len() - 40..raw_index.len() - 20],
+            &checksum[..]
+        );
+    }
+
+    #[test]
+    fn fails_to_deserialize_truncated_index() {
+        let raw: Vec<u8> = [&IDX_SIGNATURE[..], &2u32.to_be_bytes(), &[0; 16]].concat();
+        assert!(matches!(
+            Index::deserialize(&raw, ObjectFormat::Sha1),
+            Err(Error::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn writes_packs_without_leaving_lock_files() {
+        let dir = std::env::temp_dir().join(format!("gitrs_writes_packs_{}", std::process::id()));
+        let entries = || {
+            vec![Entry {
+                hash: "ab".repeat(20).parse().unwrap(),
+                kind: "blob".to_string(),
+                size: 13,
+                name: "file".to_string(),
+            }]
+        };
+        let read = |_: &ObjectId| Ok(b"test content\n".to_vec());
+        let pack = Pack::write(&dir, entries(), ObjectFormat::Sha1, &read).unwrap();
+        let mut names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![pack.path.with_extension("idx"), pack.path.clone()]
+        );
+
+        let mut lock = pack.path.into_os_string();
+        lock.push(".lock");
+        fs::write(&lock, "").unwrap();
+        assert!(matches!(
+            Pack::write(&dir, entries(), ObjectFormat::Sha1, &read),
+            Err(Error::Refused(_))
+        ));
+        let failing = |_: &ObjectId| Err(Error::NotFound("object".to_string()));
+        assert!(matches!(
+            Pack::write(&dir, entries(), ObjectFormat::Sha1, &failing),
+            Err(Error::NotFound(_))
+        ));
+        // abandoned packs don't leave their temporary files behind
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+        assert!(Pack::open_all(&dir, ObjectFormat::Sha1).is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn write_delta_pack(name: &str, kind: u8, base: &[u8], hashes: Vec<ObjectId>) -> Pack {
+        let path = std::env::temp_dir().join(format!("gitrs_{}_{}.pack", name, std::process::id()));
+        let delta = create_delta(b"base", b"target");
+        let raw = [
+            PACK_SIGNATURE,
+            &2u32.to_be_bytes(),
+            &1u32.to_be_bytes(),
+            &serialize_entry_header(kind, delta.len()),
+            base,
+            &deflate(&delta),
+        ]
+        .concat();
+        fs::write(&path, raw).unwrap();
+        Pack {
+            path,
+            index: Index {
+                format: ObjectFormat::Sha1,
+                crcs: vec![0; hashes.len()],
+                offsets: vec![12; hashes.len()],
+                hashes,
+            },
+        }
+    }
+
+    #[test]
+    fn fails_on_deltas_based_on_themselves() {
+        let pack = write_delta_pack("self_ofs_delta", KIND_OFS_DELTA, &[0x00], vec![]);
+        assert!(matches!(
+            pack.read(12, 0, &|_, _| unreachable!()),
+            Err(Error::Corrupt(_))
+        ));
+        fs::remove_file(&pack.path).unwrap();
+
+        fn read_self(pack: &Pack, depth: usize) -> crate::error::Result<(String, Vec<u8>)> {
+            pack.read(12, depth, &|_, depth| read_self(pack, depth))
+        }
+        let hash: ObjectId = "ab".repeat(20).parse().unwrap();
+        let pack = write_delta_pack("self_ref_delta", KIND_REF_DELTA, &[0xab; 20], vec![hash]);
+        assert!(matches!(
+            pack.read(12, 0, &|_, _| unreachable!()),
+            Err(Error::Corrupt(_))
+        ));
+        fs::remove_file(&pack.path).unwrap();
+
+        let pack = write_delta_pack("external_ref_delta", KIND_REF_DELTA, &[0xab; 20], vec![]);
+        assert!(matches!(read_self(&pack, 0), Err(Error::Corrupt(_))));
+        fs::remove_file(&pack.path).unwrap();
+    }
+}
@@ -1,44 +1,82 @@
 use regex::Regex;
 
-#[derive(Debug, PartialEq)]
+use crate::error::{Error, Result};
+use crate::object::id::ObjectId;
+
+pub mod log;
+pub mod packed;
+
+pub const BRANCH_PREFIX: &str = "refs/heads/";
+pub const REMOTE_PREFIX: &str = "refs/remotes/";
+pub const TAG_PREFIX: &str = "refs/tags/";
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum Reference {
     Head,
     Ref(String),
-    Commit(String),
+    Commit(ObjectId),
 }
 
 impl Reference {
-    pub fn serialize(&self) -> String {
+    pub fn serialize(&self) -> Result<String> {
         match self {
-            Reference::Head => panic!("Can't serialize a HEAD reference."),
-            Reference::Ref(body) => format!("ref: {}\n", body),
-            Reference::Commit(body) => format!("{}\n", body),
+            Reference::Head => Err(Error::InvalidRef(
+                "Can't serialize a HEAD reference.".to_string(),
+            )),
+            Reference::Ref(body) => Ok(format!("ref: {}\n", body)),
+            Reference::Commit(body) => Ok(format!("{}\n", body)),
         }
     }
 
-    pub fn from_name(name: &str) -> Self {
+    pub fn from_name(name: &str) -> Result<Self> {
         match name {
-            "HEAD" => Reference::Head,
-            head if head.starts_with("refs/heads/") => Reference::Ref(head.to_string()),
-            commit => Reference::Commit(commit.to_string()),
+            "HEAD" => Ok(Reference::Head),
+            path if path.starts_with("refs/") => Ok(Reference::Ref(path.to_string())),
+            commit => ObjectId::from_hex(commit)
+                .map(Reference::Commit)
+                .map_err(|_| Error::InvalidRef(format!("{} is not a valid reference.", name))),
+        }
+    }
+
+    /// Checks a full reference name like `refs/heads/topic` against git's rules for what a
+    /// reference can be called, see `git help check-ref-format`.
+    pub fn check_name(name: &str) -> Result<()> {
+        let forbidden = |c: char| c.is_ascii_control() || " ~^:?*[\\".contains(c);
+        let valid_component = |component: &str| {
+            !component.is_empty() && !component.starts_with('.') && !component.ends_with(".lock")
+        };
+        let valid = name != "@"
+            && !name.contains("..")
+            && !name.contains("@{")
+            && !name.ends_with('.')
+            && !name.contains(forbidden)
+            && name.split('/').all(valid_component);
+        if valid {
+            Ok(())
+        } else {
+            Err(Error::InvalidRef(format!(
+                "{} is not a valid reference name.",
+                name
+            )))
         }
     }
 
-    pub fn from_file(body: &str) -> Self {
+    pub fn from_file(body: &str) -> Result<Self> {
         let ref_regex = Regex::new(r"^ref: (.*)\n$").unwrap();
-        let commit_regex = Regex::new(r"^([a-z0-9]*)\n$").unwrap();
+        let commit_regex = Regex::new(r"^([0-9a-f]{40}|[0-9a-f]{64})\n$").unwrap();
 
-        let capture = ref_regex.captures(body).map(|c| c.get(1)).flatten();
+        let capture = ref_regex.captures(body).and_then(|c| c.get(1));
         match capture {
-            Some(n) => Reference::Ref(n.as_str().to_string()),
+            Some(n) => Ok(Reference::Ref(n.as_str().to_string())),
             None => {
                 // TODO[Rhys] look at some other way to parse these
                 let hash = commit_regex
                     .captures(body)
-                    .map(|c| c.get(1))
-                    .flatten()
-                    .expect("Reference couldn't be parsed.");
-                Reference::Commit(hash.as_str().to_string())
+                    .and_then(|c| c.get(1))
+                    .ok_or_else(|| {
+                        Error::InvalidRef(format!("Reference {:?} couldn't be parsed.", body))
+                    })?;
+                Ok(Reference::Commit(ObjectId::from_hex(hash.as_str())?))
             }
         }
     }
@@ -46,23 +84,92 @@ impl Reference {
 
 #[cfg(test)]
 mod tests {
+    use crate::error::Error;
     use crate::reference::Reference;
 
     #[test]
     fn round_trips_commits() {
         let data = "a94a8fe5ccb19ba61c4c0873d391e987982fbbd3\n";
-        let expected = Reference::Commit("a94a8fe5ccb19ba61c4c0873d391e987982fbbd3".to_string());
-        let deserialized = Reference::from_file(data);
+        let expected =
+            Reference::Commit("a94a8fe5ccb19ba61c4c0873d391e987982fbbd3".parse().unwrap());
+        let deserialized = Reference::from_file(data).unwrap();
         assert_eq!(deserialized, expected);
-        assert_eq!(deserialized.serialize(), data)
+        assert_eq!(deserialized.serialize().unwrap(), data)
+    }
+
+    #[test]
+    fn round_trips_sha256_commits() {
+        let data = format!("{}\n", "ab".repeat(32));
+        let deserialized = Reference::from_file(&data).unwrap();
+        assert_eq!(
+            deserialized,
+            Reference::Commit("ab".repeat(32).parse().unwrap())
+        );
+        assert_eq!(deserialized.serialize().unwrap(), data)
     }
 
     #[test]
     fn round_trips_refs() {
         let data = "ref: refs/heads/master\n";
         let expected = Reference::Ref("refs/heads/master".to_string());
-        let deserialized = Reference::from_file(data);
+        let deserialized = Reference::from_file(data).unwrap();
         assert_eq!(deserialized, expected);
-        assert_eq!(deserialized.serialize(), data)
+        assert_eq!(deserialized.serialize().unwrap(), data)
+    }
+
+    #[test]
+    fn fails_to_parse_garbage() {
+        let result = Reference::from_file("not a reference");
+        assert!(matches!(result, Err(Error::InvalidRef(_))));
+    }
+
+    #[test]
+    fn fails_to_parse_abbreviated_hashes() {
+        let result = Reference::from_file("a94a8fe\n");
+        assert!(matches!(result, Err(Error::InvalidRef(_))));
+        assert!(matches!(
+            Reference::from_name("a94a8fe"),
+            Err(Error::InvalidRef(_))
+        ));
+    }
+
+    #[test]
+    fn checks_names() {
+        for name in &[
+            "refs/heads/master",
+            "refs/heads/feature/x-1",
+            "HEAD",
+            "refs/tags/v1.0",
+        ] {
+            assert!(Reference::check_name(name).is_ok(), "{}", name);
+        }
+        for name in &[
+            "",
+            "@",
+            "refs/heads/",
+            "refs//heads",
+            "refs/heads/.hidden",
+            "refs/heads/x.lock",
+            "refs/heads/x..y",
+            "refs/heads/x.",
+            "refs/heads/a b",
+            "refs/heads/x~1",
+            "refs/heads/x@{1}",
+            "refs/heads/x\\y",
+        ] {
+            assert!(
+                matches!(Reference::check_name(name), Err(Error::InvalidRef(_))),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn fails_to_serialize_head() {
+        assert!(matches!(
+            Reference::Head.serialize(),
+            Err(Error::InvalidRef(_))
+        ));
     }
 }
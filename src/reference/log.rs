@@ -0,0 +1,128 @@
+use crate::error::{Error, Result};
+use crate::object::id::ObjectId;
+use crate::object::signature::Signature;
+
+/// One line of a reflog, recording a reference moving from one object to another, e.g.
+/// `<old> <new> Linus <x@y> 1112911993 -0700\tcheckout: moving from master to next`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LogEntry {
+    pub old: ObjectId,
+    pub new: ObjectId,
+    pub signature: Signature,
+    pub message: String,
+}
+
+impl LogEntry {
+    pub fn serialize(&self) -> String {
+        format!(
+            "{} {} {}\t{}\n",
+            self.old,
+            self.new,
+            self.signature.serialize(),
+            clean_message(&self.message)
+        )
+    }
+
+    pub fn deserialize(line: &str) -> Result<Self> {
+        let corrupt = || Error::Corrupt(format!("Reflog entry {:?} couldn't be parsed.", line));
+        // git leaves the tab off entirely when there's no message
+        let (header, message) = line.split_once('\t').unwrap_or((line, ""));
+        let mut fields = header.splitn(3, ' ');
+        let mut id = || {
+            fields
+                .next()
+                .and_then(|hex| ObjectId::from_hex(hex).ok())
+                .ok_or_else(corrupt)
+        };
+        let old = id()?;
+        let new = id()?;
+        let signature = fields
+            .next()
+            .ok_or_else(corrupt)
+            .and_then(Signature::deserialize)?;
+        Ok(Self {
+            old,
+            new,
+            signature,
+            message: message.to_string(),
+        })
+    }
+
+    /// Reads every entry of a reflog, oldest first.
+    pub fn deserialize_all(content: &str) -> Result<Vec<Self>> {
+        content
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(LogEntry::deserialize)
+            .collect()
+    }
+
+    /// The branch or commit that was moved away from if this entry records a checkout.
+    pub fn checkout_from(&self) -> Option<&str> {
+        let moved = self.message.strip_prefix("checkout: moving from ")?;
+        moved.rsplit_once(" to ").map(|(from, _)| from)
+    }
+}
+
+// Like git, whitespace and control characters are squeezed into single spaces so a message can't
+// spill onto a line of its own.
+fn clean_message(message: &str) -> String {
+    let spaced = message.replace(|c: char| c.is_control(), " ");
+    spaced.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::reference::log::LogEntry;
+
+    const ENTRY: &str = "0000000000000000000000000000000000000000 \
+                         a94a8fe5ccb19ba61c4c0873d391e987982fbbd3 \
+                         Rhys <rhys@example.com> 1600000000 +0100\t\
+                         checkout: moving from master to topic\n";
+
+    #[test]
+    fn round_trips_log_entries() {
+        let entry = LogEntry::deserialize(ENTRY.trim_end()).unwrap();
+        assert_eq!(entry.old.to_string(), "0".repeat(40));
+        assert_eq!(
+            entry.new.to_string(),
+            "a94a8fe5ccb19ba61c4c0873d391e987982fbbd3"
+        );
+        assert_eq!(entry.signature.email, "rhys@example.com");
+        assert_eq!(entry.message, "checkout: moving from master to topic");
+        assert_eq!(entry.serialize(), ENTRY)
+    }
+
+    #[test]
+    fn keeps_messages_on_one_line() {
+        let mut entry = LogEntry::deserialize(ENTRY.trim_end()).unwrap();
+        entry.message = "line1\nline2\r\x07 \tend ".to_string();
+        let serialized = entry.serialize();
+        assert_eq!(serialized.lines().count(), 1);
+        let parsed = LogEntry::deserialize(serialized.trim_end()).unwrap();
+        assert_eq!(parsed.message, "line1 line2 end");
+    }
+
+    #[test]
+    fn parses_entries_without_a_message() {
+        let line = ENTRY.split('\t').next().unwrap();
+        let entry = LogEntry::deserialize(line).unwrap();
+        assert_eq!(entry.message, "");
+        assert_eq!(entry.checkout_from(), None);
+    }
+
+    #[test]
+    fn parses_checkout_messages() {
+        let entry = LogEntry::deserialize(ENTRY.trim_end()).unwrap();
+        assert_eq!(entry.checkout_from(), Some("master"));
+    }
+
+    #[test]
+    fn fails_to_parse_garbage() {
+        assert!(matches!(
+            LogEntry::deserialize("not a reflog entry"),
+            Err(Error::Corrupt(_))
+        ));
+    }
+}
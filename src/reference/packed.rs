@@ -0,0 +1,118 @@
+use std::collections::BTreeMap;
+
+use crate::error::{Error, Result};
+use crate::object::id::ObjectId;
+
+// every ref we pack is peeled, so readers can trust a missing `^` line to mean "not a tag"
+const HEADER: &str = "# pack-refs with: peeled fully-peeled sorted \n";
+
+/// A reference stored in `packed-refs` along with the object it peels to if it names a tag.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackedRef {
+    pub hash: ObjectId,
+    pub peeled: Option<ObjectId>,
+}
+
+/// The contents of a `packed-refs` file, keyed by full reference name.
+#[derive(Debug, Default, PartialEq)]
+pub struct PackedRefs {
+    pub refs: BTreeMap<String, PackedRef>,
+}
+
+impl PackedRefs {
+    pub fn deserialize(content: &str) -> Result<Self> {
+        let corrupt =
+            |line: &str| Error::Corrupt(format!("Packed ref {:?} couldn't be parsed.", line));
+        let mut refs: BTreeMap<String, PackedRef> = BTreeMap::new();
+        let mut last: Option<String> = None;
+        for line in content.lines() {
+            if line.starts_with('#') || line.is_empty() {
+                continue;
+            } else if let Some(peeled) = line.strip_prefix('^') {
+                let peeled = ObjectId::from_hex(peeled).map_err(|_| corrupt(line))?;
+                let packed = last
+                    .as_ref()
+                    .and_then(|name| refs.get_mut(name))
+                    .ok_or_else(|| corrupt(line))?;
+                packed.peeled = Some(peeled);
+                // a ref can only be peeled once
+                last = None;
+            } else {
+                let (hash, name) = line.split_once(' ').ok_or_else(|| corrupt(line))?;
+                let hash = ObjectId::from_hex(hash).map_err(|_| corrupt(line))?;
+                refs.insert(name.to_string(), PackedRef { hash, peeled: None });
+                last = Some(name.to_string());
+            }
+        }
+        Ok(Self { refs })
+    }
+
+    pub fn serialize(&self) -> String {
+        let mut content = HEADER.to_string();
+        for (name, packed) in &self.refs {
+            content.push_str(&format!("{} {}\n", packed.hash, name));
+            if let Some(peeled) = packed.peeled {
+                content.push_str(&format!("^{}\n", peeled));
+            }
+        }
+        content
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::reference::packed::{PackedRef, PackedRefs};
+
+    const PACKED: &str = "# pack-refs with: peeled fully-peeled sorted \n\
+                          1111111111111111111111111111111111111111 refs/heads/master\n\
+                          2222222222222222222222222222222222222222 refs/tags/v1\n\
+                          ^3333333333333333333333333333333333333333\n";
+
+    #[test]
+    fn round_trips_packed_refs() {
+        let packed = PackedRefs::deserialize(PACKED).unwrap();
+        assert_eq!(
+            packed.refs["refs/heads/master"],
+            PackedRef {
+                hash: "1".repeat(40).parse().unwrap(),
+                peeled: None,
+            }
+        );
+        assert_eq!(
+            packed.refs["refs/tags/v1"],
+            PackedRef {
+                hash: "2".repeat(40).parse().unwrap(),
+                peeled: Some("3".repeat(40).parse().unwrap()),
+            }
+        );
+        assert_eq!(packed.serialize(), PACKED)
+    }
+
+    #[test]
+    fn parses_packed_refs_without_a_header() {
+        let packed =
+            PackedRefs::deserialize(&format!("{} refs/tags/v1\n", "ab".repeat(32))).unwrap();
+        assert_eq!(
+            packed.refs["refs/tags/v1"].hash,
+            "ab".repeat(32).parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn fails_to_parse_orphaned_peeled_lines() {
+        let content = format!("^{}\n", "1".repeat(40));
+        assert!(matches!(
+            PackedRefs::deserialize(&content),
+            Err(Error::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn fails_to_parse_garbage() {
+        assert!(matches!(
+            PackedRefs::deserialize("not a packed ref\n"),
+            Err(Error::Corrupt(_))
+        ));
+    }
+}
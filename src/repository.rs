@@ -1,166 +1,1913 @@
+use std::collections::{BinaryHeap, HashSet};
+use std::env;
 use std::fs;
 use std::fs::File;
-use std::io::prelude::*;
-use std::io::{BufReader, BufWriter, Read};
+use std::io;
+use std::io::{ErrorKind, Read};
 use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use crypto::digest::Digest;
-use crypto::sha1::Sha1;
-use flate2::read::ZlibDecoder;
-use flate2::write::ZlibEncoder;
-use flate2::Compression;
+use regex::Regex;
 
-use crate::object::tree::Tree;
+use crate::config::layered::{LayeredConfig, Scope};
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::object::commit::Commit;
+use crate::object::constant::{NAME_BLOB, NAME_COMMIT, NAME_TAG, NAME_TREE};
+use crate::object::id::{ObjectFormat, ObjectId};
+use crate::object::index::Index;
+use crate::object::signature::{self, Signature};
+use crate::object::tree::{Leaf, Mode, Tree};
 use crate::object::Object;
-use crate::reference::Reference;
+use crate::pack::Entry;
+use crate::reference::log::LogEntry;
+use crate::reference::{Reference, BRANCH_PREFIX, REMOTE_PREFIX, TAG_PREFIX};
+use crate::revision::{ReflogSelector, Revision};
+use crate::store::file::{FileObjectStore, FileRefStore, LockFile};
+use crate::store::memory::{MemoryObjectStore, MemoryRefStore};
+use crate::store::{ObjectStore, RefChange, RefStore, RefUpdate};
+use crate::stream::{ObjectReader, ObjectWriter};
 
 const GIT_DIR: &str = ".git";
 const OBJECT_DIR: &str = "objects";
 const REFS_DIR: &str = "refs";
 const HEAD_FILE: &str = "HEAD";
+const CONFIG_FILE: &str = "config";
+const INDEX_FILE: &str = "index";
+const DEFAULT_BRANCH: &str = "master";
+const INIT_DIRS: [&str; 6] = [
+    "objects/info",
+    "objects/pack",
+    "refs/heads",
+    "refs/tags",
+    "hooks",
+    "info",
+];
+// the same content git's default templates have
+const INIT_FILES: [(&str, &str); 2] = [
+    (
+        "description",
+        "Unnamed repository; edit this file 'description' to name the repository.\n",
+    ),
+    (
+        "info/exclude",
+        "# git ls-files --others --exclude-from=.git/info/exclude\n\
+         # Lines that start with '#' are comments.\n\
+         # For a project mostly in C, the following would be a good set of\n\
+         # exclude patterns (uncomment them if you want to use them):\n\
+         # *.[oa]\n\
+         # *~\n",
+    ),
+];
+/// git's default for `core.abbrev`, which is lengthened wherever it'd be ambiguous.
+pub const DEFAULT_ABBREV: usize = 7;
+// git won't look up anything shorter as it'd almost always be ambiguous
+const MIN_ABBREV: usize = 4;
+// the same limit as git, which is enough to catch a reference that points at itself
+const MAX_SYMBOLIC_DEPTH: usize = 5;
+const LOGGED_PREFIXES: [&str; 3] = [BRANCH_PREFIX, REMOTE_PREFIX, "refs/notes/"];
+// the order git tries names in, see `git help revisions`
+const NAME_RULES: [(&str, &str); 6] = [
+    ("", ""),
+    ("refs/", ""),
+    (TAG_PREFIX, ""),
+    (BRANCH_PREFIX, ""),
+    (REMOTE_PREFIX, ""),
+    (REMOTE_PREFIX, "/HEAD"),
+];
+
+#[cfg(unix)]
+fn device(path: &Path) -> Result<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Ok(fs::metadata(path)?.dev())
+}
+
+#[cfg(not(unix))]
+fn device(_path: &Path) -> Result<u64> {
+    // other platforms don't give us a device id so every path looks like it's on the same one
+    Ok(0)
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+/// How a repository is shared between users, following `git init --shared`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shared {
+    /// Permissions are left to the user's umask.
+    Umask,
+    /// Everything can be written by the group.
+    Group,
+    /// Everything can be written by the group and read by everyone else.
+    All,
+    /// Everything gets exactly these permissions, like `0660`.
+    Mode(u32),
+}
+
+impl Shared {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "umask" | "false" | "0" => Ok(Shared::Umask),
+            "group" | "true" | "1" => Ok(Shared::Group),
+            "all" | "world" | "everybody" | "2" => Ok(Shared::All),
+            mode => match u32::from_str_radix(mode, 8) {
+                // git insists the owner can always read and write their own files
+                Ok(mode) if mode & 0o600 == 0o600 && mode <= 0o777 => Ok(Shared::Mode(mode)),
+                _ => Err(Error::InvalidArgument(format!(
+                    "{} is not a way to share a repository, expected umask, group, all or \
+                     permissions like 0660.",
+                    value
+                ))),
+            },
+        }
+    }
+
+    fn config_value(&self) -> Option<String> {
+        match self {
+            Shared::Umask => None,
+            Shared::Group => Some("1".to_string()),
+            Shared::All => Some("2".to_string()),
+            Shared::Mode(mode) => Some(format!("{:04o}", mode)),
+        }
+    }
+
+    fn mode(&self, mode: u32, is_dir: bool) -> u32 {
+        let (mut tweak, replace) = match self {
+            Shared::Umask => return mode,
+            Shared::Group => (0o660, false),
+            Shared::All => (0o664, false),
+            Shared::Mode(mode) => (*mode, true),
+        };
+        if mode & 0o200 == 0 {
+            tweak &= !0o222;
+        }
+        if is_dir || mode & 0o100 != 0 {
+            tweak |= (tweak & 0o444) >> 2;
+        }
+        let mode = match replace {
+            true => (mode & !0o777) | tweak,
+            false => mode | tweak,
+        };
+        match is_dir {
+            true => mode | 0o2000,
+            false => mode,
+        }
+    }
+}
+
+/// How to lay out a new repository, following the options to `git init`.
+#[derive(Debug, Clone, Default)]
+pub struct InitOptions {
+    /// The object format, which can't be changed once a repository exists and is SHA-1 by
+    /// default.
+    pub format: Option<ObjectFormat>,
+    pub bare: bool,
+    /// The branch HEAD starts out on, `master` by default.
+    pub initial_branch: Option<String>,
+    /// A directory whose contents, like hooks, are copied into the git directory.
+    pub template: Option<PathBuf>,
+    pub shared: Option<Shared>,
+}
 
-#[derive(Debug)]
 pub struct Repository {
-    pub work_tree: PathBuf,
+    /// Where the repository is checked out, which bare repositories don't have.
+    pub work_tree: Option<PathBuf>,
     pub root: PathBuf,
-    pub objects: PathBuf,
-    pub refs: PathBuf,
-    pub head: PathBuf,
+    pub objects: Box<dyn ObjectStore>,
+    pub refs: Box<dyn RefStore>,
 }
 
 impl Repository {
-    pub fn for_working_directory() -> Self {
-        std::env::current_dir().map(Repository::new).unwrap()
+    /// Finds the repository the current directory is in the same way git does, honouring
+    /// `GIT_DIR`, `GIT_WORK_TREE` and `GIT_CEILING_DIRECTORIES`.
+    pub fn for_working_directory() -> Result<Self> {
+        let current = env::current_dir()?;
+        let (root, found) = match env::var_os("GIT_DIR") {
+            // git takes the current directory as the top of the work tree in this case
+            Some(root) => (current.join(root), Some(current)),
+            None => {
+                let ceilings = env::var_os("GIT_CEILING_DIRECTORIES")
+                    .map(|dirs| env::split_paths(&dirs).collect::<Vec<_>>())
+                    .unwrap_or_default();
+                let across = env::var("GIT_DISCOVERY_ACROSS_FILESYSTEM")
+                    .map(|v| v == "1" || v == "true")
+                    .unwrap_or(false);
+                Repository::find_git_dir(&current, &ceilings, across)?
+            }
+        };
+        let work_tree = match env::var_os("GIT_WORK_TREE") {
+            Some(dir) => Some(env::current_dir()?.join(dir)),
+            None if Repository::is_bare(&root)? => None,
+            None => found,
+        };
+        Repository::open(root, work_tree)
     }
 
-    pub fn new(work_tree: PathBuf) -> Self {
-        let root = work_tree.join(GIT_DIR);
-        let objects = root.join(Path::new(OBJECT_DIR));
-        let refs = root.join(Path::new(REFS_DIR));
-        let head = root.join(Path::new(HEAD_FILE));
+    /// Opens the repository whose work tree is at the given path, following its `.git` if it's
+    /// a file pointing somewhere else.
+    pub fn new(work_tree: PathBuf) -> Result<Self> {
+        let root = Repository::git_dir_in(&work_tree)?.unwrap_or_else(|| work_tree.join(GIT_DIR));
+        Repository::open(root, Some(work_tree))
+    }
+
+    pub fn open(root: PathBuf, work_tree: Option<PathBuf>) -> Result<Self> {
+        let format = Repository::read_object_format(&root.join(CONFIG_FILE))?;
+        let objects = FileObjectStore::open(root.join(OBJECT_DIR), format)?;
+        let refs = FileRefStore::new(root.clone());
+        Ok(Repository::from_stores(
+            work_tree,
+            root,
+            Box::new(objects),
+            Box::new(refs),
+        ))
+    }
+
+    fn find_git_dir(
+        start: &Path,
+        ceilings: &[PathBuf],
+        across: bool,
+    ) -> Result<(PathBuf, Option<PathBuf>)> {
+        let ceilings: Vec<_> = ceilings
+            .iter()
+            .filter_map(|dir| dir.canonicalize().ok())
+            .collect();
+        let start = start.canonicalize()?;
+        let start_device = device(&start)?;
+        let mut dir = start.as_path();
+        loop {
+            if let Some(root) = Repository::git_dir_in(dir)? {
+                return Ok((root, Some(dir.to_path_buf())));
+            }
+            if Repository::is_git_dir(dir) {
+                return Ok((dir.to_path_buf(), None));
+            }
+            match dir.parent() {
+                Some(parent) if !ceilings.iter().any(|c| c == parent) => {
+                    if !across && device(parent)? != start_device {
+                        break;
+                    }
+                    dir = parent;
+                }
+                _ => break,
+            }
+        }
+        Err(Error::NotFound(format!(
+            "Repository in {} or any of its parents",
+            start.display()
+        )))
+    }
+
+    fn git_dir_in(work_tree: &Path) -> Result<Option<PathBuf>> {
+        let dot_git = work_tree.join(GIT_DIR);
+        if dot_git.is_dir() {
+            return Ok(Some(dot_git).filter(|dir| Repository::is_git_dir(dir)));
+        }
+        let content = match fs::read_to_string(&dot_git) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let target = content
+            .lines()
+            .next()
+            .and_then(|line| line.strip_prefix("gitdir:"))
+            .map(str::trim)
+            .ok_or_else(|| Error::Corrupt(format!("{} couldn't be parsed.", dot_git.display())))?;
+        // a relative path is relative to the directory holding the `.git` file
+        let root = work_tree.join(target);
+        if !Repository::is_git_dir(&root) {
+            return Err(Error::NotFound(format!(
+                "Repository at {} named by {}",
+                root.display(),
+                dot_git.display()
+            )));
+        }
+        Ok(Some(root))
+    }
+
+    /// Whether a directory has what git needs to treat it as a repository.
+    pub fn is_git_dir(dir: &Path) -> bool {
+        dir.join(HEAD_FILE).is_file()
+            && dir.join(OBJECT_DIR).is_dir()
+            && dir.join(REFS_DIR).is_dir()
+    }
+
+    pub fn from_stores(
+        work_tree: Option<PathBuf>,
+        root: PathBuf,
+        objects: Box<dyn ObjectStore>,
+        refs: Box<dyn RefStore>,
+    ) -> Self {
         Self {
             work_tree,
             root,
             objects,
             refs,
-            head,
         }
     }
 
-    pub fn is_empty(&self) -> bool {
-        let mut files = self.work_tree.read_dir().unwrap();
-        files.all(|f| f.unwrap().file_name() == GIT_DIR)
+    /// Creates a repository that's held entirely in memory, with nothing checked out.
+    pub fn in_memory(format: ObjectFormat) -> Self {
+        Repository::from_stores(
+            None,
+            PathBuf::new(),
+            Box::new(MemoryObjectStore::new(format)),
+            Box::new(MemoryRefStore::new()),
+        )
+    }
+
+    /// Lays out a new repository on disk the way `git init` does, or fills in anything missing
+    /// from one that already exists without touching its HEAD or its objects.
+    ///
+    /// A bare repository is laid out directly in the given directory rather than in a `.git`
+    /// inside it.
+    pub fn init(dir: PathBuf, options: &InitOptions) -> Result<Self> {
+        let (root, work_tree) = match options.bare {
+            true => (dir, None),
+            false => (dir.join(GIT_DIR), Some(dir)),
+        };
+        let existing = Repository::is_git_dir(&root);
+        let config_path = root.join(CONFIG_FILE);
+        let format = match (existing, options.format) {
+            (true, Some(format)) if format != Repository::read_object_format(&config_path)? => {
+                return Err(Error::Refused(format!(
+                    "{} can't be reinitialized with a different object format.",
+                    root.display()
+                )))
+            }
+            (_, format) => format.unwrap_or(ObjectFormat::Sha1),
+        };
+
+        fs::create_dir_all(&root)?;
+        if let Some(template) = &options.template {
+            Repository::copy_template(template, &root, true)?;
+        }
+        for dir in &INIT_DIRS {
+            fs::create_dir_all(root.join(dir))?;
+        }
+        for (file, content) in &INIT_FILES {
+            let path = root.join(file);
+            if !path.exists() {
+                fs::write(path, content)?;
+            }
+        }
+
+        let mut config = Config::read(&config_path)?;
+        if !existing {
+            // extensions are only honoured from repository format version 1 onwards
+            let version = if format == ObjectFormat::Sha1 {
+                "0"
+            } else {
+                "1"
+            };
+            config.set("core.repositoryformatversion", version)?;
+            let filemode = Repository::probe_filemode(&root.join(INIT_FILES[0].0))?;
+            config.set("core.filemode", &filemode.to_string())?;
+            config.set("core.bare", &options.bare.to_string())?;
+            if !options.bare {
+                config.set("core.logallrefupdates", "true")?;
+            }
+            if format != ObjectFormat::Sha1 {
+                config.set("extensions.objectformat", format.name())?;
+            }
+        }
+        if let Some(value) = options.shared.as_ref().and_then(Shared::config_value) {
+            config.set("core.sharedrepository", &value)?;
+            config.set("receive.denyNonFastforwards", "true")?;
+        }
+        config.write(&config_path)?;
+
+        let repo = Repository::open(root, work_tree)?;
+        if !existing {
+            let branch = options.initial_branch.as_deref().unwrap_or(DEFAULT_BRANCH);
+            let head = format!("{}{}", BRANCH_PREFIX, branch);
+            Reference::check_name(&head)?;
+            repo.set_head(&Reference::Ref(head), "init")?;
+        }
+        if let Some(shared) = &options.shared {
+            Repository::share(&repo.root, shared)?;
+        }
+        Ok(repo)
+    }
+
+    fn copy_template(from: &Path, to: &Path, top: bool) -> Result<()> {
+        // like git, a missing template directory just means there's nothing to copy
+        let entries = match from.read_dir() {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let entry = entry?;
+            // git merges a template's config into its own rather than copying it
+            if top && entry.file_name() == CONFIG_FILE {
+                continue;
+            }
+            let target = to.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                fs::create_dir_all(&target)?;
+                Repository::copy_template(&entry.path(), &target, false)?;
+            } else if !target.exists() {
+                fs::copy(entry.path(), target)?;
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn probe_filemode(path: &Path) -> Result<bool> {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(path)?.permissions().mode();
+        fs::set_permissions(path, fs::Permissions::from_mode(mode ^ 0o100))?;
+        let toggled = fs::metadata(path)?.permissions().mode() != mode;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+        Ok(toggled)
+    }
+
+    #[cfg(not(unix))]
+    fn probe_filemode(_path: &Path) -> Result<bool> {
+        Ok(false)
+    }
+
+    #[cfg(unix)]
+    fn share(path: &Path, shared: &Shared) -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        let metadata = fs::symlink_metadata(path)?;
+        if metadata.file_type().is_symlink() {
+            return Ok(());
+        }
+        let is_dir = metadata.is_dir();
+        let mode = shared.mode(metadata.permissions().mode(), is_dir);
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+        if is_dir {
+            for entry in path.read_dir()? {
+                Repository::share(&entry?.path(), shared)?;
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn share(_path: &Path, _shared: &Shared) -> Result<()> {
+        Ok(())
+    }
+
+    fn is_bare(root: &Path) -> Result<bool> {
+        Ok(Config::read(&root.join(CONFIG_FILE))?
+            .get_bool("core.bare")?
+            .unwrap_or(false))
+    }
+
+    /// Where the repository is checked out, refusing anything that needs one in a bare repository.
+    pub fn work_tree(&self) -> Result<&Path> {
+        self.work_tree.as_deref().ok_or_else(|| {
+            Error::Refused(format!(
+                "{} is a bare repository and has no work tree.",
+                self.root.display()
+            ))
+        })
+    }
+
+    /// Where the repository's own config is kept.
+    pub fn config_path(&self) -> PathBuf {
+        self.root.join(CONFIG_FILE)
+    }
+
+    pub fn format(&self) -> ObjectFormat {
+        self.objects.format()
+    }
+
+    fn read_object_format(config: &Path) -> Result<ObjectFormat> {
+        match fs::read_to_string(config) {
+            Ok(content) => Repository::parse_object_format(&content),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(ObjectFormat::Sha1),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn parse_object_format(content: &str) -> Result<ObjectFormat> {
+        match Config::deserialize(content)?.get("extensions.objectformat")? {
+            Some(name) => ObjectFormat::from_name(&name)
+                .map_err(|e| Error::Corrupt(format!("Config is invalid, {}", e))),
+            None => Ok(ObjectFormat::Sha1),
+        }
+    }
+
+    /// Reads the given config scopes as they apply to this repository.
+    pub fn load_config(&self, scopes: &[Scope]) -> Result<LayeredConfig> {
+        // repositories held in memory don't have a config file of their own
+        let git_dir = Some(self.root.as_path()).filter(|root| !root.as_os_str().is_empty());
+        let branch = match self.refs.read(HEAD_FILE)? {
+            Some(Reference::Ref(name)) => name.strip_prefix(BRANCH_PREFIX).map(str::to_string),
+            _ => None,
+        };
+        LayeredConfig::load(scopes, git_dir, branch.as_deref())
+    }
+
+    /// Reads every config value that applies to this repository.
+    pub fn config(&self) -> Result<LayeredConfig> {
+        self.load_config(&Scope::ALL)
+    }
+
+    /// Reads the staging area, which is empty until something's been added to it.
+    pub fn read_index(&self) -> Result<Index> {
+        match fs::read(self.root.join(INDEX_FILE)) {
+            Ok(bytes) => Index::deserialize(&bytes, self.format()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Index::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Replaces the staging area while holding its lock, as git does.
+    pub fn write_index(&self, index: &Index) -> Result<()> {
+        let mut lock = LockFile::acquire(self.root.join(INDEX_FILE))?;
+        lock.write(&index.serialize(self.format()))?;
+        lock.commit()
+    }
+
+    pub fn is_empty(&self) -> Result<bool> {
+        for entry in self.work_tree()?.read_dir()? {
+            if entry?.file_name() != GIT_DIR {
+                return Ok(false);
+            }
+        }
+        Ok(true)
     }
 
-    pub fn checkout_tree(&self, tree: Tree, path: &PathBuf) {
+    pub fn checkout_tree(&self, tree: Tree, path: &Path) -> Result<()> {
         // TODO[Rhys] this is pretty sloppy
-        tree.leaves.iter().for_each(|l| {
+        let mismatched = |hash: &ObjectId| {
+            Error::Corrupt(format!(
+                "Object {} did not match its tree entry's mode.",
+                hash
+            ))
+        };
+        for l in tree.leaves.iter() {
+            Leaf::check_path(&l.path)?;
             let child_path = path.join(PathBuf::from(&l.path));
-            match self.read_object(l.hash.as_str()) {
-                Object::Tree(data) => {
+            // a symlink checked out earlier in the tree could otherwise point anywhere
+            if fs::symlink_metadata(&child_path).is_ok_and(|m| m.file_type().is_symlink()) {
+                return Err(Error::Refused(format!(
+                    "{} is a symlink so it won't be written through.",
+                    child_path.display()
+                )));
+            }
+            match l.mode {
+                Mode::Directory => match self.read_object(&l.hash)? {
                     // TODO[Rhys] don't check out the tree if the hashes are the same
-                    self.checkout_tree(data, &child_path);
-                }
-                Object::Blob(data) => {
-                    fs::create_dir_all(child_path.parent().unwrap()).unwrap();
-                    let mut file = File::create(child_path).unwrap();
-                    file.write_all(data.content.as_bytes()).unwrap();
+                    Object::Tree(data) => self.checkout_tree(data, &child_path)?,
+                    _ => return Err(mismatched(&l.hash)),
+                },
+                // like git we leave an empty directory for submodules we haven't cloned
+                Mode::Submodule => fs::create_dir_all(child_path)?,
+                mode => {
+                    // blobs are streamed straight to disk so large files never sit in memory
+                    let mut blob = self.open_object(&l.hash)?;
+                    if blob.kind != NAME_BLOB {
+                        return Err(mismatched(&l.hash));
+                    }
+                    fs::create_dir_all(path)?;
+                    Repository::write_leaf(&child_path, mode, &mut blob)?;
                 }
-                _ => panic!("Object was not a tree or a blob."),
             }
-        });
+        }
+        Ok(())
     }
 
-    // TODO[Rhys] consider typing this more strongly to return a Commit
-    pub fn find_commit(&self, reference: &Reference) -> String {
+    #[cfg(unix)]
+    fn write_leaf(path: &Path, mode: Mode, content: &mut impl Read) -> Result<()> {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        if mode == Mode::Symlink {
+            let mut target = vec![];
+            content.read_to_end(&mut target)?;
+            symlink(OsStr::from_bytes(&target), path)?;
+        } else {
+            let mut file = File::create(path)?;
+            io::copy(content, &mut file)?;
+            if mode == Mode::Executable {
+                file.set_permissions(fs::Permissions::from_mode(0o755))?;
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn write_leaf(path: &Path, _mode: Mode, content: &mut impl Read) -> Result<()> {
+        // without symlinks or an executable bit everything is checked out as a plain file
+        let mut file = File::create(path)?;
+        io::copy(content, &mut file)?;
+        Ok(())
+    }
+
+    /// Looks up a name given on the command line in the same order as git, so `v1` finds
+    /// `refs/tags/v1` before `refs/heads/v1`, falling back to treating it as an object id.
+    pub fn reference(&self, name: &str) -> Result<Reference> {
+        if let Ok(hash) = ObjectId::from_hex(name) {
+            return Ok(Reference::Commit(hash));
+        }
+        // names that could never be refs, like `../config`, mustn't reach the ref store
+        let valid = Reference::check_name(name).is_ok();
+        for (prefix, suffix) in NAME_RULES.iter().filter(|_| valid) {
+            let full = format!("{}{}{}", prefix, name, suffix);
+            // only pseudo refs like HEAD live at the top level, `config` isn't a ref
+            let pseudo = full.chars().all(|c| c.is_ascii_uppercase() || c == '_');
+            if prefix.is_empty() && !pseudo && !full.starts_with("refs/") {
+                continue;
+            }
+            if self.refs.read(&full)?.is_some() {
+                return Ok(match full.as_str() {
+                    HEAD_FILE => Reference::Head,
+                    _ => Reference::Ref(full),
+                });
+            }
+        }
+        // like git, short ids are only tried once no reference matches the name
+        let is_hex = name.chars().all(|c| c.is_ascii_hexdigit());
+        if is_hex && name.len() >= MIN_ABBREV {
+            return self
+                .resolve_prefix(&name.to_lowercase())
+                .map(Reference::Commit);
+        }
+        Err(Error::NotFound(format!("Revision {}", name)))
+    }
+
+    /// Finds the one object whose id starts with the given lower case hex prefix.
+    pub fn resolve_prefix(&self, prefix: &str) -> Result<ObjectId> {
+        let candidates = self.objects.find_prefix(prefix)?;
+        match candidates.as_slice() {
+            [] => Err(Error::NotFound(format!("Object {}", prefix))),
+            [hash] => Ok(*hash),
+            _ => {
+                let described = candidates
+                    .iter()
+                    .map(|h| {
+                        self.read_header(h)
+                            .map(|(kind, _)| format!("  {} {}", h, kind))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Err(Error::InvalidArgument(format!(
+                    "Short object id {} is ambiguous, the candidates are:\n{}",
+                    prefix,
+                    described.join("\n")
+                )))
+            }
+        }
+    }
+
+    /// Shortens an id to the fewest characters, but no fewer than `min`, that no other object
+    /// in the repository starts with.
+    pub fn abbreviate(&self, hash: &ObjectId, min: usize) -> Result<String> {
+        let hex = hash.to_hex();
+        let mut len = min.max(MIN_ABBREV).min(hex.len());
+        let mut others = self.objects.find_prefix(&hex[..len])?;
+        others.retain(|h| h != hash);
+        while !others.is_empty() && len < hex.len() {
+            len += 1;
+            others.retain(|h| h.to_hex().starts_with(&hex[..len]));
+        }
+        Ok(hex[..len].to_string())
+    }
+
+    fn resolve_name(&self, name: &str) -> Result<ObjectId> {
+        match self.follow(name)? {
+            (_, Some(Reference::Commit(hash))) => Ok(hash),
+            (name, _) => Err(Error::NotFound(name)),
+        }
+    }
+
+    // Follows symbolic refs from `name`, returning the last name reached and what it holds.
+    fn follow(&self, name: &str) -> Result<(String, Option<Reference>)> {
+        let mut name = name.to_string();
+        for _ in 0..MAX_SYMBOLIC_DEPTH {
+            match self.refs.read(&name)? {
+                Some(Reference::Ref(target)) => name = target,
+                Some(Reference::Head) => name = HEAD_FILE.to_string(),
+                target => return Ok((name, target)),
+            }
+        }
+        Err(Error::InvalidRef(format!(
+            "{} is nested too deeply to follow.",
+            name
+        )))
+    }
+
+    /// Follows a reference to the object it points at without peeling tags.
+    pub fn resolve(&self, reference: &Reference) -> Result<ObjectId> {
         match reference {
-            Reference::Head => {
-                let content = fs::read_to_string(&self.head).unwrap();
-                self.find_commit(&Reference::from_file(content.as_str()))
+            Reference::Head => self.resolve_name(HEAD_FILE),
+            Reference::Ref(path) => self.resolve_name(path),
+            Reference::Commit(hash) => Ok(*hash),
+        }
+    }
+
+    /// Follows annotated tags until reaching the object they ultimately point at.
+    pub fn peel(&self, hash: ObjectId) -> Result<ObjectId> {
+        match self.read_object(&hash)? {
+            Object::Tag(tag) => self.peel(tag.object),
+            _ => Ok(hash),
+        }
+    }
+
+    // TODO[Rhys] consider typing this more strongly to return a Commit
+    pub fn find_commit(&self, reference: &Reference) -> Result<ObjectId> {
+        // packed refs remember what they peel to which saves reading through the tags
+        if let Reference::Ref(name) = reference {
+            if let Some(peeled) = self.refs.peeled(name)? {
+                return Ok(peeled);
+            }
+        }
+        self.peel(self.resolve(reference)?)
+    }
+
+    /// Resolves a revision expression like `HEAD~2` or `v1.0^{tree}` to the object it names.
+    pub fn rev_parse(&self, spec: &str) -> Result<ObjectId> {
+        self.evaluate(&Revision::parse(spec)?)
+    }
+
+    pub fn evaluate(&self, revision: &Revision) -> Result<ObjectId> {
+        match revision {
+            Revision::Name(name) => self.resolve(&self.reference(name)?),
+            Revision::PreviousCheckout(n) => {
+                self.resolve(&self.reference(&self.previous_checkout(*n)?)?)
+            }
+            Revision::Reflog(name, selector) => self.find_logged(name, selector),
+            Revision::MessageSearch(pattern) => self.search_messages(pattern),
+            Revision::Ancestor(base, n) => {
+                let mut hash = self.peel_to(self.evaluate(base)?, NAME_COMMIT)?;
+                for _ in 0..*n {
+                    hash = self.parent(&hash, 1)?;
+                }
+                Ok(hash)
             }
-            Reference::Ref(path) => {
-                let file = &self.root.join(Path::new(&path));
-                let content = fs::read_to_string(file).unwrap();
-                self.find_commit(&Reference::from_file(content.as_str()))
+            Revision::Parent(base, n) => {
+                let hash = self.peel_to(self.evaluate(base)?, NAME_COMMIT)?;
+                match n {
+                    0 => Ok(hash),
+                    n => self.parent(&hash, *n),
+                }
             }
-            Reference::Commit(hash) => hash.to_string(),
+            Revision::Peel(base, kind) => {
+                let hash = self.evaluate(base)?;
+                match kind.as_str() {
+                    "" => self.peel(hash),
+                    "object" => Ok(hash),
+                    kind => self.peel_to(hash, kind),
+                }
+            }
+            Revision::Path(base, path) => {
+                let tree = self.peel_to(self.evaluate(base)?, NAME_TREE)?;
+                self.find_path(tree, path)
+            }
+            Revision::IndexPath(stage, path) => self
+                .read_index()?
+                .entries
+                .into_iter()
+                .find(|entry| entry.stage == *stage && entry.path == *path)
+                .map(|entry| entry.hash)
+                .ok_or_else(|| {
+                    Error::NotFound(format!("Path {} at stage {} of the index", path, stage))
+                }),
         }
     }
 
-    pub fn set_head(&self, head: &Reference) {
-        // Note[Rhys] serializing the content early means we can fail before blanking the HEAD file
-        let content = head.serialize();
-        File::create(&self.head)
-            .unwrap()
-            .write_all(content.as_bytes())
-            .unwrap();
+    /// Finds the branch, or commit if HEAD was detached, that was checked out `n` checkouts ago.
+    pub fn previous_checkout(&self, n: usize) -> Result<String> {
+        self.refs
+            .log(HEAD_FILE)?
+            .iter()
+            .rev()
+            .filter_map(|entry| entry.checkout_from())
+            .nth(n.saturating_sub(1))
+            .map(str::to_string)
+            .ok_or_else(|| Error::NotFound(format!("Checkout @{{-{}}}", n)))
+    }
+
+    /// Follows tags, and commits to their trees, until reaching an object of the given kind.
+    pub fn peel_to(&self, hash: ObjectId, kind: &str) -> Result<ObjectId> {
+        if ![NAME_BLOB, NAME_COMMIT, NAME_TAG, NAME_TREE].contains(&kind) {
+            return Err(Error::InvalidArgument(format!(
+                "Object type {} is not valid.",
+                kind
+            )));
+        }
+        let mut current = hash;
+        loop {
+            // the header is enough to stop at the right kind without reading large blobs
+            if self.read_header(&current)?.0 == kind {
+                return Ok(current);
+            }
+            current = match self.read_object(&current)? {
+                Object::Tag(tag) => tag.object,
+                Object::Commit(commit) if kind == NAME_TREE => commit.tree,
+                _ => {
+                    return Err(Error::InvalidRef(format!(
+                        "{} can't be peeled to a {}.",
+                        hash, kind
+                    )))
+                }
+            };
+        }
+    }
+
+    pub fn read_commit(&self, hash: &ObjectId) -> Result<Commit> {
+        match self.read_object(hash)? {
+            Object::Commit(commit) => Ok(commit),
+            _ => Err(Error::InvalidRef(format!("{} is not a commit.", hash))),
+        }
+    }
+
+    fn parent(&self, hash: &ObjectId, n: usize) -> Result<ObjectId> {
+        self.read_commit(hash)?
+            .parents
+            .get(n - 1)
+            .copied()
+            .ok_or_else(|| Error::NotFound(format!("Parent {} of {}", n, hash)))
+    }
+
+    fn find_path(&self, tree: ObjectId, path: &str) -> Result<ObjectId> {
+        let missing = || Error::NotFound(format!("Path {} in {}", path, tree));
+        let mut hash = tree;
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            let tree = match self.read_object(&hash)? {
+                Object::Tree(tree) => tree,
+                _ => return Err(missing()),
+            };
+            hash = tree
+                .leaves
+                .into_iter()
+                .find(|l| l.path == component)
+                .map(|l| l.hash)
+                .ok_or_else(missing)?;
+        }
+        Ok(hash)
     }
 
-    pub fn hash(bytes: &[u8]) -> String {
-        let mut hasher = Sha1::new();
-        hasher.input(bytes);
-        hasher.result_str()
+    fn search_messages(&self, pattern: &str) -> Result<ObjectId> {
+        let regex = Regex::new(pattern)
+            .map_err(|_| Error::InvalidArgument(format!("{} is not a valid pattern.", pattern)))?;
+        let mut pending = BinaryHeap::new();
+        let mut seen = HashSet::new();
+        let mut starts = vec![Reference::Head];
+        starts.extend(self.list_refs()?);
+        for start in starts {
+            // an unborn HEAD or a ref to something other than a commit has no history
+            let hash = match self
+                .resolve(&start)
+                .and_then(|hash| self.peel_to(hash, NAME_COMMIT))
+            {
+                Ok(hash) => hash,
+                Err(Error::NotFound(_)) | Err(Error::InvalidRef(_)) => continue,
+                Err(e) => return Err(e),
+            };
+            if seen.insert(hash) {
+                pending.push((self.read_commit(&hash)?.committer().time, hash));
+            }
+        }
+
+        while let Some((_, hash)) = pending.pop() {
+            let commit = self.read_commit(&hash)?;
+            if regex.is_match(&commit.message) {
+                return Ok(hash);
+            }
+            for parent in commit.parents {
+                if seen.insert(parent) {
+                    pending.push((self.read_commit(&parent)?.committer().time, parent));
+                }
+            }
+        }
+        Err(Error::NotFound(format!("Commit matching {}", pattern)))
     }
 
-    fn hash_to_path(hash: &str) -> PathBuf {
-        let (dir, file) = hash.split_at(2);
-        Path::new(&format!("{}/{}", dir, file)).into()
+    /// Lists every reference under `refs`.
+    pub fn list_refs(&self) -> Result<Vec<Reference>> {
+        Ok(self.refs.list()?.into_iter().map(Reference::Ref).collect())
     }
 
-    fn read_zlib(path: PathBuf) -> Vec<u8> {
-        let file = File::open(path).unwrap();
-        let mut decoder = ZlibDecoder::new(BufReader::new(&file));
-        let mut bytes = vec![];
-        decoder
-            .read_to_end(&mut bytes)
-            .expect("Unable to read file.");
-        bytes
+    /// Packs tags, or every reference if `all` is set, returning how many were packed.
+    pub fn pack_refs(&self, all: bool) -> Result<usize> {
+        self.refs
+            .pack(&|name| all || name.starts_with(TAG_PREFIX), &|hash| {
+                self.peel(*hash)
+            })
     }
 
-    fn write_zlib(path: PathBuf, bytes: &Vec<u8>) {
-        fs::create_dir_all(path.parent().unwrap()).unwrap();
-        let file = File::create(path).unwrap();
-        let mut encoder = ZlibEncoder::new(BufWriter::new(&file), Compression::default());
-        encoder.write_all(bytes).unwrap();
-        encoder.finish().unwrap();
+    /// Reads what HEAD points at without following it.
+    pub fn head(&self) -> Result<Reference> {
+        self.refs
+            .read(HEAD_FILE)?
+            .ok_or_else(|| Error::NotFound(HEAD_FILE.to_string()))
     }
 
-    pub fn write_object(&self, obj: Object) -> String {
-        let content = obj.serialize();
-        let hash = Repository::hash(&content);
-        let relative_path = Repository::hash_to_path(&*hash);
-        let path = self.objects.join(relative_path);
-        Repository::write_zlib(path, &content);
-        hash
+    pub fn set_head(&self, head: &Reference, message: &str) -> Result<()> {
+        self.write_ref(HEAD_FILE, head, message)
     }
 
-    pub fn read_object(&self, hash: &str) -> Object {
-        let relative_path = Repository::hash_to_path(hash);
-        let path = self.objects.join(relative_path);
-        let bytes = Repository::read_zlib(path);
-        Object::deserialize(bytes.as_slice())
+    /// Points a reference at a new value, recording the move in its log and in HEAD's log too if
+    /// HEAD is attached to it.
+    pub fn write_ref(&self, name: &str, value: &Reference, message: &str) -> Result<()> {
+        self.update_refs(
+            &[RefUpdate::new(name, RefChange::Write(value.clone()))],
+            message,
+        )
+    }
+
+    /// Applies a set of reference updates together, logging each one that moves a reference and
+    /// dropping the logs of any that are deleted.
+    pub fn update_refs(&self, updates: &[RefUpdate], message: &str) -> Result<()> {
+        let head = self.refs.read(HEAD_FILE)?;
+        let mut head_entry = None;
+        let mut logged = vec![];
+        for update in updates {
+            let mut update = update.clone();
+            if let RefChange::Write(value) = &update.change {
+                if let Some(entry) = self.log_entry(&update.name, value, message)? {
+                    if update.name != HEAD_FILE && head == Some(Reference::Ref(update.name.clone()))
+                    {
+                        head_entry = Some(entry.clone());
+                    }
+                    if self.is_logged(&update.name)? {
+                        update.log = Some(entry);
+                    }
+                }
+            }
+            logged.push(update);
+        }
+        // like git, HEAD is locked while its log records the branch it points at moving
+        if let Some(entry) = head_entry {
+            if logged.iter().any(|u| u.name == HEAD_FILE) {
+                return Err(Error::InvalidArgument(
+                    "HEAD can't be updated along with the branch it points at.".to_string(),
+                ));
+            }
+            logged.push(
+                RefUpdate::new(HEAD_FILE, RefChange::Verify)
+                    .expecting(head)
+                    .logging(entry),
+            );
+        }
+        self.refs.transaction(&logged)
+    }
+
+    // The log entry for a reference moving to a new value, or `None` when it's pointed at a
+    // branch that hasn't been born yet so there's nothing to record.
+    fn log_entry(&self, name: &str, value: &Reference, message: &str) -> Result<Option<LogEntry>> {
+        let old = match self.resolve_name(name) {
+            Ok(hash) => hash,
+            Err(Error::NotFound(_)) => ObjectId::null(self.format()),
+            Err(e) => return Err(e),
+        };
+        let new = match self.resolve(value) {
+            Ok(hash) => hash,
+            Err(Error::NotFound(_)) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        Ok(Some(LogEntry {
+            old,
+            new,
+            signature: self.identity()?,
+            message: message.to_string(),
+        }))
+    }
+
+    /// Moves a reference and its log to a new name, leaving HEAD attached to it if it was before.
+    pub fn rename_ref(&self, from: &str, to: &str, message: &str) -> Result<()> {
+        let value = self
+            .refs
+            .read(from)?
+            .ok_or_else(|| Error::NotFound(from.to_string()))?;
+        let mut updates = vec![
+            RefUpdate::new(to, RefChange::Write(value.clone())).expecting(None),
+            RefUpdate::new(from, RefChange::Delete).expecting(Some(value.clone())),
+        ];
+        let attached = Reference::Ref(from.to_string());
+        let head_attached = self.refs.read(HEAD_FILE)? == Some(attached.clone());
+        if head_attached {
+            updates.push(
+                RefUpdate::new(HEAD_FILE, RefChange::Write(Reference::Ref(to.to_string())))
+                    .expecting(Some(attached)),
+            );
+        }
+        let mut log = self.refs.log(from)?;
+        self.refs.transaction(&updates)?;
+
+        // like git, the rename is logged as a move from the reference's value to itself
+        let hash = self.resolve(&value)?;
+        let entry = LogEntry {
+            old: hash,
+            new: hash,
+            signature: self.identity()?,
+            message: message.to_string(),
+        };
+        if !log.is_empty() || self.is_logged(to)? {
+            log.push(entry.clone());
+            self.refs.write_log(to, &log)?;
+        }
+        if head_attached {
+            self.refs.append_log(HEAD_FILE, &entry)?;
+        }
+        Ok(())
+    }
+
+    /// Whether `ancestor` can be reached from `descendant` by following parents, counting a
+    /// commit as its own ancestor.
+    pub fn is_ancestor(&self, ancestor: &ObjectId, descendant: &ObjectId) -> Result<bool> {
+        let mut pending = vec![*descendant];
+        let mut seen = HashSet::new();
+        while let Some(hash) = pending.pop() {
+            if hash == *ancestor {
+                return Ok(true);
+            }
+            if seen.insert(hash) {
+                pending.extend(self.read_commit(&hash)?.parents);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Follows symbolic references like HEAD to the name of the reference they end up at, which
+    /// may not exist yet.
+    pub fn dereference(&self, name: &str) -> Result<String> {
+        self.follow(name).map(|(name, _)| name)
+    }
+
+    fn is_logged(&self, name: &str) -> Result<bool> {
+        let config = self.config()?;
+        let key = "core.logallrefupdates";
+        // repositories held in memory have no work tree but aren't bare either
+        let bare = self.work_tree.is_none() && !self.root.as_os_str().is_empty();
+        let logged_by_default = match config.get(key)? {
+            Some(value) if value.eq_ignore_ascii_case("always") => true,
+            _ => {
+                config.get_bool(key)?.unwrap_or(!bare)
+                    && (name == HEAD_FILE
+                        || LOGGED_PREFIXES
+                            .iter()
+                            .any(|prefix| name.starts_with(prefix)))
+            }
+        };
+        Ok(logged_by_default || !self.refs.log(name)?.is_empty())
+    }
+
+    /// The identity recorded in reflogs, read from the same environment variables as git and
+    /// then from `user.name` and `user.email`.
+    pub fn identity(&self) -> Result<Signature> {
+        let config = self.config()?;
+        let user = env::var("USER")
+            .or_else(|_| env::var("USERNAME"))
+            .unwrap_or_else(|_| "unknown".to_string());
+        let name = match env::var("GIT_COMMITTER_NAME") {
+            Ok(name) => name,
+            Err(_) => config.get("user.name")?.unwrap_or_else(|| user.clone()),
+        };
+        let email = match env::var("GIT_COMMITTER_EMAIL") {
+            Ok(email) => email,
+            Err(_) => config
+                .get("user.email")?
+                .unwrap_or_else(|| format!("{}@localhost", user)),
+        };
+        let (time, offset) = match env::var("GIT_COMMITTER_DATE") {
+            Ok(date) => signature::parse_signature_date(&date, now())?,
+            Err(_) => {
+                let time = now();
+                (time, signature::local_offset(time))
+            }
+        };
+        Ok(Signature::new(name, email, time, offset))
+    }
+
+    /// Finds the reference whose log `name@{...}` reads, where an empty name means the current
+    /// branch, or HEAD if it's detached.
+    pub fn log_name(&self, name: &str) -> Result<String> {
+        if name.is_empty() {
+            return match self.head()? {
+                Reference::Ref(branch) => Ok(branch),
+                _ => Ok(HEAD_FILE.to_string()),
+            };
+        }
+        match self.reference(name)? {
+            Reference::Head => Ok(HEAD_FILE.to_string()),
+            Reference::Ref(full) => Ok(full),
+            Reference::Commit(hash) => Err(Error::InvalidRef(format!("{} has no log.", hash))),
+        }
+    }
+
+    fn find_logged(&self, name: &str, selector: &ReflogSelector) -> Result<ObjectId> {
+        let full = self.log_name(name)?;
+        let log = self.refs.log(&full)?;
+        match selector {
+            // `@{0}` is always the current value even if nothing has been logged
+            ReflogSelector::Entry(0) if log.is_empty() => self.resolve_name(&full),
+            ReflogSelector::Entry(n) => {
+                log.iter()
+                    .rev()
+                    .nth(*n)
+                    .map(|entry| entry.new)
+                    .ok_or_else(|| {
+                        Error::NotFound(format!("Log for {} only has {} entries", full, log.len()))
+                    })
+            }
+            ReflogSelector::Date(date) => {
+                let time = signature::parse_date(date, now())?;
+                // like git, a date before the log starts gives the value it started
+                // from, or the first value if the reference was created by that entry
+                match log.iter().rev().find(|entry| entry.signature.time <= time) {
+                    Some(entry) => Ok(entry.new),
+                    None => log
+                        .first()
+                        .map(|entry| {
+                            if entry.old.is_null() {
+                                entry.new
+                            } else {
+                                entry.old
+                            }
+                        })
+                        .ok_or_else(|| Error::NotFound(format!("Log for {} at {}", full, date))),
+                }
+            }
+        }
+    }
+
+    pub fn hash(&self, bytes: &[u8]) -> ObjectId {
+        self.format().hash(bytes)
+    }
+
+    pub fn write_object(&self, obj: Object) -> Result<ObjectId> {
+        let (kind, content) = obj.serialize_body();
+        self.objects.write(kind, &content)
+    }
+
+    /// Packs the entries together, returning where the pack was written if the store keeps packs.
+    pub fn repack(&self, entries: Vec<Entry>) -> Result<Option<PathBuf>> {
+        self.objects.repack(entries)
+    }
+
+    /// Opens a stream over an object's content so that it never has to be held in memory at once.
+    pub fn open_object(&self, hash: &ObjectId) -> Result<ObjectReader> {
+        self.objects.open(hash)
+    }
+
+    /// Reads an object's kind and size without reading its content.
+    pub fn read_header(&self, hash: &ObjectId) -> Result<(String, usize)> {
+        let object = self.open_object(hash)?;
+        Ok((object.kind, object.size))
+    }
+
+    /// Creates a sink that writes an object of the given kind and size into the object store.
+    pub fn create_object(&self, kind: &str, size: usize) -> Result<ObjectWriter<'_>> {
+        self.objects.create(kind, size)
+    }
+
+    /// Reads the kind and content of an object without parsing it.
+    pub fn read_raw(&self, hash: &ObjectId) -> Result<(String, Vec<u8>)> {
+        self.objects.read(hash)
+    }
+
+    pub fn read_object(&self, hash: &ObjectId) -> Result<Object> {
+        let (kind, content) = self.read_raw(hash)?;
+        Object::new(kind, content, self.format())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::path::Path;
+    use std::fs;
+
+    use crate::config::Config;
+    use crate::error::Error;
+    use crate::object::blob::Blob;
+    use crate::object::commit::Commit;
+    use crate::object::id::{ObjectFormat, ObjectId};
+    use crate::object::index::{Entry as IndexEntry, Index, Stat};
+    use crate::object::tag::Tag;
+    use crate::object::tree::{Leaf, Mode, Tree};
+    use crate::object::Object;
+    use crate::reference::Reference;
+    use crate::repository::{InitOptions, Repository, Shared};
+    use crate::store::{RefChange, RefUpdate};
+
+    fn write_commit(
+        repo: &Repository,
+        tree: ObjectId,
+        parents: &[ObjectId],
+        time: i64,
+        message: &str,
+    ) -> ObjectId {
+        let parents: String = parents.iter().map(|p| format!("parent {}\n", p)).collect();
+        let signature = format!("Rhys <rhys@example.com> {} +0000", time);
+        let content = format!(
+            "tree {}\n{}author {}\ncommitter {}\n\n{}\n",
+            tree, parents, signature, signature, message
+        );
+        let commit = Commit::deserialize(content.into_bytes()).unwrap();
+        repo.write_object(Object::Commit(commit)).unwrap()
+    }
+
+    fn write_tag(repo: &Repository, name: &str, object: ObjectId, kind: &str) -> ObjectId {
+        let tag = repo
+            .write_object(Object::Tag(Tag::new(
+                object,
+                kind.to_string(),
+                name.to_string(),
+                None,
+                b"Release\n".to_vec(),
+            )))
+            .unwrap();
+        repo.refs
+            .write(&format!("refs/tags/{}", name), &Reference::Commit(tag))
+            .unwrap();
+        tag
+    }
+
+    #[test]
+    fn resolves_references_through_head() {
+        let repo = Repository::in_memory(ObjectFormat::Sha1);
+        let hash = repo
+            .write_object(Object::Blob(Blob {
+                content: b"test".to_vec(),
+            }))
+            .unwrap();
+        repo.set_head(&Reference::Ref("refs/heads/master".to_string()), "")
+            .unwrap();
+        repo.refs
+            .write("refs/heads/master", &Reference::Commit(hash))
+            .unwrap();
+
+        assert_eq!(repo.resolve(&Reference::Head).unwrap(), hash);
+        assert_eq!(
+            repo.list_refs().unwrap(),
+            vec![Reference::Ref("refs/heads/master".to_string())]
+        );
+    }
+
+    #[test]
+    fn fails_to_resolve_missing_references() {
+        let repo = Repository::in_memory(ObjectFormat::Sha1);
+        assert!(matches!(
+            repo.resolve(&Reference::Head),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn fails_to_resolve_symbolic_ref_cycles() {
+        let repo = Repository::in_memory(ObjectFormat::Sha1);
+        repo.refs
+            .write("refs/heads/a", &Reference::Ref("refs/heads/b".to_string()))
+            .unwrap();
+        repo.refs
+            .write("refs/heads/b", &Reference::Ref("refs/heads/a".to_string()))
+            .unwrap();
+
+        assert!(matches!(repo.rev_parse("a"), Err(Error::InvalidRef(_))));
+        assert!(matches!(
+            repo.dereference("refs/heads/a"),
+            Err(Error::InvalidRef(_))
+        ));
+    }
+
+    #[test]
+    fn peels_tags_found_by_name() {
+        let repo = Repository::in_memory(ObjectFormat::Sha256);
+        let blob = Object::Blob(Blob {
+            content: b"test".to_vec(),
+        });
+        let hash = repo.write_object(blob).unwrap();
+        let tag = repo
+            .write_object(Object::Tag(Tag::new(
+                hash,
+                "blob".to_string(),
+                "v1".to_string(),
+                None,
+                b"First\n".to_vec(),
+            )))
+            .unwrap();
+        repo.refs
+            .write("refs/tags/v1", &Reference::Commit(tag))
+            .unwrap();
+
+        let reference = repo.reference("v1").unwrap();
+        assert_eq!(reference, Reference::Ref("refs/tags/v1".to_string()));
+        assert_eq!(repo.resolve(&reference).unwrap(), tag);
+        assert_eq!(repo.find_commit(&reference).unwrap(), hash);
+    }
 
-    use crate::repository::Repository;
+    #[test]
+    fn parses_object_format_from_config() {
+        let config = "\
+            [core]\n\
+            \trepositoryformatversion = 1\n\
+            [extensions]\n\
+            \tobjectFormat = sha256\n\
+        ";
+        assert_eq!(
+            Repository::parse_object_format(config).unwrap(),
+            ObjectFormat::Sha256
+        );
+    }
 
     #[test]
-    fn hashes() {
-        let data = "test";
-        let expected = "a94a8fe5ccb19ba61c4c0873d391e987982fbbd3";
-        let hash = Repository::hash(data.as_bytes());
-        assert_eq!(hash, expected)
+    fn defaults_to_sha1_without_extension() {
+        let config = "[core]\n\tbare = false\n";
+        assert_eq!(
+            Repository::parse_object_format(config).unwrap(),
+            ObjectFormat::Sha1
+        );
     }
 
     #[test]
-    fn converts_hash_to_path() {
-        let hash = "a94a8fe5ccb19ba61c4c0873d391e987982fbbd3";
-        let expected = Path::new("a9/4a8fe5ccb19ba61c4c0873d391e987982fbbd3");
-        let path = Repository::hash_to_path(hash);
-        assert_eq!(path, expected)
+    fn fails_on_unknown_object_format() {
+        let config = "[extensions]\n\tobjectformat = md5\n";
+        assert!(matches!(
+            Repository::parse_object_format(config),
+            Err(Error::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn resolves_revision_expressions() {
+        let repo = Repository::in_memory(ObjectFormat::Sha1);
+        let blob = repo
+            .write_object(Object::Blob(Blob {
+                content: b"test".to_vec(),
+            }))
+            .unwrap();
+        let src = repo
+            .write_object(Object::Tree(Tree {
+                leaves: vec![Leaf {
+                    mode: Mode::File,
+                    path: "lib.rs".to_string(),
+                    hash: blob,
+                }],
+            }))
+            .unwrap();
+        let tree = repo
+            .write_object(Object::Tree(Tree {
+                leaves: vec![Leaf {
+                    mode: Mode::Directory,
+                    path: "src".to_string(),
+                    hash: src,
+                }],
+            }))
+            .unwrap();
+        let first = write_commit(&repo, tree, &[], 1, "First");
+        let second = write_commit(&repo, tree, &[first], 2, "Second");
+        let side = write_commit(&repo, tree, &[first], 3, "Side");
+        let merge = write_commit(&repo, tree, &[second, side], 4, "Merge");
+        let tag = write_tag(&repo, "v1", second, "commit");
+        repo.refs
+            .write("refs/heads/master", &Reference::Commit(merge))
+            .unwrap();
+        repo.set_head(&Reference::Ref("refs/heads/master".to_string()), "")
+            .unwrap();
+
+        let expected = [
+            ("HEAD", merge),
+            ("@", merge),
+            ("master^0", merge),
+            ("HEAD~1", second),
+            ("HEAD~2", first),
+            ("master^2", side),
+            ("master^2~1", first),
+            ("v1", tag),
+            ("refs/tags/v1", tag),
+            ("v1^{}", second),
+            ("v1^{commit}", second),
+            ("v1~1", first),
+            ("v1^{tree}", tree),
+            ("HEAD:src", src),
+            ("HEAD:src/lib.rs", blob),
+            ("v1:", tree),
+            (":/^Sec", second),
+            (":/[SF]i", side),
+        ];
+        for (spec, hash) in expected.iter() {
+            assert_eq!(repo.rev_parse(spec).unwrap(), *hash, "{}", spec);
+        }
+
+        assert!(matches!(repo.rev_parse("HEAD~3"), Err(Error::NotFound(_))));
+        assert!(matches!(repo.rev_parse("HEAD^3"), Err(Error::NotFound(_))));
+        assert!(matches!(
+            repo.rev_parse("HEAD:missing"),
+            Err(Error::NotFound(_))
+        ));
+        assert!(matches!(
+            repo.rev_parse("HEAD^{commitish}"),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            repo.rev_parse("HEAD^{tree}^{commit}"),
+            Err(Error::InvalidRef(_))
+        ));
+        assert!(matches!(
+            repo.rev_parse(":/Nothing"),
+            Err(Error::NotFound(_))
+        ));
+        assert!(matches!(repo.rev_parse("@{-1}"), Err(Error::NotFound(_))));
+    }
+
+    #[test]
+    fn looks_up_names_in_git_order() {
+        let repo = Repository::in_memory(ObjectFormat::Sha1);
+        let hash: ObjectId = "ab".repeat(20).parse().unwrap();
+        for name in &["refs/heads/v1", "refs/tags/v1", "refs/remotes/origin/HEAD"] {
+            repo.refs.write(name, &Reference::Commit(hash)).unwrap();
+        }
+        assert_eq!(
+            repo.reference("v1").unwrap(),
+            Reference::Ref("refs/tags/v1".to_string())
+        );
+        assert_eq!(
+            repo.reference("heads/v1").unwrap(),
+            Reference::Ref("refs/heads/v1".to_string())
+        );
+        assert_eq!(
+            repo.reference("origin").unwrap(),
+            Reference::Ref("refs/remotes/origin/HEAD".to_string())
+        );
+        assert_eq!(
+            repo.reference(&hash.to_string()).unwrap(),
+            Reference::Commit(hash)
+        );
+        assert!(matches!(repo.reference("v2"), Err(Error::NotFound(_))));
+    }
+
+    #[test]
+    fn ignores_names_outside_the_repository() {
+        let dir = std::env::temp_dir().join(format!("gitrs_outside_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let repo = Repository::init(dir.clone(), &InitOptions::default()).unwrap();
+        fs::write(dir.join("outside"), format!("{}\n", "ab".repeat(20))).unwrap();
+
+        assert!(matches!(
+            repo.reference("../../outside"),
+            Err(Error::NotFound(_))
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn resolves_abbreviated_ids() {
+        let repo = Repository::in_memory(ObjectFormat::Sha1);
+        let hashes: Vec<ObjectId> = (0..1000)
+            .map(|i| {
+                repo.write_object(Object::Blob(Blob {
+                    content: i.to_string().into_bytes(),
+                }))
+                .unwrap()
+            })
+            .collect();
+        // a thousand ids are all but certain to share a four character prefix
+        let mut sorted: Vec<String> = hashes.iter().map(|h| h.to_hex()).collect();
+        sorted.sort();
+        let (first, second) = sorted
+            .windows(2)
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .find(|(a, b)| a[..4] == b[..4])
+            .unwrap();
+
+        match repo.rev_parse(&first[..4]) {
+            Err(Error::InvalidArgument(message)) => {
+                assert!(message.contains(&format!("{} blob", first)));
+                assert!(message.contains(&format!("{} blob", second)));
+            }
+            other => panic!("{:?} wasn't ambiguous", other.map(|h| h.to_string())),
+        }
+
+        let id: ObjectId = first.parse().unwrap();
+        let short = repo.abbreviate(&id, 4).unwrap();
+        assert!(short.len() > 4 && first.starts_with(&short));
+        assert_eq!(repo.rev_parse(&short).unwrap(), id);
+        assert_eq!(repo.rev_parse(&short.to_uppercase()).unwrap(), id);
+        assert_eq!(repo.abbreviate(&hashes[0], 40).unwrap(), hashes[0].to_hex());
+        assert!(matches!(repo.rev_parse("abc"), Err(Error::NotFound(_))));
+    }
+
+    #[test]
+    fn logs_reference_updates() {
+        let repo = Repository::in_memory(ObjectFormat::Sha1);
+        let tree = repo
+            .write_object(Object::Tree(Tree { leaves: vec![] }))
+            .unwrap();
+        let first = write_commit(&repo, tree, &[], 1, "First");
+        let second = write_commit(&repo, tree, &[first], 2, "Second");
+        let master = Reference::Ref("refs/heads/master".to_string());
+        repo.set_head(&master, "init").unwrap();
+        assert!(repo.refs.log("HEAD").unwrap().is_empty());
+
+        repo.write_ref(
+            "refs/heads/master",
+            &Reference::Commit(first),
+            "commit (initial): First",
+        )
+        .unwrap();
+        repo.write_ref(
+            "refs/heads/master",
+            &Reference::Commit(second),
+            "commit: Second",
+        )
+        .unwrap();
+        let log = repo.refs.log("refs/heads/master").unwrap();
+        assert_eq!(log, repo.refs.log("HEAD").unwrap());
+        assert_eq!(log.len(), 2);
+        assert!(log[0].old.is_null());
+        assert_eq!((log[1].old, log[1].new), (first, second));
+        assert_eq!(log[1].message, "commit: Second");
+
+        repo.set_head(
+            &Reference::Commit(first),
+            "checkout: moving from master to HEAD~1",
+        )
+        .unwrap();
+        assert_eq!(repo.refs.log("HEAD").unwrap().len(), 3);
+        assert_eq!(repo.refs.log("refs/heads/master").unwrap().len(), 2);
+
+        let expected = [
+            ("master@{0}", second),
+            ("master@{1}", first),
+            ("master@{now}", second),
+            ("HEAD@{0}", first),
+            ("HEAD@{1}", second),
+            ("@{0}", first),
+            ("@{-1}", second),
+            ("@{-1}~1", first),
+        ];
+        for (spec, hash) in expected.iter() {
+            assert_eq!(repo.rev_parse(spec).unwrap(), *hash, "{}", spec);
+        }
+        assert!(matches!(
+            repo.rev_parse("master@{2}"),
+            Err(Error::NotFound(_))
+        ));
+        assert_eq!(repo.rev_parse("master@{1970-01-01}").unwrap(), first);
+        assert!(matches!(
+            repo.rev_parse("master@{whenever}"),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn updates_refs_together_or_not_at_all() {
+        let repo = Repository::in_memory(ObjectFormat::Sha1);
+        let tree = repo
+            .write_object(Object::Tree(Tree { leaves: vec![] }))
+            .unwrap();
+        let first = write_commit(&repo, tree, &[], 1, "First");
+        let second = write_commit(&repo, tree, &[first], 2, "Second");
+        let master = Reference::Ref("refs/heads/master".to_string());
+        repo.set_head(&master, "init").unwrap();
+        repo.write_ref("refs/heads/master", &Reference::Commit(first), "create")
+            .unwrap();
+        repo.write_ref("refs/heads/old", &Reference::Commit(first), "create")
+            .unwrap();
+        assert_eq!(repo.dereference("HEAD").unwrap(), "refs/heads/master");
+
+        let stale = [
+            RefUpdate::new(
+                "refs/heads/master",
+                RefChange::Write(Reference::Commit(second)),
+            )
+            .expecting(Some(Reference::Commit(first))),
+            RefUpdate::new("refs/tags/v1", RefChange::Verify).expecting(None),
+            RefUpdate::new("refs/heads/old", RefChange::Delete)
+                .expecting(Some(Reference::Commit(second))),
+        ];
+        assert!(matches!(
+            repo.update_refs(&stale, "release"),
+            Err(Error::Refused(_))
+        ));
+        assert_eq!(repo.rev_parse("master").unwrap(), first);
+        assert_eq!(repo.rev_parse("old").unwrap(), first);
+
+        let mut updates = stale.to_vec();
+        updates[2].expected = Some(Some(Reference::Commit(first)));
+        repo.update_refs(&updates, "release").unwrap();
+        assert_eq!(repo.rev_parse("master").unwrap(), second);
+        assert!(matches!(repo.rev_parse("old"), Err(Error::NotFound(_))));
+        assert!(repo.refs.log("refs/heads/old").unwrap().is_empty());
+        assert_eq!(
+            repo.refs.log("HEAD").unwrap().last().unwrap().message,
+            "release"
+        );
+
+        assert!(matches!(
+            repo.update_refs(&updates[..2], "again"),
+            Err(Error::Refused(_))
+        ));
+        let duplicated = [updates[1].clone(), updates[1].clone()];
+        assert!(matches!(
+            repo.update_refs(&duplicated, "twice"),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn renames_refs_with_their_logs() {
+        let repo = Repository::in_memory(ObjectFormat::Sha1);
+        let tree = repo
+            .write_object(Object::Tree(Tree { leaves: vec![] }))
+            .unwrap();
+        let first = write_commit(&repo, tree, &[], 1, "First");
+        let second = write_commit(&repo, tree, &[first], 2, "Second");
+        let third = write_commit(&repo, tree, &[first], 3, "Third");
+        let topic = Reference::Ref("refs/heads/topic".to_string());
+        repo.set_head(&topic, "init").unwrap();
+        repo.write_ref("refs/heads/topic", &Reference::Commit(first), "create")
+            .unwrap();
+        repo.write_ref("refs/heads/topic", &Reference::Commit(second), "commit")
+            .unwrap();
+        repo.write_ref("refs/heads/other", &Reference::Commit(third), "create")
+            .unwrap();
+
+        assert!(matches!(
+            repo.rename_ref("refs/heads/topic", "refs/heads/other", "rename"),
+            Err(Error::Refused(_))
+        ));
+        repo.rename_ref("refs/heads/topic", "refs/heads/renamed", "rename")
+            .unwrap();
+        assert_eq!(
+            repo.head().unwrap(),
+            Reference::Ref("refs/heads/renamed".to_string())
+        );
+        assert!(repo.refs.read("refs/heads/topic").unwrap().is_none());
+        assert!(repo.refs.log("refs/heads/topic").unwrap().is_empty());
+        let log = repo.refs.log("refs/heads/renamed").unwrap();
+        assert_eq!(log.len(), 3);
+        assert_eq!((log[2].old, log[2].new), (second, second));
+        assert_eq!(repo.rev_parse("renamed@{2}").unwrap(), first);
+
+        assert!(repo.is_ancestor(&first, &second).unwrap());
+        assert!(repo.is_ancestor(&second, &second).unwrap());
+        assert!(!repo.is_ancestor(&second, &third).unwrap());
+        assert!(!repo.is_ancestor(&third, &first).unwrap());
+    }
+
+    #[test]
+    fn discovers_repositories_from_subdirectories() {
+        let dir = std::env::temp_dir().join(format!("gitrs_discovers_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let work_tree = dir.join("project");
+        Repository::init(work_tree.clone(), &InitOptions::default()).unwrap();
+        let nested = work_tree.join("src/deeply/nested");
+        fs::create_dir_all(&nested).unwrap();
+        let work_tree = work_tree.canonicalize().unwrap();
+
+        let (root, found) = Repository::find_git_dir(&nested, &[], false).unwrap();
+        assert_eq!(
+            (root, found),
+            (work_tree.join(".git"), Some(work_tree.clone()))
+        );
+        assert!(matches!(
+            Repository::find_git_dir(&nested, &[work_tree.join("src")], false),
+            Err(Error::NotFound(_))
+        ));
+
+        // a `.git` file points at a repository kept somewhere else
+        let linked = dir.join("linked");
+        fs::create_dir_all(linked.join("sub")).unwrap();
+        fs::write(linked.join(".git"), "gitdir: ../project/.git\n").unwrap();
+        let (root, found) = Repository::find_git_dir(&linked.join("sub"), &[], false).unwrap();
+        assert_eq!(root.canonicalize().unwrap(), work_tree.join(".git"));
+        assert_eq!(found, Some(linked.canonicalize().unwrap()));
+
+        fs::write(linked.join(".git"), "gitdir: ../missing\n").unwrap();
+        assert!(matches!(
+            Repository::find_git_dir(&linked, &[], false),
+            Err(Error::NotFound(_))
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn opens_bare_repositories() {
+        let dir = std::env::temp_dir().join(format!("gitrs_opens_bare_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let root = dir.join("mirror.git");
+        fs::create_dir_all(&root).unwrap();
+        let options = InitOptions {
+            bare: true,
+            ..InitOptions::default()
+        };
+        let repo = Repository::init(root.clone(), &options).unwrap();
+        assert!(root.join("HEAD").is_file());
+        assert!(!root.join(".git").exists());
+        assert!(matches!(repo.work_tree(), Err(Error::Refused(_))));
+        assert!(Repository::is_bare(&root).unwrap());
+
+        let root = root.canonicalize().unwrap();
+        let (found, work_tree) = Repository::find_git_dir(&root.join("refs"), &[], false).unwrap();
+        assert_eq!((found, work_tree), (root, None));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn initializes_repositories_in_place() {
+        let dir = std::env::temp_dir().join(format!("gitrs_initializes_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let work_tree = dir.join("project");
+        let template = dir.join("template");
+        fs::create_dir_all(template.join("hooks")).unwrap();
+        fs::write(template.join("hooks/pre-commit"), "#!/bin/sh\n").unwrap();
+        fs::write(template.join("description"), "From the template\n").unwrap();
+        fs::write(template.join("config"), "[core]\n\tbare = true\n").unwrap();
+        fs::create_dir_all(&work_tree).unwrap();
+        fs::write(work_tree.join("README"), "existing project\n").unwrap();
+
+        let options = InitOptions {
+            initial_branch: Some("main".to_string()),
+            template: Some(template),
+            shared: Some(Shared::Group),
+            ..InitOptions::default()
+        };
+        let repo = Repository::init(work_tree.clone(), &options).unwrap();
+        let root = work_tree.join(".git");
+        assert_eq!(
+            repo.head().unwrap(),
+            Reference::Ref("refs/heads/main".to_string())
+        );
+        for dir in &["objects/pack", "refs/heads", "refs/tags", "info"] {
+            assert!(root.join(dir).is_dir(), "{}", dir);
+        }
+        assert!(root.join("hooks/pre-commit").is_file());
+        assert!(root.join("info/exclude").is_file());
+        assert_eq!(
+            fs::read_to_string(root.join("description")).unwrap(),
+            "From the template\n"
+        );
+        let config = Config::read(&root.join("config")).unwrap();
+        assert_eq!(config.get_bool("core.bare").unwrap(), Some(false));
+        assert_eq!(config.get("core.sharedrepository").unwrap().unwrap(), "1");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(root.join("refs"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o2070, 0o2070);
+        }
+
+        // initializing again fills in anything missing but leaves HEAD alone
+        fs::remove_file(root.join("info/exclude")).unwrap();
+        repo.set_head(&Reference::Ref("refs/heads/other".to_string()), "test")
+            .unwrap();
+        let repo = Repository::init(work_tree.clone(), &InitOptions::default()).unwrap();
+        assert!(root.join("info/exclude").is_file());
+        assert_eq!(
+            repo.head().unwrap(),
+            Reference::Ref("refs/heads/other".to_string())
+        );
+        assert!(work_tree.join("README").is_file());
+
+        let options = InitOptions {
+            format: Some(ObjectFormat::Sha256),
+            ..InitOptions::default()
+        };
+        assert!(matches!(
+            Repository::init(work_tree, &options),
+            Err(Error::Refused(_))
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn parses_shared_permissions() {
+        assert_eq!(Shared::parse("group").unwrap(), Shared::Group);
+        assert_eq!(Shared::parse("everybody").unwrap(), Shared::All);
+        assert_eq!(Shared::parse("false").unwrap(), Shared::Umask);
+        assert_eq!(Shared::parse("0640").unwrap(), Shared::Mode(0o640));
+        assert_eq!(Shared::Group.mode(0o644, false), 0o664);
+        assert_eq!(Shared::All.mode(0o755, true), 0o2775);
+        assert_eq!(Shared::Mode(0o640).mode(0o100755, false), 0o100750);
+        for value in &["0400", "sometimes", "0999"] {
+            assert!(
+                matches!(Shared::parse(value), Err(Error::InvalidArgument(_))),
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn round_trips_the_index() {
+        let dir = std::env::temp_dir().join(format!("gitrs_index_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let repo = Repository::init(dir.clone(), &InitOptions::default()).unwrap();
+        assert_eq!(repo.read_index().unwrap(), Index::default());
+
+        let index = Index {
+            version: 4,
+            entries: vec![IndexEntry {
+                stat: Stat::default(),
+                mode: Mode::File,
+                hash: "ea8c4bf7f35f6f77f75d92ad8ce8349f6e81ddba".parse().unwrap(),
+                stage: 0,
+                assume_valid: false,
+                skip_worktree: false,
+                intent_to_add: false,
+                path: "README.md".to_string(),
+            }],
+            extensions: vec![],
+        };
+        repo.write_index(&index).unwrap();
+        assert!(!dir.join(".git/index.lock").exists());
+        assert_eq!(repo.read_index().unwrap(), index);
+        assert_eq!(repo.rev_parse(":README.md").unwrap(), index.entries[0].hash);
+        assert!(matches!(
+            repo.rev_parse(":2:README.md"),
+            Err(Error::NotFound(_))
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_to_check_out_unsafe_trees() {
+        let dir = std::env::temp_dir().join(format!("gitrs_unsafe_trees_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let work_tree = dir.join("work");
+        let repo = Repository::init(work_tree.clone(), &InitOptions::default()).unwrap();
+        let write_blob = |content: &[u8]| {
+            repo.write_object(Object::Blob(Blob {
+                content: content.to_vec(),
+            }))
+            .unwrap()
+        };
+        let leaf = |mode, path: &str, hash| Leaf {
+            mode,
+            path: path.to_string(),
+            hash,
+        };
+        let blob = write_blob(b"escaped");
+
+        let escaping = Tree {
+            leaves: vec![leaf(Mode::File, "..", blob)],
+        };
+        assert!(matches!(
+            repo.checkout_tree(escaping, &work_tree),
+            Err(Error::Corrupt(_))
+        ));
+
+        if cfg!(unix) {
+            let inner = repo
+                .write_object(Object::Tree(Tree {
+                    leaves: vec![leaf(Mode::File, "escaped", blob)],
+                }))
+                .unwrap();
+            let target = write_blob(dir.to_string_lossy().as_bytes());
+            let linked = Tree {
+                leaves: vec![
+                    leaf(Mode::Symlink, "link", target),
+                    leaf(Mode::Directory, "link", inner),
+                ],
+            };
+            assert!(matches!(
+                repo.checkout_tree(linked, &work_tree),
+                Err(Error::Refused(_))
+            ));
+            assert!(!dir.join("escaped").exists());
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn logs_references_following_config() {
+        let dir = std::env::temp_dir().join(format!("gitrs_log_config_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let options = InitOptions {
+            bare: true,
+            ..InitOptions::default()
+        };
+        let repo = Repository::init(dir.clone(), &options).unwrap();
+        let tree = repo
+            .write_object(Object::Tree(Tree { leaves: vec![] }))
+            .unwrap();
+        let commit = Reference::Commit(write_commit(&repo, tree, &[], 1, "First"));
+        let logged = |name: &str| {
+            repo.write_ref(name, &commit, "test").unwrap();
+            !repo.refs.log(name).unwrap().is_empty()
+        };
+        assert!(!logged("refs/heads/bare"));
+
+        let set = |value: &str| {
+            let path = dir.join("config");
+            let mut config = Config::read(&path).unwrap();
+            config.set("core.logAllRefUpdates", value).unwrap();
+            config.write(&path).unwrap();
+        };
+        set("true");
+        assert!(logged("refs/heads/master"));
+        assert!(!logged("refs/tags/v1"));
+        set("always");
+        assert!(logged("refs/tags/v2"));
+        set("false");
+        assert!(!logged("refs/heads/topic"));
+        assert!(logged("refs/heads/master"));
+        fs::remove_dir_all(dir).unwrap();
     }
 }
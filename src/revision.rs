@@ -0,0 +1,285 @@
+use crate::error::{Error, Result};
+
+/// A parsed git revision expression, e.g. `master~2`, `v1.0^{tree}` or `HEAD:src/lib.rs`.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Revision {
+    /// A reference or object id looked up in git's usual order, e.g. `master`, `v1.0` or `HEAD`.
+    Name(String),
+    /// `@{-n}`, the branch or commit that was checked out `n` checkouts ago.
+    PreviousCheckout(usize),
+    /// `name@{n}` or `name@{date}`, an earlier value of a reference read from its log, where an
+    /// empty name means the current branch.
+    Reflog(String, ReflogSelector),
+    /// `:/pattern`, the youngest commit reachable from any reference whose message matches.
+    MessageSearch(String),
+    /// `rev~n`, following first parents `n` times.
+    Ancestor(Box<Revision>, usize),
+    /// `rev^n`, the `n`th parent, where `rev^0` is the commit itself.
+    Parent(Box<Revision>, usize),
+    /// `rev^{kind}`, peeling until reaching an object of that kind or, for `rev^{}`, a non-tag.
+    Peel(Box<Revision>, String),
+    /// `rev:path`, the object at a path within the revision's tree.
+    Path(Box<Revision>, String),
+    /// `:n:path`, the object staged at a path in the index, where `:path` reads stage 0.
+    IndexPath(u8, String),
+}
+
+/// Which entry of a reference's log to read.
+#[derive(Debug, PartialEq, Clone)]
+pub enum ReflogSelector {
+    /// The value `n` updates ago, where `0` is the current value.
+    Entry(usize),
+    /// The value as of a date like `yesterday` or `2020-01-31`.
+    Date(String),
+}
+
+impl Revision {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let invalid = || Error::InvalidArgument(format!("{} is not a valid revision.", spec));
+        if let Some(pattern) = spec.strip_prefix(":/") {
+            return Ok(Revision::MessageSearch(pattern.to_string()));
+        }
+        if let Some(rest) = spec.strip_prefix(':') {
+            let (stage, path) = match rest.as_bytes() {
+                [stage @ b'0'..=b'3', b':', ..] => (stage - b'0', &rest[2..]),
+                _ => (0, rest),
+            };
+            if path.is_empty() {
+                return Err(invalid());
+            }
+            return Ok(Revision::IndexPath(stage, path.to_string()));
+        }
+
+        // a colon inside braces, e.g. in `@{1:00}`, doesn't start a path
+        let mut depth = 0;
+        for (i, c) in spec.char_indices() {
+            match c {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                ':' if depth == 0 => {
+                    let base = Revision::parse_suffixes(&spec[..i]).ok_or_else(invalid)?;
+                    return Ok(Revision::Path(Box::new(base), spec[i + 1..].to_string()));
+                }
+                _ => {}
+            }
+        }
+        Revision::parse_suffixes(spec).ok_or_else(invalid)
+    }
+
+    fn parse_suffixes(spec: &str) -> Option<Self> {
+        let end = spec.find(['~', '^']).unwrap_or(spec.len());
+        let mut revision = Revision::parse_name(&spec[..end])?;
+        let mut rest = &spec[end..];
+        while !rest.is_empty() {
+            let (operator, tail) = rest.split_at(1);
+            if operator == "^" && tail.starts_with('{') {
+                let close = tail.find('}')?;
+                let kind = &tail[1..close];
+                revision = Revision::Peel(Box::new(revision), kind.to_string());
+                rest = &tail[close + 1..];
+                continue;
+            }
+            let digits = tail
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(tail.len());
+            // a bare `~` or `^` is the same as `~1` or `^1`
+            let count = match &tail[..digits] {
+                "" => 1,
+                number => number.parse().ok()?,
+            };
+            revision = match operator {
+                "~" => Revision::Ancestor(Box::new(revision), count),
+                "^" => Revision::Parent(Box::new(revision), count),
+                _ => return None,
+            };
+            rest = &tail[digits..];
+        }
+        Some(revision)
+    }
+
+    fn parse_name(name: &str) -> Option<Self> {
+        if name.is_empty() {
+            return None;
+        }
+        if name == "@" {
+            return Some(Revision::Name("HEAD".to_string()));
+        }
+        let (base, selector) = match name.find("@{") {
+            Some(open) => (&name[..open], name[open + 2..].strip_suffix('}')?),
+            None => return Some(Revision::Name(name.to_string())),
+        };
+        if let Some(count) = selector.strip_prefix('-') {
+            if !base.is_empty() {
+                return None;
+            }
+            return count
+                .parse()
+                .ok()
+                .filter(|n| *n > 0)
+                .map(Revision::PreviousCheckout);
+        }
+        let selector = match selector.parse() {
+            Ok(n) => ReflogSelector::Entry(n),
+            Err(_) if !selector.is_empty() => ReflogSelector::Date(selector.to_string()),
+            Err(_) => return None,
+        };
+        Some(Revision::Reflog(base.to_string(), selector))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::revision::{ReflogSelector, Revision};
+
+    fn name(name: &str) -> Box<Revision> {
+        Box::new(Revision::Name(name.to_string()))
+    }
+
+    #[test]
+    fn parses_names() {
+        assert_eq!(Revision::parse("master").unwrap(), *name("master"));
+        assert_eq!(Revision::parse("@").unwrap(), *name("HEAD"));
+        assert_eq!(
+            Revision::parse("refs/heads/feature/x").unwrap(),
+            *name("refs/heads/feature/x")
+        );
+    }
+
+    #[test]
+    fn parses_ancestors_and_parents() {
+        assert_eq!(
+            Revision::parse("HEAD~3").unwrap(),
+            Revision::Ancestor(name("HEAD"), 3)
+        );
+        assert_eq!(
+            Revision::parse("main^2").unwrap(),
+            Revision::Parent(name("main"), 2)
+        );
+        assert_eq!(
+            Revision::parse("main^~").unwrap(),
+            Revision::Ancestor(Box::new(Revision::Parent(name("main"), 1)), 1)
+        );
+        assert_eq!(
+            Revision::parse("main^0").unwrap(),
+            Revision::Parent(name("main"), 0)
+        );
+    }
+
+    #[test]
+    fn parses_peeling() {
+        assert_eq!(
+            Revision::parse("v1.0^{tree}").unwrap(),
+            Revision::Peel(name("v1.0"), "tree".to_string())
+        );
+        assert_eq!(
+            Revision::parse("v1.0^{}~2").unwrap(),
+            Revision::Ancestor(Box::new(Revision::Peel(name("v1.0"), "".to_string())), 2)
+        );
+    }
+
+    #[test]
+    fn parses_previous_checkouts() {
+        assert_eq!(
+            Revision::parse("@{-1}").unwrap(),
+            Revision::PreviousCheckout(1)
+        );
+        assert_eq!(
+            Revision::parse("@{-2}~1").unwrap(),
+            Revision::Ancestor(Box::new(Revision::PreviousCheckout(2)), 1)
+        );
+    }
+
+    #[test]
+    fn parses_reflog_selectors() {
+        assert_eq!(
+            Revision::parse("master@{2}").unwrap(),
+            Revision::Reflog("master".to_string(), ReflogSelector::Entry(2))
+        );
+        assert_eq!(
+            Revision::parse("@{1}~1").unwrap(),
+            Revision::Ancestor(
+                Box::new(Revision::Reflog("".to_string(), ReflogSelector::Entry(1))),
+                1
+            )
+        );
+        assert_eq!(
+            Revision::parse("HEAD@{2020-01-31 12:00:00}:src").unwrap(),
+            Revision::Path(
+                Box::new(Revision::Reflog(
+                    "HEAD".to_string(),
+                    ReflogSelector::Date("2020-01-31 12:00:00".to_string())
+                )),
+                "src".to_string()
+            )
+        );
+        assert_eq!(
+            Revision::parse("@{yesterday}").unwrap(),
+            Revision::Reflog(
+                "".to_string(),
+                ReflogSelector::Date("yesterday".to_string())
+            )
+        );
+    }
+
+    #[test]
+    fn parses_message_searches() {
+        assert_eq!(
+            Revision::parse(":/fix: the bug").unwrap(),
+            Revision::MessageSearch("fix: the bug".to_string())
+        );
+    }
+
+    #[test]
+    fn parses_index_paths() {
+        assert_eq!(
+            Revision::parse(":README").unwrap(),
+            Revision::IndexPath(0, "README".to_string())
+        );
+        assert_eq!(
+            Revision::parse(":3:src/lib.rs").unwrap(),
+            Revision::IndexPath(3, "src/lib.rs".to_string())
+        );
+        assert_eq!(
+            Revision::parse(":4:x").unwrap(),
+            Revision::IndexPath(0, "4:x".to_string())
+        );
+    }
+
+    #[test]
+    fn parses_paths() {
+        assert_eq!(
+            Revision::parse("HEAD~1:src/lib.rs").unwrap(),
+            Revision::Path(
+                Box::new(Revision::Ancestor(name("HEAD"), 1)),
+                "src/lib.rs".to_string()
+            )
+        );
+        assert_eq!(
+            Revision::parse("master:").unwrap(),
+            Revision::Path(name("master"), "".to_string())
+        );
+    }
+
+    #[test]
+    fn fails_to_parse_malformed_revisions() {
+        for spec in &[
+            "",
+            "~1",
+            "HEAD~x",
+            "HEAD^{tree",
+            "@{-0}",
+            ":",
+            ":2:",
+            "master@{-1}",
+            "@{}",
+            "@{1",
+        ] {
+            assert!(
+                matches!(Revision::parse(spec), Err(Error::InvalidArgument(_))),
+                "{}",
+                spec
+            );
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::HashSet;
+use std::io::Cursor;
+use std::path::PathBuf;
+
+use crate::error::{Error, Result};
+use crate::object::id::{ObjectFormat, ObjectId};
+use crate::pack::Entry;
+use crate::reference::log::LogEntry;
+use crate::reference::Reference;
+use crate::stream::{ObjectReader, ObjectWriter};
+
+pub mod file;
+pub mod memory;
+
+/// Somewhere objects can be read from and written to by id.
+pub trait ObjectStore {
+    fn format(&self) -> ObjectFormat;
+
+    fn contains(&self, hash: &ObjectId) -> Result<bool>;
+
+    /// Reads an object's kind and content.
+    fn read(&self, hash: &ObjectId) -> Result<(String, Vec<u8>)>;
+
+    /// Opens a stream over an object's content, which stores should override if they can avoid
+    /// reading it all up front.
+    fn open(&self, hash: &ObjectId) -> Result<ObjectReader> {
+        let (kind, content) = self.read(hash)?;
+        Ok(ObjectReader::new(
+            kind,
+            content.len(),
+            Box::new(Cursor::new(content)),
+        ))
+    }
+
+    /// Writes an object's content, returning the id it's stored under.
+    fn write(&self, kind: &str, content: &[u8]) -> Result<ObjectId>;
+
+    /// Creates a sink for an object of the given kind and size that's written as it's streamed.
+    fn create(&self, kind: &str, size: usize) -> Result<ObjectWriter<'_>>;
+
+    /// Lists the id of every object in the store in order.
+    fn hashes(&self) -> Result<Vec<ObjectId>>;
+
+    /// Lists the ids of objects that start with the given lower case hex prefix, which stores
+    /// should override if they can avoid listing everything.
+    fn find_prefix(&self, prefix: &str) -> Result<Vec<ObjectId>> {
+        Ok(self
+            .hashes()?
+            .into_iter()
+            .filter(|h| h.to_hex().starts_with(prefix))
+            .collect())
+    }
+
+    /// Packs the entries together and drops anything that's made redundant, returning where the
+    /// pack was written if the store keeps packs at all.
+    fn repack(&self, _entries: Vec<Entry>) -> Result<Option<PathBuf>> {
+        Ok(None)
+    }
+}
+
+/// What a transaction does to a reference.
+#[derive(Debug, PartialEq, Clone)]
+pub enum RefChange {
+    Write(Reference),
+    /// Removes the reference along with its log.
+    Delete,
+    /// Leaves the reference alone, so the update only checks its expected value.
+    Verify,
+}
+
+/// A change to one reference within a transaction.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RefUpdate {
+    pub name: String,
+    pub change: RefChange,
+    /// The value the reference must have for the transaction to go ahead, where `None` skips the
+    /// check and `Some(None)` requires that the reference doesn't exist.
+    pub expected: Option<Option<Reference>>,
+    /// An entry for the reference's log, which is written while the reference is still locked so
+    /// the log can't miss the change.
+    pub log: Option<LogEntry>,
+}
+
+impl RefUpdate {
+    pub fn new(name: &str, change: RefChange) -> Self {
+        Self {
+            name: name.to_string(),
+            change,
+            expected: None,
+            log: None,
+        }
+    }
+
+    pub fn expecting(mut self, expected: Option<Reference>) -> Self {
+        self.expected = Some(expected);
+        self
+    }
+
+    pub fn logging(mut self, entry: LogEntry) -> Self {
+        self.log = Some(entry);
+        self
+    }
+
+    /// Checks the reference's current value against what the update expects.
+    pub fn check(&self, current: Option<&Reference>) -> Result<()> {
+        match &self.expected {
+            Some(expected) if expected.as_ref() != current => Err(Error::Refused(format!(
+                "Reference {} is {} but was expected to be {}.",
+                self.name,
+                describe(current),
+                describe(expected.as_ref())
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that a reference can be updated by name, which must be a valid name under `refs/`
+    /// or an all-caps pseudo-reference like `HEAD` or `ORIG_HEAD`.
+    pub fn check_name(name: &str) -> Result<()> {
+        Reference::check_name(name)?;
+        let pseudo = name.starts_with(|c: char| c.is_ascii_uppercase())
+            && name.chars().all(|c| c.is_ascii_uppercase() || c == '_');
+        if name.starts_with("refs/") || pseudo {
+            Ok(())
+        } else {
+            Err(Error::InvalidRef(format!(
+                "{} is not a reference that can be updated.",
+                name
+            )))
+        }
+    }
+
+    /// Checks that a set of updates can be applied together before anything is locked.
+    pub fn validate(updates: &[RefUpdate]) -> Result<()> {
+        let mut names = HashSet::new();
+        for update in updates {
+            Self::check_name(&update.name)?;
+            if !names.insert(&update.name) {
+                return Err(Error::InvalidArgument(format!(
+                    "Reference {} can only be updated once per transaction.",
+                    update.name
+                )));
+            }
+            if let RefChange::Write(value) = &update.change {
+                value.serialize()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn describe(value: Option<&Reference>) -> String {
+    match value {
+        Some(Reference::Commit(hash)) => hash.to_string(),
+        Some(Reference::Ref(name)) => format!("ref: {}", name),
+        Some(Reference::Head) => "HEAD".to_string(),
+        None => "missing".to_string(),
+    }
+}
+
+/// Somewhere references can be read from and written to by name, e.g. `HEAD` or `refs/heads/master`.
+pub trait RefStore {
+    /// Reads the value of a reference without following it, or `None` if it doesn't exist.
+    fn read(&self, name: &str) -> Result<Option<Reference>>;
+
+    /// Applies every update or, if any reference doesn't have its expected value, none of them.
+    fn transaction(&self, updates: &[RefUpdate]) -> Result<()>;
+
+    fn write(&self, name: &str, value: &Reference) -> Result<()> {
+        self.transaction(&[RefUpdate::new(name, RefChange::Write(value.clone()))])
+    }
+
+    /// Deletes a reference, returning whether it existed.
+    fn delete(&self, name: &str) -> Result<bool> {
+        let current = self.read(name)?;
+        if current.is_some() {
+            // expecting the value we just read stops us deleting a newer one
+            self.transaction(&[RefUpdate::new(name, RefChange::Delete).expecting(current)])?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Lists the name of every reference under `refs/` in order.
+    fn list(&self) -> Result<Vec<String>>;
+
+    /// Reads a reference's log, oldest entry first, which is empty if it has never been logged.
+    fn log(&self, name: &str) -> Result<Vec<LogEntry>>;
+
+    /// Adds an entry to the end of a reference's log, creating the log if it doesn't exist.
+    fn append_log(&self, name: &str, entry: &LogEntry) -> Result<()>;
+
+    /// Replaces every entry in a reference's log, e.g. when it's moved to a new name.
+    fn write_log(&self, name: &str, entries: &[LogEntry]) -> Result<()>;
+
+    /// Drops the entries of a reference's log that aren't to be kept, reading it only once the
+    /// reference is locked so entries added by concurrent updates aren't lost.
+    fn expire_log(&self, name: &str, keep: &dyn Fn(&LogEntry) -> bool) -> Result<()>;
+
+    /// Deletes a reference's log, e.g. once the reference itself is deleted.
+    fn delete_log(&self, name: &str) -> Result<()>;
+
+    /// Reads the object a reference peels to if the store has it recorded, saving a caller from
+    /// reading through its tags.
+    fn peeled(&self, _name: &str) -> Result<Option<ObjectId>> {
+        Ok(None)
+    }
+
+    /// Packs the references matching the filter together, recording what each of them peels to,
+    /// and returns how many were packed if the store packs references at all.
+    fn pack(
+        &self,
+        _filter: &dyn Fn(&str) -> bool,
+        _peel: &dyn Fn(&ObjectId) -> Result<ObjectId>,
+    ) -> Result<usize> {
+        Ok(0)
+    }
+}
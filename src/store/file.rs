@@ -0,0 +1,867 @@
+use std::collections::HashSet;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::error::{Error, Result};
+use crate::object::id::{ObjectFormat, ObjectId};
+use crate::object::Object;
+use crate::pack::{Entry, Pack};
+use crate::reference::log::LogEntry;
+use crate::reference::packed::{PackedRef, PackedRefs};
+use crate::reference::Reference;
+use crate::store::{ObjectStore, RefChange, RefStore, RefUpdate};
+use crate::stream::{ObjectReader, ObjectSink, ObjectWriter};
+
+const PACK_DIR: &str = "pack";
+const REFS_DIR: &str = "refs";
+const LOGS_DIR: &str = "logs";
+const PACKED_REFS_FILE: &str = "packed-refs";
+const LOCK_SUFFIX: &str = ".lock";
+
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Objects kept as loose zlib files under `objects/xx/` along with any packs in `objects/pack`.
+pub struct FileObjectStore {
+    pub dir: PathBuf,
+    format: ObjectFormat,
+    packs: Vec<Pack>,
+}
+
+impl FileObjectStore {
+    pub fn open(dir: PathBuf, format: ObjectFormat) -> Result<Self> {
+        let packs = Pack::open_all(&dir.join(PACK_DIR), format)?;
+        Ok(Self { dir, format, packs })
+    }
+
+    pub fn hash_to_path(hash: &ObjectId) -> PathBuf {
+        let hex = hash.to_hex();
+        let (dir, file) = hex.split_at(2);
+        Path::new(dir).join(file)
+    }
+
+    fn loose_path(&self, hash: &ObjectId) -> PathBuf {
+        self.dir.join(FileObjectStore::hash_to_path(hash))
+    }
+
+    fn read_zlib(path: PathBuf) -> Result<Vec<u8>> {
+        let file = File::open(&path)?;
+        let mut decoder = ZlibDecoder::new(BufReader::new(&file));
+        let mut bytes = vec![];
+        decoder
+            .read_to_end(&mut bytes)
+            .map_err(|_| Error::Corrupt(format!("Unable to inflate {}.", path.display())))?;
+        Ok(bytes)
+    }
+
+    fn read_at_depth(&self, hash: &ObjectId, depth: usize) -> Result<(String, Vec<u8>)> {
+        let path = self.loose_path(hash);
+        if path.exists() {
+            let bytes = FileObjectStore::read_zlib(path)?;
+            let (kind, content) = Object::deserialize_header(&bytes)?;
+            return Ok((kind, content.to_vec()));
+        }
+        let (pack, offset) = self.find_packed(hash)?;
+        pack.read(offset, depth, &|base, depth| {
+            self.read_at_depth(base, depth)
+        })
+    }
+
+    fn find_packed(&self, hash: &ObjectId) -> Result<(&Pack, u64)> {
+        self.packs
+            .iter()
+            .find_map(|p| p.find(hash).map(|offset| (p, offset)))
+            .ok_or_else(|| Error::NotFound(format!("Object {}", hash)))
+    }
+}
+
+impl ObjectStore for FileObjectStore {
+    fn format(&self) -> ObjectFormat {
+        self.format
+    }
+
+    fn contains(&self, hash: &ObjectId) -> Result<bool> {
+        Ok(self.loose_path(hash).exists() || self.packs.iter().any(|p| p.find(hash).is_some()))
+    }
+
+    fn read(&self, hash: &ObjectId) -> Result<(String, Vec<u8>)> {
+        self.read_at_depth(hash, 0)
+    }
+
+    fn open(&self, hash: &ObjectId) -> Result<ObjectReader> {
+        let path = self.loose_path(hash);
+        if path.exists() {
+            return ObjectReader::loose(&path);
+        }
+        let (pack, offset) = self.find_packed(hash)?;
+        match pack.stream(offset)? {
+            Some((kind, size, body)) => Ok(ObjectReader::new(kind, size, Box::new(body))),
+            None => {
+                let (kind, content) =
+                    pack.read(offset, 0, &|base, depth| self.read_at_depth(base, depth))?;
+                Ok(ObjectReader::new(
+                    kind,
+                    content.len(),
+                    Box::new(io::Cursor::new(content)),
+                ))
+            }
+        }
+    }
+
+    fn write(&self, kind: &str, content: &[u8]) -> Result<ObjectId> {
+        let bytes = [
+            Object::serialize_header(kind, content.len()),
+            content.to_vec(),
+        ]
+        .concat();
+        let hash = self.format.hash(&bytes);
+        // objects are immutable so there's nothing to gain from writing one twice
+        if !self.contains(&hash)? {
+            let mut sink = Box::new(LooseSink::create(&self.dir, kind, content.len())?);
+            sink.write_all(content)?;
+            sink.commit(&hash)?;
+        }
+        Ok(hash)
+    }
+
+    fn create(&self, kind: &str, size: usize) -> Result<ObjectWriter<'_>> {
+        let sink = LooseSink::create(&self.dir, kind, size)?;
+        Ok(ObjectWriter::new(self.format, kind, size, Box::new(sink)))
+    }
+
+    fn hashes(&self) -> Result<Vec<ObjectId>> {
+        let mut hashes = HashSet::new();
+        if self.dir.is_dir() {
+            for fanout in self.dir.read_dir()? {
+                let fanout = fanout?;
+                let prefix = fanout.file_name().to_string_lossy().to_string();
+                if prefix.len() != 2 || !fanout.path().is_dir() {
+                    continue;
+                }
+                for entry in fanout.path().read_dir()? {
+                    let name = entry?.file_name().to_string_lossy().to_string();
+                    // temporary files and other strays aren't valid ids so they're skipped
+                    if let Ok(hash) = ObjectId::from_hex(&format!("{}{}", prefix, name)) {
+                        hashes.insert(hash);
+                    }
+                }
+            }
+        }
+        for pack in &self.packs {
+            hashes.extend(pack.hashes().cloned());
+        }
+        let mut hashes: Vec<ObjectId> = hashes.into_iter().collect();
+        hashes.sort();
+        Ok(hashes)
+    }
+
+    fn find_prefix(&self, prefix: &str) -> Result<Vec<ObjectId>> {
+        // only the one fan-out directory can hold loose objects with this prefix
+        if prefix.len() < 2 {
+            let hashes = self.hashes()?;
+            return Ok(hashes
+                .into_iter()
+                .filter(|h| h.to_hex().starts_with(prefix))
+                .collect());
+        }
+        let (fanout, rest) = prefix.split_at(2);
+        let mut hashes = HashSet::new();
+        let dir = self.dir.join(fanout);
+        if dir.is_dir() {
+            for entry in dir.read_dir()? {
+                let name = entry?.file_name().to_string_lossy().to_string();
+                if !name.starts_with(rest) {
+                    continue;
+                }
+                if let Ok(hash) = ObjectId::from_hex(&format!("{}{}", fanout, name)) {
+                    hashes.insert(hash);
+                }
+            }
+        }
+        for pack in &self.packs {
+            hashes.extend(pack.find_prefix(prefix));
+        }
+        let mut hashes: Vec<ObjectId> = hashes.into_iter().collect();
+        hashes.sort();
+        Ok(hashes)
+    }
+
+    fn repack(&self, entries: Vec<Entry>) -> Result<Option<PathBuf>> {
+        let hashes: Vec<ObjectId> = entries.iter().map(|e| e.hash).collect();
+        let read = |hash: &ObjectId| self.read(hash).map(|(_, content)| content);
+        let pack = Pack::write(&self.dir.join(PACK_DIR), entries, self.format, &read)?;
+
+        let packed: HashSet<&ObjectId> = hashes.iter().collect();
+        for old in &self.packs {
+            if old.path != pack.path && old.hashes().all(|h| packed.contains(&h)) {
+                old.remove()?;
+            }
+        }
+
+        for hash in &hashes {
+            let path = self.loose_path(hash);
+            if path.exists() {
+                fs::remove_file(&path)?;
+                // this only succeeds once the fan-out directory is empty
+                let _ = fs::remove_dir(path.parent().unwrap());
+            }
+        }
+        Ok(Some(pack.path))
+    }
+}
+
+struct LooseSink {
+    temp: Option<PathBuf>,
+    objects: PathBuf,
+    encoder: ZlibEncoder<BufWriter<File>>,
+}
+
+impl LooseSink {
+    fn create(objects: &Path, kind: &str, size: usize) -> Result<Self> {
+        fs::create_dir_all(objects)?;
+        let temp = objects.join(format!(
+            "tmp_obj_{}_{}",
+            std::process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        let file = File::create(&temp)?;
+        let mut encoder = ZlibEncoder::new(BufWriter::new(file), Compression::default());
+        encoder.write_all(&Object::serialize_header(kind, size))?;
+        Ok(Self {
+            temp: Some(temp),
+            objects: objects.to_path_buf(),
+            encoder,
+        })
+    }
+}
+
+impl Write for LooseSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.encoder.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.encoder.flush()
+    }
+}
+
+impl ObjectSink for LooseSink {
+    fn commit(mut self: Box<Self>, hash: &ObjectId) -> Result<()> {
+        self.encoder.try_finish()?;
+        self.encoder.get_mut().flush()?;
+        let file = self.encoder.get_ref().get_ref();
+        // the content has to reach the disk before the rename does or a crash could
+        // leave an empty object behind under a valid name
+        file.sync_all()?;
+        // git makes objects read-only so that nothing edits them in place by accident
+        let mut permissions = file.metadata()?.permissions();
+        permissions.set_readonly(true);
+        file.set_permissions(permissions)?;
+
+        let path = self.objects.join(FileObjectStore::hash_to_path(hash));
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // objects are immutable so if another writer beat us to it their copy is as good
+        // as ours, and the temporary file is cleaned up on drop either way
+        if !path.exists() {
+            if let Err(e) = fs::rename(self.temp.as_ref().unwrap(), &path) {
+                if !path.exists() {
+                    return Err(e.into());
+                }
+            }
+            self.temp = None;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for LooseSink {
+    // a sink that's abandoned part way through shouldn't leave its temporary file behind
+    fn drop(&mut self) {
+        if let Some(temp) = self.temp.take() {
+            let _ = fs::remove_file(temp);
+        }
+    }
+}
+
+pub(crate) struct LockFile {
+    path: PathBuf,
+    lock: Option<PathBuf>,
+    file: File,
+}
+
+impl LockFile {
+    pub(crate) fn acquire(path: PathBuf) -> Result<Self> {
+        let mut lock = path.clone().into_os_string();
+        lock.push(LOCK_SUFFIX);
+        let lock = PathBuf::from(lock);
+        match OpenOptions::new().write(true).create_new(true).open(&lock) {
+            Ok(file) => Ok(Self {
+                path,
+                lock: Some(lock),
+                file,
+            }),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => Err(Error::Refused(format!(
+                "{} is locked by another process.",
+                path.display()
+            ))),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub(crate) fn write(&mut self, content: &[u8]) -> Result<()> {
+        self.file.write_all(content)?;
+        self.file.sync_all()?;
+        Ok(())
+    }
+
+    pub(crate) fn commit(mut self) -> Result<()> {
+        fs::rename(self.lock.as_ref().unwrap(), &self.path)?;
+        self.lock = None;
+        Ok(())
+    }
+}
+
+impl Drop for LockFile {
+    // an abandoned update has to release its lock or every later one would be refused
+    fn drop(&mut self) {
+        if let Some(lock) = self.lock.take() {
+            let _ = fs::remove_file(lock);
+        }
+    }
+}
+
+/// References kept as individual files under a git directory, falling back to `packed-refs` for
+/// any that don't have a file of their own.
+pub struct FileRefStore {
+    pub root: PathBuf,
+}
+
+impl FileRefStore {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    fn read_loose(&self, name: &str) -> Result<Option<Reference>> {
+        // names come from users so anything that could step outside the repository is refused
+        Reference::check_name(name)?;
+        match fs::read_to_string(self.root.join(name)) {
+            Ok(content) => Reference::from_file(content.as_str()).map(Some),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn log_path(&self, name: &str) -> Result<PathBuf> {
+        Reference::check_name(name)?;
+        Ok(self.root.join(LOGS_DIR).join(name))
+    }
+
+    fn list_loose(&self) -> Result<Vec<String>> {
+        fn walk(dir: &Path, refs: &mut Vec<PathBuf>) -> Result<()> {
+            if dir.is_dir() {
+                for entry in dir.read_dir()? {
+                    let path = entry?.path();
+                    if path.is_dir() {
+                        walk(&path, refs)?;
+                    } else if !path.to_string_lossy().ends_with(LOCK_SUFFIX) {
+                        // skipping locks hides refs that are still being written
+                        refs.push(path);
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        let mut paths = vec![];
+        walk(&self.root.join(REFS_DIR), &mut paths)?;
+        Ok(paths
+            .iter()
+            .filter_map(|p| p.strip_prefix(&self.root).ok())
+            .map(|name| name.to_string_lossy().replace('\\', "/"))
+            .collect())
+    }
+
+    fn remove_loose(&self, name: &str) -> Result<bool> {
+        let path = self.root.join(name);
+        match fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        }
+        self.prune_dirs(&path, &self.root.join(REFS_DIR));
+        Ok(true)
+    }
+
+    fn prune_dirs(&self, path: &Path, top: &Path) {
+        let mut dir = path.parent();
+        while let Some(d) = dir {
+            if d == top || d.parent() == Some(top) || fs::remove_dir(d).is_err() {
+                break;
+            }
+            dir = d.parent();
+        }
+    }
+
+    fn read_packed(&self) -> Result<PackedRefs> {
+        match fs::read_to_string(self.root.join(PACKED_REFS_FILE)) {
+            Ok(content) => PackedRefs::deserialize(&content),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(PackedRefs::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn update_packed(&self, update: impl FnOnce(&mut PackedRefs) -> Result<()>) -> Result<()> {
+        let mut lock = LockFile::acquire(self.root.join(PACKED_REFS_FILE))?;
+        let mut packed = self.read_packed()?;
+        update(&mut packed)?;
+        lock.write(packed.serialize().as_bytes())?;
+        lock.commit()
+    }
+}
+
+impl RefStore for FileRefStore {
+    fn read(&self, name: &str) -> Result<Option<Reference>> {
+        match self.read_loose(name)? {
+            Some(reference) => Ok(Some(reference)),
+            None => Ok(self
+                .read_packed()?
+                .refs
+                .get(name)
+                .map(|packed| Reference::Commit(packed.hash))),
+        }
+    }
+
+    fn transaction(&self, updates: &[RefUpdate]) -> Result<()> {
+        RefUpdate::validate(updates)?;
+        let mut locks = vec![];
+        for update in updates {
+            let path = self.root.join(&update.name);
+            if path.is_dir() {
+                return Err(Error::Refused(format!(
+                    "Reference {} would clash with the references below it.",
+                    update.name
+                )));
+            }
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            locks.push(LockFile::acquire(path)?);
+        }
+        // deleting a packed ref rewrites `packed-refs` so it has to be locked too
+        let mut packed = None;
+        if updates.iter().any(|u| u.change == RefChange::Delete) {
+            let lock = LockFile::acquire(self.root.join(PACKED_REFS_FILE))?;
+            packed = Some((lock, self.read_packed()?));
+        }
+
+        for update in updates {
+            update.check(self.read(&update.name)?.as_ref())?;
+        }
+        for (update, lock) in updates.iter().zip(locks.iter_mut()) {
+            if let RefChange::Write(value) = &update.change {
+                lock.write(value.serialize()?.as_bytes())?;
+            }
+        }
+        // like git the logs are written before anything is committed, while nobody else can
+        // update the references they belong to
+        for update in updates {
+            if let Some(entry) = &update.log {
+                self.append_log(&update.name, entry)?;
+            }
+        }
+
+        if let Some((mut lock, mut packed)) = packed {
+            let count = packed.refs.len();
+            for update in updates.iter().filter(|u| u.change == RefChange::Delete) {
+                packed.refs.remove(&update.name);
+            }
+            if packed.refs.len() != count {
+                lock.write(packed.serialize().as_bytes())?;
+                lock.commit()?;
+            }
+        }
+        for (update, lock) in updates.iter().zip(locks) {
+            match update.change {
+                RefChange::Write(_) => lock.commit()?,
+                RefChange::Delete => {
+                    let path = self.root.join(&update.name);
+                    match fs::remove_file(&path) {
+                        Ok(()) => {}
+                        Err(e) if e.kind() == ErrorKind::NotFound => {}
+                        Err(e) => return Err(e.into()),
+                    }
+                    self.delete_log(&update.name)?;
+                    // the lock has to go before its directory can be pruned
+                    drop(lock);
+                    self.prune_dirs(&path, &self.root.join(REFS_DIR));
+                }
+                RefChange::Verify => {
+                    drop(lock);
+                    self.prune_dirs(&self.root.join(&update.name), &self.root.join(REFS_DIR));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        let mut names = self.list_loose()?;
+        names.extend(self.read_packed()?.refs.into_keys());
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+
+    fn log(&self, name: &str) -> Result<Vec<LogEntry>> {
+        match fs::read_to_string(self.log_path(name)?) {
+            Ok(content) => LogEntry::deserialize_all(&content),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(vec![]),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn append_log(&self, name: &str, entry: &LogEntry) -> Result<()> {
+        let path = self.log_path(name)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // appends of a single line are atomic so concurrent writers can't interleave
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        file.write_all(entry.serialize().as_bytes())?;
+        Ok(())
+    }
+
+    fn write_log(&self, name: &str, entries: &[LogEntry]) -> Result<()> {
+        let content: String = entries.iter().map(LogEntry::serialize).collect();
+        let mut lock = LockFile::acquire(self.log_path(name)?)?;
+        lock.write(content.as_bytes())?;
+        lock.commit()
+    }
+
+    fn expire_log(&self, name: &str, keep: &dyn Fn(&LogEntry) -> bool) -> Result<()> {
+        // appends happen under the reference's lock so holding it keeps the log still
+        Reference::check_name(name)?;
+        let path = self.root.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let lock = LockFile::acquire(path.clone())?;
+        let log = self.log(name)?;
+        let kept: Vec<_> = log.iter().filter(|entry| keep(entry)).cloned().collect();
+        if kept.len() != log.len() {
+            self.write_log(name, &kept)?;
+        }
+        drop(lock);
+        self.prune_dirs(&path, &self.root.join(REFS_DIR));
+        Ok(())
+    }
+
+    fn delete_log(&self, name: &str) -> Result<()> {
+        let path = self.log_path(name)?;
+        match fs::remove_file(&path) {
+            Ok(()) => {
+                self.prune_dirs(&path, &self.root.join(LOGS_DIR).join(REFS_DIR));
+                Ok(())
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn peeled(&self, name: &str) -> Result<Option<ObjectId>> {
+        // a loose ref takes precedence so whatever was packed for it may be stale
+        if self.read_loose(name)?.is_some() {
+            return Ok(None);
+        }
+        Ok(self
+            .read_packed()?
+            .refs
+            .get(name)
+            .and_then(|packed| packed.peeled))
+    }
+
+    fn pack(
+        &self,
+        filter: &dyn Fn(&str) -> bool,
+        peel: &dyn Fn(&ObjectId) -> Result<ObjectId>,
+    ) -> Result<usize> {
+        let mut moved = vec![];
+        self.update_packed(|packed| {
+            for name in self.list_loose()? {
+                if let (true, Some(Reference::Commit(hash))) =
+                    (filter(&name), self.read_loose(&name)?)
+                {
+                    packed
+                        .refs
+                        .insert(name.clone(), PackedRef { hash, peeled: None });
+                    moved.push((name, hash));
+                }
+            }
+            for packed in packed.refs.values_mut() {
+                let peeled = peel(&packed.hash)?;
+                packed.peeled = Some(peeled).filter(|p| *p != packed.hash);
+            }
+            Ok(())
+        })?;
+
+        for (name, hash) in &moved {
+            // a ref that moved while we were packing it has to keep its new value
+            if self.read_loose(name)? == Some(Reference::Commit(*hash)) {
+                self.remove_loose(name)?;
+            }
+        }
+        Ok(moved.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::prelude::*;
+    use std::path::{Path, PathBuf};
+
+    use crate::error::Error;
+    use crate::object::id::{ObjectFormat, ObjectId};
+    use crate::reference::log::LogEntry;
+    use crate::reference::Reference;
+    use crate::store::file::{FileObjectStore, FileRefStore};
+    use crate::store::{ObjectStore, RefChange, RefStore, RefUpdate};
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gitrs_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn converts_hash_to_path() {
+        let hash = "a94a8fe5ccb19ba61c4c0873d391e987982fbbd3".parse().unwrap();
+        let expected = Path::new("a9/4a8fe5ccb19ba61c4c0873d391e987982fbbd3");
+        let path = FileObjectStore::hash_to_path(&hash);
+        assert_eq!(path, expected)
+    }
+
+    #[test]
+    fn writes_read_only_objects() {
+        let dir = scratch_dir("writes_read_only_objects");
+        let store = FileObjectStore::open(dir.clone(), ObjectFormat::Sha1).unwrap();
+        let hash = store.write("blob", b"test content\n").unwrap();
+        let path = dir.join(FileObjectStore::hash_to_path(&hash));
+        assert!(fs::metadata(path).unwrap().permissions().readonly());
+        assert_eq!(
+            store.read(&hash).unwrap(),
+            ("blob".to_string(), b"test content\n".to_vec())
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn writes_existing_objects_without_leaving_temporary_files() {
+        let dir = scratch_dir("writes_existing_objects");
+        let store = FileObjectStore::open(dir.clone(), ObjectFormat::Sha1).unwrap();
+        let first = store.write("blob", b"test content\n").unwrap();
+        let second = store.write("blob", b"test content\n").unwrap();
+        let mut writer = store.create("blob", 13).unwrap();
+        writer.write_all(b"test content\n").unwrap();
+        let streamed = writer.finish().unwrap();
+        assert_eq!(first, second);
+        assert_eq!(first, streamed);
+
+        let strays: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.is_file())
+            .collect();
+        assert!(strays.is_empty(), "{:?}", strays);
+        assert_eq!(store.hashes().unwrap(), vec![first]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn packs_refs_and_falls_back_to_them() {
+        let dir = scratch_dir("packs_refs");
+        let store = FileRefStore::new(dir.clone());
+        let commit: ObjectId = "11".repeat(20).parse().unwrap();
+        let tag: ObjectId = "22".repeat(20).parse().unwrap();
+        let master = Reference::Ref("refs/heads/master".to_string());
+        store.write("HEAD", &master).unwrap();
+        store
+            .write("refs/heads/master", &Reference::Commit(commit))
+            .unwrap();
+        store
+            .write("refs/tags/v1", &Reference::Commit(tag))
+            .unwrap();
+
+        let peel = |hash: &ObjectId| Ok(if *hash == tag { commit } else { *hash });
+        assert_eq!(store.pack(&|_| true, &peel).unwrap(), 2);
+        assert!(!dir.join("refs/tags/v1").exists());
+        assert!(dir.join("refs/tags").is_dir());
+        assert!(!dir.join("packed-refs.lock").exists());
+
+        assert_eq!(store.read("HEAD").unwrap(), Some(master));
+        assert_eq!(
+            store.read("refs/tags/v1").unwrap(),
+            Some(Reference::Commit(tag))
+        );
+        assert_eq!(store.peeled("refs/tags/v1").unwrap(), Some(commit));
+        assert_eq!(store.peeled("refs/heads/master").unwrap(), None);
+        assert_eq!(
+            store.list().unwrap(),
+            vec!["refs/heads/master", "refs/tags/v1"]
+        );
+
+        // a loose ref written after packing takes precedence over the packed one
+        store
+            .write("refs/tags/v1", &Reference::Commit(commit))
+            .unwrap();
+        assert_eq!(
+            store.read("refs/tags/v1").unwrap(),
+            Some(Reference::Commit(commit))
+        );
+        assert_eq!(store.peeled("refs/tags/v1").unwrap(), None);
+
+        assert!(store.delete("refs/tags/v1").unwrap());
+        assert_eq!(store.read("refs/tags/v1").unwrap(), None);
+        assert_eq!(store.list().unwrap(), vec!["refs/heads/master"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_to_pack_refs_while_locked() {
+        let dir = scratch_dir("refuses_to_pack_refs");
+        let store = FileRefStore::new(dir.clone());
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("packed-refs.lock"), "").unwrap();
+        assert!(matches!(
+            store.pack(&|_| true, &|hash| Ok(*hash)),
+            Err(Error::Refused(_))
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn applies_transactions_under_lock() {
+        let dir = scratch_dir("applies_transactions");
+        let store = FileRefStore::new(dir.clone());
+        let first = Reference::Commit("11".repeat(20).parse().unwrap());
+        let second = Reference::Commit("22".repeat(20).parse().unwrap());
+        store.write("refs/heads/master", &first).unwrap();
+        store.write("refs/tags/v1", &first).unwrap();
+        store.pack(&|_| true, &|hash| Ok(*hash)).unwrap();
+
+        let updates = [
+            RefUpdate::new("refs/heads/master", RefChange::Write(second.clone()))
+                .expecting(Some(first.clone())),
+            RefUpdate::new("refs/heads/topic/x", RefChange::Write(first.clone())).expecting(None),
+            RefUpdate::new("refs/tags/v1", RefChange::Delete).expecting(Some(first.clone())),
+        ];
+        fs::write(dir.join("refs/heads/master.lock"), "").unwrap();
+        assert!(matches!(
+            store.transaction(&updates),
+            Err(Error::Refused(_))
+        ));
+        assert!(!dir.join("refs/heads/topic").exists());
+        fs::remove_file(dir.join("refs/heads/master.lock")).unwrap();
+
+        store.transaction(&updates).unwrap();
+        assert_eq!(store.read("refs/heads/master").unwrap(), Some(second));
+        assert_eq!(store.read("refs/heads/topic/x").unwrap(), Some(first));
+        assert_eq!(store.read("refs/tags/v1").unwrap(), None);
+        assert_eq!(
+            store.list().unwrap(),
+            vec!["refs/heads/master", "refs/heads/topic/x"]
+        );
+        assert!(matches!(
+            store.transaction(&updates),
+            Err(Error::Refused(_))
+        ));
+        assert!(!dir.join("refs/heads/master.lock").exists());
+        assert!(!dir.join("packed-refs.lock").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn logs_updates_while_locked() {
+        let dir = scratch_dir("logs_updates_while_locked");
+        let store = FileRefStore::new(dir.clone());
+        let value = Reference::Commit("11".repeat(20).parse().unwrap());
+        let entry = LogEntry::deserialize(&format!(
+            "{} {} A <a@b> 1600000000 +0000\tcreated",
+            "00".repeat(20),
+            "11".repeat(20)
+        ))
+        .unwrap();
+        let updates =
+            [RefUpdate::new("refs/heads/master", RefChange::Write(value.clone())).logging(entry)];
+
+        // a log that can't be written stops the reference from moving
+        fs::create_dir_all(dir.join("logs/refs/heads/master")).unwrap();
+        assert!(matches!(store.transaction(&updates), Err(Error::Io(_))));
+        assert_eq!(store.read("refs/heads/master").unwrap(), None);
+        fs::remove_dir(dir.join("logs/refs/heads/master")).unwrap();
+
+        store.transaction(&updates).unwrap();
+        assert_eq!(store.read("refs/heads/master").unwrap(), Some(value));
+        assert_eq!(store.log("refs/heads/master").unwrap().len(), 1);
+
+        fs::write(dir.join("refs/heads/master.lock"), "").unwrap();
+        assert!(matches!(
+            store.expire_log("refs/heads/master", &|_| false),
+            Err(Error::Refused(_))
+        ));
+        assert_eq!(store.log("refs/heads/master").unwrap().len(), 1);
+        fs::remove_file(dir.join("refs/heads/master.lock")).unwrap();
+        store.expire_log("refs/heads/master", &|_| false).unwrap();
+        assert!(store.log("refs/heads/master").unwrap().is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn fails_to_update_invalid_names() {
+        let dir = scratch_dir("fails_to_update_invalid_names");
+        let store = FileRefStore::new(dir.join(".git"));
+        let value = Reference::Commit("11".repeat(20).parse().unwrap());
+        for name in &[
+            "../../escaped",
+            "refs/heads/x..y",
+            "/tmp/escaped",
+            "config",
+            "Head",
+        ] {
+            assert!(matches!(
+                store.write(name, &value),
+                Err(Error::InvalidRef(_))
+            ));
+        }
+        assert!(!dir.exists());
+        store.write("ORIG_HEAD", &value).unwrap();
+        assert_eq!(store.read("ORIG_HEAD").unwrap(), Some(value));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn fails_to_read_invalid_names() {
+        let dir = scratch_dir("fails_to_read_invalid_names");
+        let store = FileRefStore::new(dir.join(".git").join("nested"));
+        fs::create_dir_all(dir.join(".git").join("logs")).unwrap();
+        fs::write(dir.join(".git").join("escaped"), "ref: refs/heads/x\n").unwrap();
+        fs::write(dir.join(".git").join("logs").join("escaped"), "").unwrap();
+
+        assert!(matches!(
+            store.read("../escaped"),
+            Err(Error::InvalidRef(_))
+        ));
+        assert!(matches!(store.log("../escaped"), Err(Error::InvalidRef(_))));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,261 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io;
+use std::io::prelude::*;
+
+use crate::error::{Error, Result};
+use crate::object::id::{ObjectFormat, ObjectId};
+use crate::object::Object;
+use crate::reference::log::LogEntry;
+use crate::reference::Reference;
+use crate::store::{ObjectStore, RefChange, RefStore, RefUpdate};
+use crate::stream::{ObjectSink, ObjectWriter};
+
+/// Objects held in memory, for tests and tools that don't need anything to outlive them.
+pub struct MemoryObjectStore {
+    format: ObjectFormat,
+    objects: RefCell<BTreeMap<ObjectId, (String, Vec<u8>)>>,
+}
+
+impl MemoryObjectStore {
+    pub fn new(format: ObjectFormat) -> Self {
+        Self {
+            format,
+            objects: RefCell::new(BTreeMap::new()),
+        }
+    }
+}
+
+impl ObjectStore for MemoryObjectStore {
+    fn format(&self) -> ObjectFormat {
+        self.format
+    }
+
+    fn contains(&self, hash: &ObjectId) -> Result<bool> {
+        Ok(self.objects.borrow().contains_key(hash))
+    }
+
+    fn read(&self, hash: &ObjectId) -> Result<(String, Vec<u8>)> {
+        self.objects
+            .borrow()
+            .get(hash)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(format!("Object {}", hash)))
+    }
+
+    fn write(&self, kind: &str, content: &[u8]) -> Result<ObjectId> {
+        let bytes = [
+            Object::serialize_header(kind, content.len()),
+            content.to_vec(),
+        ]
+        .concat();
+        let hash = self.format.hash(&bytes);
+        self.objects
+            .borrow_mut()
+            .insert(hash, (kind.to_string(), content.to_vec()));
+        Ok(hash)
+    }
+
+    fn create(&self, kind: &str, size: usize) -> Result<ObjectWriter<'_>> {
+        let sink = MemorySink {
+            store: self,
+            kind: kind.to_string(),
+            content: Vec::with_capacity(size),
+        };
+        Ok(ObjectWriter::new(self.format, kind, size, Box::new(sink)))
+    }
+
+    fn hashes(&self) -> Result<Vec<ObjectId>> {
+        Ok(self.objects.borrow().keys().cloned().collect())
+    }
+}
+
+struct MemorySink<'a> {
+    store: &'a MemoryObjectStore,
+    kind: String,
+    content: Vec<u8>,
+}
+
+impl Write for MemorySink<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.content.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl ObjectSink for MemorySink<'_> {
+    fn commit(self: Box<Self>, hash: &ObjectId) -> Result<()> {
+        self.store
+            .objects
+            .borrow_mut()
+            .insert(*hash, (self.kind, self.content));
+        Ok(())
+    }
+}
+
+/// References and their logs held in memory, keyed by their full name.
+#[derive(Default)]
+pub struct MemoryRefStore {
+    refs: RefCell<BTreeMap<String, Reference>>,
+    logs: RefCell<BTreeMap<String, Vec<LogEntry>>>,
+}
+
+impl MemoryRefStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl RefStore for MemoryRefStore {
+    fn read(&self, name: &str) -> Result<Option<Reference>> {
+        Ok(self.refs.borrow().get(name).cloned())
+    }
+
+    fn transaction(&self, updates: &[RefUpdate]) -> Result<()> {
+        // this keeps the same rules as the file store even though anything could be held
+        RefUpdate::validate(updates)?;
+        let mut refs = self.refs.borrow_mut();
+        for update in updates {
+            update.check(refs.get(&update.name))?;
+        }
+        for update in updates {
+            match &update.change {
+                RefChange::Write(value) => {
+                    refs.insert(update.name.clone(), value.clone());
+                }
+                RefChange::Delete => {
+                    refs.remove(&update.name);
+                    self.logs.borrow_mut().remove(&update.name);
+                }
+                RefChange::Verify => {}
+            }
+            if let Some(entry) = &update.log {
+                self.append_log(&update.name, entry)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        Ok(self
+            .refs
+            .borrow()
+            .keys()
+            .filter(|name| name.starts_with("refs/"))
+            .cloned()
+            .collect())
+    }
+
+    fn log(&self, name: &str) -> Result<Vec<LogEntry>> {
+        Ok(self.logs.borrow().get(name).cloned().unwrap_or_default())
+    }
+
+    fn append_log(&self, name: &str, entry: &LogEntry) -> Result<()> {
+        self.logs
+            .borrow_mut()
+            .entry(name.to_string())
+            .or_default()
+            .push(entry.clone());
+        Ok(())
+    }
+
+    fn write_log(&self, name: &str, entries: &[LogEntry]) -> Result<()> {
+        self.logs
+            .borrow_mut()
+            .insert(name.to_string(), entries.to_vec());
+        Ok(())
+    }
+
+    fn expire_log(&self, name: &str, keep: &dyn Fn(&LogEntry) -> bool) -> Result<()> {
+        if let Some(log) = self.logs.borrow_mut().get_mut(name) {
+            log.retain(|entry| keep(entry));
+        }
+        Ok(())
+    }
+
+    fn delete_log(&self, name: &str) -> Result<()> {
+        self.logs.borrow_mut().remove(name);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::prelude::*;
+
+    use crate::error::Error;
+    use crate::object::id::ObjectFormat;
+    use crate::reference::Reference;
+    use crate::store::memory::{MemoryObjectStore, MemoryRefStore};
+    use crate::store::{ObjectStore, RefStore};
+
+    #[test]
+    fn round_trips_objects() {
+        let store = MemoryObjectStore::new(ObjectFormat::Sha1);
+        let hash = store.write("blob", b"test content\n").unwrap();
+        // this matches `git hash-object` for the same content
+        assert_eq!(hash.to_string(), "d670460b4b4aece5915caf5c68d12f560a9fe3e4");
+        assert!(store.contains(&hash).unwrap());
+        assert_eq!(
+            store.read(&hash).unwrap(),
+            ("blob".to_string(), b"test content\n".to_vec())
+        );
+        assert_eq!(store.hashes().unwrap(), vec![hash]);
+    }
+
+    #[test]
+    fn streams_objects() {
+        let store = MemoryObjectStore::new(ObjectFormat::Sha256);
+        let mut writer = store.create("blob", 13).unwrap();
+        writer.write_all(b"test ").unwrap();
+        writer.write_all(b"content\n").unwrap();
+        let hash = writer.finish().unwrap();
+        assert_eq!(hash, store.write("blob", b"test content\n").unwrap());
+
+        let mut reader = store.open(&hash).unwrap();
+        let mut content = vec![];
+        reader.read_to_end(&mut content).unwrap();
+        assert_eq!(reader.kind, "blob");
+        assert_eq!(content, b"test content\n");
+    }
+
+    #[test]
+    fn fails_to_read_missing_objects() {
+        let store = MemoryObjectStore::new(ObjectFormat::Sha1);
+        let hash = "ab".repeat(20).parse().unwrap();
+        assert!(!store.contains(&hash).unwrap());
+        assert!(matches!(store.read(&hash), Err(Error::NotFound(_))));
+    }
+
+    #[test]
+    fn round_trips_refs() {
+        let store = MemoryRefStore::new();
+        let master = Reference::Ref("refs/heads/master".to_string());
+        let commit = Reference::Commit("ab".repeat(20).parse().unwrap());
+        store.write("HEAD", &master).unwrap();
+        store.write("refs/heads/master", &commit).unwrap();
+        store.write("refs/tags/v1", &commit).unwrap();
+
+        assert_eq!(store.read("HEAD").unwrap(), Some(master));
+        assert_eq!(store.read("refs/heads/master").unwrap(), Some(commit));
+        assert_eq!(
+            store.list().unwrap(),
+            vec!["refs/heads/master", "refs/tags/v1"]
+        );
+        assert!(store.delete("refs/tags/v1").unwrap());
+        assert!(!store.delete("refs/tags/v1").unwrap());
+        assert_eq!(store.read("refs/tags/v1").unwrap(), None);
+    }
+
+    #[test]
+    fn fails_to_write_head_references() {
+        let store = MemoryRefStore::new();
+        assert!(matches!(
+            store.write("HEAD", &Reference::Head),
+            Err(Error::InvalidRef(_))
+        ));
+    }
+}
@@ -0,0 +1,207 @@
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::Path;
+
+use crypto::digest::Digest;
+use flate2::read::ZlibDecoder;
+
+use crate::error::{Error, Result};
+use crate::object::constant::ASCII_NULL;
+use crate::object::id::{ObjectFormat, ObjectId};
+use crate::object::Object;
+
+// the longest valid header is a kind, a space, a 20 digit size and a null byte
+const MAX_HEADER_SIZE: usize = 32;
+
+/// A stream over an object's content whose kind and size are known before any of it is read.
+pub struct ObjectReader {
+    pub kind: String,
+    pub size: usize,
+    body: Box<dyn Read>,
+    remaining: usize,
+}
+
+impl ObjectReader {
+    pub fn new(kind: String, size: usize, body: Box<dyn Read>) -> Self {
+        Self {
+            kind,
+            size,
+            body: Box::new(body.take(size as u64)),
+            remaining: size,
+        }
+    }
+
+    /// Opens a loose object, inflating only as far as the end of its header.
+    pub fn loose(path: &Path) -> Result<Self> {
+        let mut decoder = ZlibDecoder::new(BufReader::new(File::open(path)?));
+        let mut header = vec![];
+        while header.last() != Some(&ASCII_NULL) {
+            if header.len() == MAX_HEADER_SIZE {
+                return Err(Error::Corrupt(format!(
+                    "Object {} has an oversized header.",
+                    path.display()
+                )));
+            }
+            let mut byte = [0];
+            decoder
+                .read_exact(&mut byte)
+                .map_err(|_| Error::Corrupt(format!("Unable to inflate {}.", path.display())))?;
+            header.push(byte[0]);
+        }
+        let (kind, size, _) = Object::parse_header(&header)?;
+        Ok(ObjectReader::new(kind, size, Box::new(decoder)))
+    }
+}
+
+impl Read for ObjectReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.body.read(buf)?;
+        if read == 0 && self.remaining != 0 && !buf.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Object content was shorter than its encoded size.",
+            ));
+        }
+        self.remaining -= read;
+        Ok(read)
+    }
+}
+
+/// Somewhere an object's content can be written to as it's hashed.
+pub trait ObjectSink: Write {
+    /// Stores everything that's been written under the object's now known id.
+    fn commit(self: Box<Self>, hash: &ObjectId) -> Result<()>;
+}
+
+/// A sink that hashes an object as it's written, passing its content on to a store if there is one.
+///
+/// The size has to be declared up front as it's part of the header that's hashed first.
+pub struct ObjectWriter<'a> {
+    format: ObjectFormat,
+    hasher: Box<dyn Digest>,
+    sink: Option<Box<dyn ObjectSink + 'a>>,
+    remaining: usize,
+}
+
+impl<'a> ObjectWriter<'a> {
+    /// Creates a writer that only hashes its content.
+    pub fn hasher(format: ObjectFormat, kind: &str, size: usize) -> Self {
+        let mut hasher = format.hasher();
+        hasher.input(&Object::serialize_header(kind, size));
+        Self {
+            format,
+            hasher,
+            sink: None,
+            remaining: size,
+        }
+    }
+
+    pub fn new(
+        format: ObjectFormat,
+        kind: &str,
+        size: usize,
+        sink: Box<dyn ObjectSink + 'a>,
+    ) -> Self {
+        let mut writer = ObjectWriter::hasher(format, kind, size);
+        writer.sink = Some(sink);
+        writer
+    }
+
+    /// Checks that the declared size was written and commits the object, returning its id.
+    pub fn finish(mut self) -> Result<ObjectId> {
+        if self.remaining != 0 {
+            return Err(Error::InvalidArgument(format!(
+                "Object content was {} bytes shorter than its declared size.",
+                self.remaining
+            )));
+        }
+        let mut hash = vec![0; self.format.size()];
+        self.hasher.result(&mut hash);
+        let hash = ObjectId::from_bytes(&hash)?;
+        if let Some(sink) = self.sink.take() {
+            sink.commit(&hash)?;
+        }
+        Ok(hash)
+    }
+}
+
+impl Write for ObjectWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.len() > self.remaining {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Object content was longer than its declared size.",
+            ));
+        }
+        self.hasher.input(buf);
+        if let Some(sink) = self.sink.as_mut() {
+            sink.write_all(buf)?;
+        }
+        self.remaining -= buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.sink.as_mut() {
+            Some(sink) => sink.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::prelude::*;
+
+    use crate::object::blob::Blob;
+    use crate::object::id::ObjectFormat;
+    use crate::object::Object;
+    use crate::stream::{ObjectReader, ObjectWriter};
+
+    #[test]
+    fn hashes_content_as_it_is_written() {
+        let content = b"some blob";
+        let mut writer = ObjectWriter::hasher(ObjectFormat::Sha1, "blob", content.len());
+        writer.write_all(&content[..4]).unwrap();
+        writer.write_all(&content[4..]).unwrap();
+        let expected = ObjectFormat::Sha1.hash(
+            &Object::Blob(Blob {
+                content: content.to_vec(),
+            })
+            .serialize(),
+        );
+        assert_eq!(writer.finish().unwrap(), expected)
+    }
+
+    #[test]
+    fn fails_when_writing_more_than_declared() {
+        let mut writer = ObjectWriter::hasher(ObjectFormat::Sha1, "blob", 2);
+        assert!(writer.write_all(b"abc").is_err());
+    }
+
+    #[test]
+    fn fails_when_writing_less_than_declared() {
+        let mut writer = ObjectWriter::hasher(ObjectFormat::Sha1, "blob", 4);
+        writer.write_all(b"abc").unwrap();
+        assert!(writer.finish().is_err());
+    }
+
+    #[test]
+    fn reads_only_the_declared_size() {
+        let body: &'static [u8] = b"some blob and then some";
+        let mut reader = ObjectReader::new("blob".to_string(), 9, Box::new(body));
+        let mut content = vec![];
+        reader.read_to_end(&mut content).unwrap();
+        assert_eq!(content, b"some blob");
+    }
+
+    #[test]
+    fn fails_when_body_is_truncated() {
+        let body: &'static [u8] = b"some";
+        let mut reader = ObjectReader::new("blob".to_string(), 9, Box::new(body));
+        let mut content = vec![];
+        assert!(reader.read_to_end(&mut content).is_err());
+    }
+}
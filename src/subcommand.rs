@@ -1,5 +1,14 @@
+pub mod branch;
 pub mod cat_file;
 pub mod checkout;
+pub mod config;
+pub mod gc;
 pub mod hash_object;
 pub mod init;
 pub mod log;
+pub mod ls_files;
+pub mod pack_refs;
+pub mod reflog;
+pub mod rev_parse;
+pub mod tag;
+pub mod update_ref;
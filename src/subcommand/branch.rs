@@ -0,0 +1,269 @@
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::object::constant::NAME_COMMIT;
+use crate::reference::{Reference, BRANCH_PREFIX, REMOTE_PREFIX};
+use crate::repository::{Repository, DEFAULT_ABBREV};
+use crate::store::{RefChange, RefUpdate};
+
+/// Lists, creates, renames or deletes branches, or changes which branch one tracks, depending
+/// on which of the options are given.
+pub fn branch(
+    names: Vec<String>,
+    delete: bool,
+    force_delete: bool,
+    rename: bool,
+    track: bool,
+    set_upstream_to: Option<String>,
+    unset_upstream: bool,
+) -> Result<()> {
+    let repo = Repository::for_working_directory()?;
+    let invalid = || Error::InvalidArgument(format!("{} is not valid here.", names.join(" ")));
+    if delete || force_delete {
+        if names.is_empty() {
+            return Err(Error::InvalidArgument(
+                "A branch to delete is required.".to_string(),
+            ));
+        }
+        return names
+            .iter()
+            .try_for_each(|name| delete_branch(&repo, name, force_delete));
+    }
+    if rename {
+        return match names.as_slice() {
+            [to] => rename_branch(&repo, &current_branch(&repo)?, to),
+            [from, to] => rename_branch(&repo, from, to),
+            _ => Err(invalid()),
+        };
+    }
+    if set_upstream_to.is_some() || unset_upstream {
+        let name = match names.as_slice() {
+            [] => current_branch(&repo)?,
+            [name] => name.clone(),
+            _ => return Err(invalid()),
+        };
+        return match set_upstream_to {
+            Some(upstream) => set_upstream(&repo, &name, &upstream),
+            None => remove_upstream(&repo, &name),
+        };
+    }
+    match names.as_slice() {
+        [] => list_branches(&repo),
+        [name] => create_branch(&repo, name, "HEAD", track),
+        [name, start] => create_branch(&repo, name, start, track),
+        _ => Err(invalid()),
+    }
+}
+
+fn list_branches(repo: &Repository) -> Result<()> {
+    let head = repo.head()?;
+    if let Reference::Commit(hash) = head {
+        println!(
+            "* (HEAD detached at {})",
+            repo.abbreviate(&hash, DEFAULT_ABBREV)?
+        );
+    }
+    for full in repo.refs.list()? {
+        if let Some(name) = full.strip_prefix(BRANCH_PREFIX) {
+            let marker = if head == Reference::Ref(full.clone()) {
+                '*'
+            } else {
+                ' '
+            };
+            println!("{} {}", marker, name);
+        }
+    }
+    Ok(())
+}
+
+fn create_branch(repo: &Repository, name: &str, start: &str, track: bool) -> Result<()> {
+    let full = branch_ref(name)?;
+    if repo.refs.read(&full)?.is_some() {
+        return Err(Error::Refused(format!(
+            "A branch named '{}' already exists.",
+            name
+        )));
+    }
+    let hash = repo.peel_to(repo.rev_parse(start)?, NAME_COMMIT)?;
+    // like git's default `branch.autoSetupMerge`, starting from a remote-tracking
+    // branch tracks it without being asked
+    let upstream = match repo.reference(start) {
+        Ok(Reference::Ref(path))
+            if path.starts_with(REMOTE_PREFIX) || (track && path.starts_with(BRANCH_PREFIX)) =>
+        {
+            Some(path)
+        }
+        _ if track => {
+            return Err(Error::InvalidArgument(format!(
+                "{} can't be tracked as it isn't a branch.",
+                start
+            )))
+        }
+        _ => None,
+    };
+
+    let update = RefUpdate::new(&full, RefChange::Write(Reference::Commit(hash))).expecting(None);
+    repo.update_refs(&[update], &format!("branch: Created from {}", start))?;
+    if let Some(upstream) = upstream {
+        write_upstream(repo, name, &upstream)?;
+    }
+    Ok(())
+}
+
+fn rename_branch(repo: &Repository, from: &str, to: &str) -> Result<()> {
+    let (from_full, to_full) = (branch_ref(from)?, branch_ref(to)?);
+    if repo.refs.read(&to_full)?.is_some() {
+        return Err(Error::Refused(format!(
+            "A branch named '{}' already exists.",
+            to
+        )));
+    }
+    let message = format!("Branch: renamed {} to {}", from_full, to_full);
+    // a branch that hasn't been born yet only exists as what HEAD points at
+    if repo.refs.read(&from_full)?.is_none() && repo.head()? == Reference::Ref(from_full.clone()) {
+        repo.set_head(&Reference::Ref(to_full), &message)?;
+    } else {
+        repo.rename_ref(&from_full, &to_full, &message)?;
+    }
+
+    let mut config = Config::read(&repo.config_path())?;
+    if config.rename_section("branch", from, to) {
+        config.write(&repo.config_path())?;
+    }
+    Ok(())
+}
+
+fn delete_branch(repo: &Repository, name: &str, force: bool) -> Result<()> {
+    let full = branch_ref(name)?;
+    if repo.head()? == Reference::Ref(full.clone()) {
+        return Err(Error::Refused(format!(
+            "Branch '{}' can't be deleted while it's checked out.",
+            name
+        )));
+    }
+    let value = repo
+        .refs
+        .read(&full)?
+        .ok_or_else(|| Error::NotFound(format!("Branch {}", name)))?;
+    let hash = repo.resolve(&value)?;
+
+    let mut config = Config::read(&repo.config_path())?;
+    if !force {
+        // like git, a branch only needs to be merged into what it tracks if that
+        // still exists, and otherwise into HEAD
+        let target = match upstream(&config, name)? {
+            Some(upstream) if repo.refs.read(&upstream)?.is_some() => upstream,
+            _ => "HEAD".to_string(),
+        };
+        let merged = match repo.rev_parse(&target) {
+            Ok(target) => repo.is_ancestor(&hash, &repo.peel_to(target, NAME_COMMIT)?)?,
+            Err(Error::NotFound(_)) => false,
+            Err(e) => return Err(e),
+        };
+        if !merged {
+            return Err(Error::Refused(format!(
+                "The branch '{}' is not fully merged, run 'vcrs branch -D {}' to delete it anyway.",
+                name, name
+            )));
+        }
+    }
+
+    let update = RefUpdate::new(&full, RefChange::Delete).expecting(Some(value));
+    repo.update_refs(&[update], "")?;
+    if config.remove_section("branch", Some(name)) {
+        config.write(&repo.config_path())?;
+    }
+    println!(
+        "Deleted branch {} (was {}).",
+        name,
+        repo.abbreviate(&hash, DEFAULT_ABBREV)?
+    );
+    Ok(())
+}
+
+fn set_upstream(repo: &Repository, name: &str, upstream: &str) -> Result<()> {
+    if repo.refs.read(&branch_ref(name)?)?.is_none() {
+        return Err(Error::NotFound(format!("Branch {}", name)));
+    }
+    match repo.reference(upstream) {
+        Ok(Reference::Ref(path))
+            if path.starts_with(BRANCH_PREFIX) || path.starts_with(REMOTE_PREFIX) =>
+        {
+            write_upstream(repo, name, &path)
+        }
+        Ok(_) | Err(Error::NotFound(_)) => Err(Error::InvalidArgument(format!(
+            "{} can't be tracked as it isn't a branch.",
+            upstream
+        ))),
+        Err(e) => Err(e),
+    }
+}
+
+fn remove_upstream(repo: &Repository, name: &str) -> Result<()> {
+    let mut config = Config::read(&repo.config_path())?;
+    let remote = config.unset(&format!("branch.{}.remote", name))?;
+    let merge = config.unset(&format!("branch.{}.merge", name))?;
+    if !remote && !merge {
+        return Err(Error::NotFound(format!("Upstream of branch {}", name)));
+    }
+    config.write(&repo.config_path())
+}
+
+fn write_upstream(repo: &Repository, name: &str, upstream: &str) -> Result<()> {
+    let (remote, merge, short) = match upstream.strip_prefix(REMOTE_PREFIX) {
+        Some(tracking) => {
+            // this assumes the remote uses the default fetch refspec
+            let (remote, branch) = tracking.split_once('/').ok_or_else(|| {
+                Error::InvalidArgument(format!("{} doesn't name a remote's branch.", upstream))
+            })?;
+            (remote, format!("{}{}", BRANCH_PREFIX, branch), tracking)
+        }
+        None => (
+            ".",
+            upstream.to_string(),
+            upstream.strip_prefix(BRANCH_PREFIX).unwrap_or(upstream),
+        ),
+    };
+    let mut config = Config::read(&repo.config_path())?;
+    config.set(&format!("branch.{}.remote", name), remote)?;
+    config.set(&format!("branch.{}.merge", name), &merge)?;
+    config.write(&repo.config_path())?;
+    println!("branch '{}' set up to track '{}'.", name, short);
+    Ok(())
+}
+
+fn upstream(config: &Config, name: &str) -> Result<Option<String>> {
+    let remote = config.get(&format!("branch.{}.remote", name))?;
+    let merge = config.get(&format!("branch.{}.merge", name))?;
+    Ok(match (remote, merge) {
+        (Some(remote), Some(merge)) if remote == "." => Some(merge),
+        (Some(remote), Some(merge)) => {
+            let branch = merge.strip_prefix(BRANCH_PREFIX).unwrap_or(&merge);
+            Some(format!("{}{}/{}", REMOTE_PREFIX, remote, branch))
+        }
+        _ => None,
+    })
+}
+
+fn current_branch(repo: &Repository) -> Result<String> {
+    match repo.head()? {
+        Reference::Ref(path) if path.starts_with(BRANCH_PREFIX) => {
+            Ok(path[BRANCH_PREFIX.len()..].to_string())
+        }
+        _ => Err(Error::InvalidArgument(
+            "No branch was given and HEAD is detached.".to_string(),
+        )),
+    }
+}
+
+fn branch_ref(name: &str) -> Result<String> {
+    // these are valid reference names but would be confused for options or HEAD
+    if name.starts_with('-') || name == "HEAD" {
+        return Err(Error::InvalidRef(format!(
+            "{} is not a valid branch name.",
+            name
+        )));
+    }
+    let full = format!("{}{}", BRANCH_PREFIX, name);
+    Reference::check_name(&full)?;
+    Ok(full)
+}
@@ -1,9 +1,9 @@
-use crate::reference::Reference;
+use crate::error::Result;
 use crate::repository::Repository;
 
-pub fn cat_file(object: String) {
-    let reference = Reference::from_name(object.as_str());
-    let repo = Repository::for_working_directory();
-    let obj = repo.read_object(repo.find_commit(&reference).as_str());
+pub fn cat_file(object: String) -> Result<()> {
+    let repo = Repository::for_working_directory()?;
+    let obj = repo.read_object(&repo.rev_parse(object.as_str())?)?;
     println!("{:#?}", obj);
+    Ok(())
 }
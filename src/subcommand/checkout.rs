@@ -1,25 +1,51 @@
+use crate::error::{Error, Result};
+use crate::object::constant::NAME_COMMIT;
 use crate::object::Object;
-use crate::reference::Reference;
+use crate::reference::{Reference, BRANCH_PREFIX};
 use crate::repository::Repository;
+use crate::revision::Revision;
 
-pub fn checkout(object: String) {
-    let reference = Reference::from_name(object.as_str());
-    let repo = Repository::for_working_directory();
+pub fn checkout(object: String) -> Result<()> {
+    let repo = Repository::for_working_directory()?;
+    let revision = Revision::parse(object.as_str())?;
     // TODO[Rhys] add more relaxed safeguards here
-    assert!(repo.is_empty());
+    if !repo.is_empty()? {
+        return Err(Error::Refused("Working tree is not empty.".to_string()));
+    }
 
-    let hash = repo.find_commit(&reference);
+    let hash = repo.peel_to(repo.evaluate(&revision)?, NAME_COMMIT)?;
+    let tree_hash = repo.read_commit(&hash)?.tree;
 
-    let tree_hash = match repo.read_object(hash.as_str()) {
-        Object::Commit(data) => data.tree,
-        _ => panic!("Object was not a commit."),
+    let tree = match repo.read_object(&tree_hash)? {
+        Object::Tree(data) => data,
+        _ => return Err(Error::Corrupt(format!("{} is not a tree.", tree_hash))),
     };
 
-    let tree = match repo.read_object(tree_hash.as_str()) {
-        Object::Tree(data) => data,
-        _ => panic!(),
+    repo.checkout_tree(tree, repo.work_tree()?)?;
+    // the reflog records where we came from so `@{-1}` can find its way back
+    let from = match repo.head()? {
+        Reference::Ref(path) => short_name(&path).to_string(),
+        Reference::Commit(hash) => hash.to_string(),
+        Reference::Head => "HEAD".to_string(),
     };
+    // only branches can be checked out by name, anything else leaves HEAD detached
+    let name = match revision {
+        Revision::Name(name) => Some(name),
+        Revision::PreviousCheckout(n) => Some(repo.previous_checkout(n)?),
+        _ => None,
+    };
+    match name.map(|n| repo.reference(&n)).transpose()? {
+        Some(Reference::Ref(path)) if path.starts_with(BRANCH_PREFIX) => {
+            let message = format!("checkout: moving from {} to {}", from, short_name(&path));
+            repo.set_head(&Reference::Ref(path), &message)
+        }
+        _ => {
+            let message = format!("checkout: moving from {} to {}", from, object);
+            repo.set_head(&Reference::Commit(hash), &message)
+        }
+    }
+}
 
-    repo.checkout_tree(tree, &repo.work_tree);
-    repo.set_head(&reference);
+fn short_name(path: &str) -> &str {
+    path.strip_prefix(BRANCH_PREFIX).unwrap_or(path)
 }
@@ -0,0 +1,140 @@
+use std::path::PathBuf;
+
+use crate::config::layered::{LayeredConfig, Scope};
+use crate::config::{parse_bool, parse_int, Config};
+use crate::error::{Error, Result};
+use crate::repository::Repository;
+
+fn find_repository() -> Result<Option<Repository>> {
+    match Repository::for_working_directory() {
+        Ok(repo) => Ok(Some(repo)),
+        Err(Error::NotFound(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn load(scope: Option<Scope>) -> Result<LayeredConfig> {
+    let scopes = match scope {
+        Some(scope) => vec![scope],
+        None => Scope::ALL.to_vec(),
+    };
+    match find_repository()? {
+        Some(repo) => repo.load_config(&scopes),
+        None => LayeredConfig::load(&scopes, None, None),
+    }
+}
+
+fn path(scope: Option<Scope>) -> Result<PathBuf> {
+    let repo = find_repository()?;
+    scope
+        .unwrap_or(Scope::Local)
+        .path(repo.as_ref().map(|repo| repo.root.as_path()))
+}
+
+/// Prints the value of a key, or every value with `all`, optionally checking it's a `bool` or an
+/// `int` and printing it the way git would.
+pub fn config_get(
+    key: String,
+    all: bool,
+    kind: Option<String>,
+    scope: Option<Scope>,
+) -> Result<()> {
+    let mut values = load(scope)?.get_all(&key)?;
+    if values.is_empty() {
+        return Err(Error::NotFound(format!("Config value {}", key)));
+    }
+    if !all {
+        values.drain(..values.len() - 1);
+    }
+    for value in values {
+        let invalid = |kind| {
+            Error::Corrupt(format!(
+                "Config value {} for {} is not {}.",
+                value, key, kind
+            ))
+        };
+        match kind.as_deref() {
+            None => println!("{}", value),
+            Some("bool") => println!(
+                "{}",
+                parse_bool(&value).ok_or_else(|| invalid("a boolean"))?
+            ),
+            Some("int") => println!(
+                "{}",
+                parse_int(&value).ok_or_else(|| invalid("an integer"))?
+            ),
+            Some(other) => {
+                return Err(Error::InvalidArgument(format!(
+                    "{} is not a config type, expected bool or int.",
+                    other
+                )))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sets a key, refusing to replace a key with several values unless told to replace them all
+/// or to `append` another.
+pub fn config_set(
+    key: String,
+    value: String,
+    append: bool,
+    all: bool,
+    scope: Option<Scope>,
+) -> Result<()> {
+    let path = path(scope)?;
+    let mut config = Config::read(&path)?;
+    if append {
+        config.add(&key, &value)?;
+    } else if all {
+        config.replace_all(&key, &value)?;
+    } else if config.get_all(&key)?.len() > 1 {
+        return Err(Error::InvalidArgument(format!(
+            "{} has multiple values, use --all to replace them or --append to add another.",
+            key
+        )));
+    } else {
+        config.set(&key, &value)?;
+    }
+    config.write(&path)
+}
+
+/// Removes a key, refusing to remove a key with several values unless told to remove them all.
+pub fn config_unset(key: String, all: bool, scope: Option<Scope>) -> Result<()> {
+    let path = path(scope)?;
+    let mut config = Config::read(&path)?;
+    match config.get_all(&key)?.len() {
+        0 => return Err(Error::NotFound(format!("Config value {}", key))),
+        1 => {}
+        _ if all => {}
+        _ => {
+            return Err(Error::InvalidArgument(format!(
+                "{} has multiple values, use --all to remove them.",
+                key
+            )))
+        }
+    }
+    config.unset(&key)?;
+    config.write(&path)
+}
+
+/// Lists every value as `key=value`, optionally with the scope and file it came from.
+pub fn config_list(show_origin: bool, show_scope: bool, scope: Option<Scope>) -> Result<()> {
+    for value in load(scope)?.values {
+        let mut line = String::new();
+        if show_scope {
+            line.push_str(&format!("{}\t", value.scope.name()));
+        }
+        if show_origin {
+            line.push_str(&format!("file:{}\t", value.origin.display()));
+        }
+        match &value.value {
+            Some(v) => line.push_str(&format!("{}={}", value.key, v)),
+            // like git, a name on its own is listed without an `=`
+            None => line.push_str(&value.key),
+        }
+        println!("{}", line);
+    }
+    Ok(())
+}
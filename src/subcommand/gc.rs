@@ -0,0 +1,64 @@
+use std::collections::HashSet;
+
+use crate::error::Result;
+use crate::object::constant::NAME_BLOB;
+use crate::object::id::ObjectId;
+use crate::object::tree::Mode;
+use crate::object::Object;
+use crate::pack::Entry;
+use crate::reference::Reference;
+use crate::repository::Repository;
+
+pub fn gc() -> Result<()> {
+    let repo = Repository::for_working_directory()?;
+
+    let mut pending: Vec<(ObjectId, String)> = repo
+        .list_refs()?
+        .iter()
+        .map(|r| repo.resolve(r).map(|hash| (hash, "".to_string())))
+        .collect::<Result<_>>()?;
+    // a detached HEAD isn't covered by any ref but its history still needs keeping
+    if let Reference::Commit(hash) = repo.head()? {
+        pending.push((hash, "".to_string()));
+    }
+
+    let mut seen = HashSet::new();
+    let mut entries = vec![];
+    while let Some((hash, name)) = pending.pop() {
+        if !seen.insert(hash) {
+            continue;
+        }
+        let (kind, size) = repo.read_header(&hash)?;
+        // blobs don't lead anywhere so only the other objects need reading here, and none of
+        // them are kept as the pack reads everything again while it's written
+        if kind != NAME_BLOB {
+            match repo.read_object(&hash)? {
+                Object::Commit(commit) => {
+                    pending.push((commit.tree, "".to_string()));
+                    pending.extend(commit.parents.into_iter().map(|p| (p, "".to_string())));
+                }
+                Object::Tree(tree) => pending.extend(
+                    tree.leaves
+                        .into_iter()
+                        .filter(|l| l.mode != Mode::Submodule)
+                        .map(|l| (l.hash, l.path)),
+                ),
+                Object::Tag(tag) => pending.push((tag.object, "".to_string())),
+                Object::Blob(_) => {}
+            }
+        }
+        entries.push(Entry {
+            hash,
+            kind,
+            size,
+            name,
+        });
+    }
+
+    let count = entries.len();
+    match repo.repack(entries)? {
+        Some(path) => println!("Packed {} objects into {}", count, path.display()),
+        None => println!("Packed {} objects", count),
+    }
+    Ok(())
+}
@@ -1,17 +1,37 @@
-use crate::object::Object;
-use crate::repository::Repository;
 use std::fs;
+use std::fs::File;
+use std::io;
 
-pub fn hash_object(kind: String, file: String, write: bool) {
-    let repo = Repository::for_working_directory();
-    let obj = {
-        let content = fs::read_to_string(file).unwrap();
-        Object::new(kind, content.as_bytes().to_vec())
-    };
+use crate::error::Result;
+use crate::object::constant::NAME_BLOB;
+use crate::object::Object;
+use crate::repository::Repository;
+use crate::stream::ObjectWriter;
 
-    if write {
-        repo.write_object(obj);
+pub fn hash_object(kind: String, file: String, write: bool) -> Result<()> {
+    let repo = Repository::for_working_directory()?;
+    // blobs can be arbitrarily large so they're streamed rather than parsed
+    let hash = if kind == NAME_BLOB {
+        let mut source = File::open(file)?;
+        let size = source.metadata()?.len() as usize;
+        let mut sink = if write {
+            repo.create_object(&kind, size)?
+        } else {
+            ObjectWriter::hasher(repo.format(), &kind, size)
+        };
+        io::copy(&mut source, &mut sink)?;
+        sink.finish()?
     } else {
-        println!("{}", Repository::hash(&obj.serialize()));
+        let obj = Object::new(kind, fs::read(file)?, repo.format())?;
+        if write {
+            repo.write_object(obj)?
+        } else {
+            repo.hash(&obj.serialize())
+        }
     };
+
+    if !write {
+        println!("{}", hash);
+    }
+    Ok(())
 }
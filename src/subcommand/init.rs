@@ -1,16 +1,60 @@
-use std::fs;
-use std::fs::File;
-use std::io::prelude::*;
+use std::env;
+use std::path::PathBuf;
 
-use crate::repository::Repository;
+use crate::config::layered::{LayeredConfig, Scope};
+use crate::error::Result;
+use crate::object::id::ObjectFormat;
+use crate::repository::{InitOptions, Repository, Shared};
 
-pub fn init() {
-    let repo = Repository::for_working_directory();
-    assert!(repo.is_empty());
+/// Creates a repository in the current directory, or fills in anything missing from the one
+/// that's already there, taking defaults for the initial branch and template from the user's
+/// config like git does.
+pub fn init(
+    object_format: Option<String>,
+    bare: bool,
+    initial_branch: Option<String>,
+    template: Option<String>,
+    shared: Option<String>,
+) -> Result<()> {
+    let format = object_format
+        .map(|f| ObjectFormat::from_name(f.as_str()))
+        .transpose()?;
+    let shared = shared.map(|s| Shared::parse(&s)).transpose()?;
+    let config = LayeredConfig::load(&[Scope::System, Scope::Global], None, None)?;
+    let template = match template.or_else(|| env::var("GIT_TEMPLATE_DIR").ok()) {
+        Some(template) => Some(template),
+        None => config.get("init.templatedir")?,
+    };
+    let default_branch = match &initial_branch {
+        Some(branch) => Some(branch.clone()),
+        None => config.get("init.defaultbranch")?,
+    };
 
-    // TODO[Rhys] we need to create things like config, description, tags, etc.
-    fs::create_dir_all(repo.objects).unwrap();
-    fs::create_dir_all(repo.refs).unwrap();
-    let mut file = File::create(repo.head).unwrap();
-    file.write_all(b"ref: refs/heads/master\n").unwrap();
+    // this deliberately doesn't look for a repository above the current directory
+    let dir = env::current_dir()?;
+    let root = if bare { dir.clone() } else { dir.join(".git") };
+    let existing = Repository::is_git_dir(&root);
+    let options = InitOptions {
+        format,
+        bare,
+        initial_branch: default_branch,
+        template: template.filter(|t| !t.is_empty()).map(PathBuf::from),
+        shared,
+    };
+    let repo = Repository::init(dir, &options)?;
+    if existing {
+        if let Some(branch) = initial_branch {
+            eprintln!("warning: re-init: ignored --initial-branch={}", branch);
+        }
+        println!(
+            "Reinitialized existing Git repository in {}/",
+            repo.root.display()
+        );
+    } else {
+        println!(
+            "Initialized empty Git repository in {}/",
+            repo.root.display()
+        );
+    }
+    Ok(())
 }
@@ -1,25 +1,52 @@
-use crate::object::Object;
-use crate::reference::Reference;
-use crate::repository::Repository;
+use std::collections::hash_map::Entry;
+use std::collections::{BinaryHeap, HashMap};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-pub fn log(object: Option<String>) {
+use crate::error::Result;
+use crate::object::constant::NAME_COMMIT;
+use crate::object::signature::DateFormat;
+use crate::repository::{Repository, DEFAULT_ABBREV};
+
+pub fn log(object: Option<String>, date: Option<String>, abbrev: bool) -> Result<()> {
     let head = object.unwrap_or("HEAD".to_string());
-    let reference = Reference::from_name(head.as_str());
-    let repo = Repository::for_working_directory();
-    let mut maybe_commit = Some(repo.find_commit(&reference));
-    loop {
-        maybe_commit = match &maybe_commit {
-            Some(commit) => {
-                let obj = repo.read_object(commit);
-                match obj {
-                    Object::Commit(commit_obj) => {
-                        println!("{} {}", commit, commit_obj.message);
-                        commit_obj.parent
-                    }
-                    _ => panic!("Commit parent was not a commit."),
-                }
-            }
-            None => return (),
+    let format = date
+        .map(|d| DateFormat::from_name(d.as_str()))
+        .transpose()?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let repo = Repository::for_working_directory()?;
+    let start = repo.peel_to(repo.rev_parse(head.as_str())?, NAME_COMMIT)?;
+    // like git we show the most recently committed of all pending commits first
+    let mut pending = BinaryHeap::new();
+    let mut commits = HashMap::new();
+    let commit = repo.read_commit(&start)?;
+    pending.push((commit.committer().time, start));
+    commits.insert(start, commit);
+
+    while let Some((_, hash)) = pending.pop() {
+        let commit = commits[&hash].clone();
+        let id = if abbrev {
+            repo.abbreviate(&hash, DEFAULT_ABBREV)?
+        } else {
+            hash.to_string()
         };
+        match &format {
+            Some(format) => println!(
+                "{} {} {}",
+                id,
+                commit.author().format_date(format, now),
+                commit.message
+            ),
+            None => println!("{} {}", id, commit.message),
+        }
+        for parent in commit.parents {
+            if let Entry::Vacant(entry) = commits.entry(parent) {
+                let parent_commit = entry.insert(repo.read_commit(&parent)?);
+                pending.push((parent_commit.committer().time, parent));
+            }
+        }
     }
+    Ok(())
 }
@@ -0,0 +1,35 @@
+use std::env;
+
+use crate::error::Result;
+use crate::repository::Repository;
+
+/// Lists the paths in the index under the current directory relative to it, along with their
+/// mode, object and merge stage if `stage` is set.
+pub fn ls_files(stage: bool) -> Result<()> {
+    let repo = Repository::for_working_directory()?;
+    let work_tree = repo.work_tree()?.canonicalize()?;
+    let current = env::current_dir()?.canonicalize()?;
+    let prefix = match current.strip_prefix(&work_tree) {
+        Ok(dir) if dir.as_os_str().is_empty() => String::new(),
+        Ok(dir) => format!("{}/", dir.to_string_lossy()),
+        Err(_) => String::new(),
+    };
+    for entry in repo.read_index()?.entries {
+        let path = match entry.path.strip_prefix(&prefix) {
+            Some(path) => path,
+            None => continue,
+        };
+        if stage {
+            println!(
+                "{} {} {}\t{}",
+                entry.mode.serialize(),
+                entry.hash,
+                entry.stage,
+                path
+            );
+        } else {
+            println!("{}", path);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,8 @@
+use crate::error::Result;
+use crate::repository::Repository;
+
+pub fn pack_refs(all: bool) -> Result<()> {
+    let repo = Repository::for_working_directory()?;
+    repo.pack_refs(all)?;
+    Ok(())
+}
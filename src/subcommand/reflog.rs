@@ -0,0 +1,48 @@
+use crate::error::Result;
+use crate::object::signature::parse_date;
+use crate::repository::{Repository, DEFAULT_ABBREV};
+
+// git's default for `gc.reflogExpire`
+const DEFAULT_EXPIRE: &str = "90.days.ago";
+
+pub fn reflog_show(reference: Option<String>) -> Result<()> {
+    let repo = Repository::for_working_directory()?;
+    let name = reference.unwrap_or_else(|| "HEAD".to_string());
+    let log = repo.refs.log(&repo.log_name(&name)?)?;
+    for (i, entry) in log.iter().rev().enumerate() {
+        println!(
+            "{} {}@{{{}}}: {}",
+            repo.abbreviate(&entry.new, DEFAULT_ABBREV)?,
+            name,
+            i,
+            entry.message
+        );
+    }
+    Ok(())
+}
+
+/// Drops log entries older than the expiry date from the given references, or from every
+/// reference that has a log if `all` is set.
+pub fn reflog_expire(references: Vec<String>, all: bool, expire: Option<String>) -> Result<()> {
+    let repo = Repository::for_working_directory()?;
+    let expire = expire.unwrap_or_else(|| DEFAULT_EXPIRE.to_string());
+    let cutoff = match expire.as_str() {
+        "never" => i64::MIN,
+        "all" => i64::MAX,
+        date => parse_date(date, repo.identity()?.time)?,
+    };
+
+    let mut names = references
+        .iter()
+        .map(|r| repo.log_name(r))
+        .collect::<Result<Vec<_>>>()?;
+    if all {
+        names.push("HEAD".to_string());
+        names.extend(repo.refs.list()?);
+    }
+    for name in names {
+        repo.refs
+            .expire_log(&name, &|entry| entry.signature.time >= cutoff)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,15 @@
+use crate::error::Result;
+use crate::repository::Repository;
+
+pub fn rev_parse(revisions: Vec<String>) -> Result<()> {
+    let repo = Repository::for_working_directory()?;
+    // everything is resolved up front so nothing is printed if any revision is invalid
+    let hashes = revisions
+        .iter()
+        .map(|r| repo.rev_parse(r))
+        .collect::<Result<Vec<_>>>()?;
+    for hash in hashes {
+        println!("{}", hash);
+    }
+    Ok(())
+}
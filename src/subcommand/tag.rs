@@ -0,0 +1,192 @@
+use std::cmp::Ordering;
+
+use crate::error::{Error, Result};
+use crate::glob;
+use crate::object::tag::Tag;
+use crate::object::Object;
+use crate::reference::{Reference, TAG_PREFIX};
+use crate::repository::{Repository, DEFAULT_ABBREV};
+use crate::store::{RefChange, RefUpdate};
+
+/// Lists, creates or deletes tags, where giving a message makes an annotated tag rather than a
+/// lightweight one.
+pub fn tag(
+    names: Vec<String>,
+    annotate: bool,
+    message: Option<String>,
+    delete: bool,
+    list: bool,
+    force: bool,
+    sort: Option<String>,
+) -> Result<()> {
+    let repo = Repository::for_working_directory()?;
+    if delete {
+        return names.iter().try_for_each(|name| delete_tag(&repo, name));
+    }
+    if list || names.is_empty() {
+        return list_tags(&repo, &names, sort.as_deref());
+    }
+    let (name, target) = match names.as_slice() {
+        [name] => (name, "HEAD"),
+        [name, target] => (name, target.as_str()),
+        _ => {
+            return Err(Error::InvalidArgument(format!(
+                "{} is not a tag and an object.",
+                names.join(" ")
+            )))
+        }
+    };
+    match message {
+        Some(message) => create_tag(&repo, name, target, Some(&message), force),
+        None if annotate => Err(Error::InvalidArgument(
+            "Annotated tags need a message.".to_string(),
+        )),
+        None => create_tag(&repo, name, target, None, force),
+    }
+}
+
+fn list_tags(repo: &Repository, patterns: &[String], sort: Option<&str>) -> Result<()> {
+    let mut names: Vec<_> = repo
+        .refs
+        .list()?
+        .into_iter()
+        .filter_map(|full| full.strip_prefix(TAG_PREFIX).map(str::to_string))
+        .filter(|name| {
+            patterns.is_empty() || patterns.iter().any(|p| glob::matches(p, name, false))
+        })
+        .collect();
+    let (reverse, key) = match sort {
+        Some(sort) => match sort.strip_prefix('-') {
+            Some(key) => (true, key),
+            None => (false, sort),
+        },
+        None => (false, "refname"),
+    };
+    match key {
+        "refname" => names.sort(),
+        "version:refname" | "v:refname" => names.sort_by(|a, b| compare_versions(a, b)),
+        other => {
+            return Err(Error::InvalidArgument(format!(
+                "Tags can't be sorted by {}.",
+                other
+            )))
+        }
+    }
+    if reverse {
+        names.reverse();
+    }
+    for name in names {
+        println!("{}", name);
+    }
+    Ok(())
+}
+
+fn create_tag(
+    repo: &Repository,
+    name: &str,
+    target: &str,
+    message: Option<&str>,
+    force: bool,
+) -> Result<()> {
+    let full = tag_ref(name)?;
+    let existing = repo.refs.read(&full)?;
+    if existing.is_some() && !force {
+        return Err(Error::Refused(format!("Tag '{}' already exists.", name)));
+    }
+
+    let object = repo.rev_parse(target)?;
+    let hash = match message {
+        Some(message) => {
+            let (kind, _) = repo.read_header(&object)?;
+            // git always ends the message with a newline
+            let message = format!("{}\n", message.trim_end());
+            repo.write_object(Object::Tag(Tag::new(
+                object,
+                kind,
+                name.to_string(),
+                Some(repo.identity()?),
+                message.into_bytes(),
+            )))?
+        }
+        None => object,
+    };
+    let update = RefUpdate::new(&full, RefChange::Write(Reference::Commit(hash)))
+        .expecting(existing.clone());
+    repo.update_refs(&[update], "")?;
+    if let Some(old) = existing {
+        println!(
+            "Updated tag '{}' (was {})",
+            name,
+            repo.abbreviate(&repo.resolve(&old)?, DEFAULT_ABBREV)?
+        );
+    }
+    Ok(())
+}
+
+fn delete_tag(repo: &Repository, name: &str) -> Result<()> {
+    let full = tag_ref(name)?;
+    let value = repo
+        .refs
+        .read(&full)?
+        .ok_or_else(|| Error::NotFound(format!("Tag {}", name)))?;
+    let hash = repo.resolve(&value)?;
+    let update = RefUpdate::new(&full, RefChange::Delete).expecting(Some(value));
+    repo.update_refs(&[update], "")?;
+    println!(
+        "Deleted tag '{}' (was {})",
+        name,
+        repo.abbreviate(&hash, DEFAULT_ABBREV)?
+    );
+    Ok(())
+}
+
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        let (a_digits, b_digits) = (digit_run(a), digit_run(b));
+        let ordering = if a_digits > 0 && b_digits > 0 {
+            let (a_number, b_number) = (
+                a[..a_digits].trim_start_matches('0'),
+                b[..b_digits].trim_start_matches('0'),
+            );
+            let ordering = a_number
+                .len()
+                .cmp(&b_number.len())
+                .then_with(|| a_number.cmp(b_number));
+            a = &a[a_digits..];
+            b = &b[b_digits..];
+            ordering
+        } else {
+            let (mut a_chars, mut b_chars) = (a.chars(), b.chars());
+            match (a_chars.next(), b_chars.next()) {
+                (None, None) => return Ordering::Equal,
+                (x, y) => {
+                    a = a_chars.as_str();
+                    b = b_chars.as_str();
+                    x.cmp(&y)
+                }
+            }
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+fn digit_run(text: &str) -> usize {
+    text.find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len())
+}
+
+fn tag_ref(name: &str) -> Result<String> {
+    // this is a valid reference name but would be confused for an option
+    if name.starts_with('-') {
+        return Err(Error::InvalidRef(format!(
+            "{} is not a valid tag name.",
+            name
+        )));
+    }
+    let full = format!("{}{}", TAG_PREFIX, name);
+    Reference::check_name(&full)?;
+    Ok(full)
+}
@@ -0,0 +1,127 @@
+use std::io;
+use std::io::prelude::*;
+
+use crate::error::{Error, Result};
+use crate::reference::Reference;
+use crate::repository::Repository;
+use crate::store::{RefChange, RefUpdate};
+
+/// Updates references like `git update-ref`, either the one given by `args` as
+/// `<ref> <new> [<old>]` (or `<ref> [<old>]` when deleting) or every one read from stdin in a
+/// single transaction.
+pub fn update_ref(
+    args: Vec<String>,
+    delete: bool,
+    no_deref: bool,
+    stdin: bool,
+    message: Option<String>,
+) -> Result<()> {
+    let repo = Repository::for_working_directory()?;
+    let mut updates = vec![];
+    if stdin {
+        if !args.is_empty() {
+            return Err(Error::InvalidArgument(
+                "References can't be given alongside --stdin.".to_string(),
+            ));
+        }
+        for line in io::stdin().lock().lines() {
+            let line = line?;
+            if !line.is_empty() {
+                updates.push(parse_line(&repo, &line)?);
+            }
+        }
+    } else {
+        let command = if delete { "delete" } else { "update" };
+        let fields: Vec<_> = args.iter().map(String::as_str).collect();
+        updates.push(parse_update(&repo, command, &fields)?);
+    }
+
+    if !no_deref {
+        for update in &mut updates {
+            update.name = repo.dereference(&update.name)?;
+        }
+    }
+    repo.update_refs(&updates, &message.unwrap_or_default())
+}
+
+fn parse_line(repo: &Repository, line: &str) -> Result<RefUpdate> {
+    let mut fields = line.split(' ');
+    let command = fields.next().unwrap_or_default();
+    parse_update(repo, command, &fields.collect::<Vec<_>>())
+}
+
+fn parse_update(repo: &Repository, command: &str, fields: &[&str]) -> Result<RefUpdate> {
+    let update = match (command, fields) {
+        ("update", [name, new]) => RefUpdate::new(name, change(value(repo, new)?)),
+        ("update", [name, new, old]) => {
+            RefUpdate::new(name, change(value(repo, new)?)).expecting(value(repo, old)?)
+        }
+        ("create", [name, new]) => {
+            let new = value(repo, new)?.ok_or_else(|| {
+                Error::InvalidArgument(format!("{} can't be created with a zero value.", name))
+            })?;
+            RefUpdate::new(name, RefChange::Write(new)).expecting(None)
+        }
+        ("delete", [name]) => RefUpdate::new(name, RefChange::Delete),
+        ("delete", [name, old]) => {
+            RefUpdate::new(name, RefChange::Delete).expecting(value(repo, old)?)
+        }
+        // verifying without a value checks that the reference doesn't exist
+        ("verify", [name]) => RefUpdate::new(name, RefChange::Verify).expecting(None),
+        ("verify", [name, old]) => {
+            RefUpdate::new(name, RefChange::Verify).expecting(value(repo, old)?)
+        }
+        _ => {
+            return Err(Error::InvalidArgument(format!(
+                "{} {} is not a valid update.",
+                command,
+                fields.join(" ")
+            )))
+        }
+    };
+    RefUpdate::check_name(&update.name)?;
+    Ok(update)
+}
+
+fn value(repo: &Repository, spec: &str) -> Result<Option<Reference>> {
+    if !spec.is_empty() && spec.chars().all(|c| c == '0') {
+        return Ok(None);
+    }
+    repo.rev_parse(spec)
+        .map(|hash| Some(Reference::Commit(hash)))
+}
+
+fn change(new: Option<Reference>) -> RefChange {
+    new.map_or(RefChange::Delete, RefChange::Write)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::object::id::ObjectFormat;
+    use crate::repository::Repository;
+    use crate::subcommand::update_ref::{parse_line, parse_update};
+
+    #[test]
+    fn fails_to_parse_invalid_names() {
+        let repo = Repository::in_memory(ObjectFormat::Sha1);
+        for name in &[
+            "../../escaped",
+            "refs/heads/x..y",
+            "refs/heads/",
+            "config",
+            "Head",
+        ] {
+            assert!(matches!(
+                parse_update(&repo, "delete", &[name]),
+                Err(Error::InvalidRef(_))
+            ));
+            assert!(matches!(
+                parse_line(&repo, &format!("delete {}", name)),
+                Err(Error::InvalidRef(_))
+            ));
+        }
+        assert!(parse_update(&repo, "delete", &["refs/heads/master"]).is_ok());
+        assert!(parse_line(&repo, "verify ORIG_HEAD").is_ok());
+    }
+}
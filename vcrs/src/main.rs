@@ -1,6 +1,10 @@
 use clap::Clap;
 
-use gitrs::{cat_file, checkout, hash_object, init, log};
+use gitrs::config::layered::Scope;
+use gitrs::{
+    branch, cat_file, checkout, config_get, config_list, config_set, config_unset, gc, hash_object,
+    init, log, ls_files, pack_refs, reflog_expire, reflog_show, rev_parse, tag, update_ref, Error,
+};
 
 #[derive(Clap)]
 struct Opts {
@@ -10,16 +14,43 @@ struct Opts {
 
 #[derive(Clap)]
 enum SubCommand {
-    Init,
+    Init(Init),
     Log(Log),
     CatFile(CatFile),
     Checkout(Checkout),
     HashObject(HashObject),
+    Gc,
+    PackRefs(PackRefs),
+    RevParse(RevParse),
+    Reflog(Reflog),
+    UpdateRef(UpdateRef),
+    Branch(Branch),
+    Tag(Tag),
+    Config(Config),
+    LsFiles(LsFiles),
+}
+
+#[derive(Clap)]
+struct Init {
+    #[clap(long)]
+    object_format: Option<String>,
+    #[clap(long, takes_value = false)]
+    bare: bool,
+    #[clap(short = "b", long)]
+    initial_branch: Option<String>,
+    #[clap(long)]
+    template: Option<String>,
+    #[clap(long)]
+    shared: Option<String>,
 }
 
 #[derive(Clap)]
 struct Log {
     hash: Option<String>,
+    #[clap(long)]
+    date: Option<String>,
+    #[clap(long, takes_value = false)]
+    abbrev: bool,
 }
 
 #[derive(Clap)]
@@ -40,14 +71,260 @@ struct HashObject {
     write: bool,
 }
 
+#[derive(Clap)]
+struct PackRefs {
+    #[clap(long, takes_value = false)]
+    all: bool,
+}
+
+#[derive(Clap)]
+struct RevParse {
+    #[clap(required = true)]
+    revisions: Vec<String>,
+}
+
+#[derive(Clap)]
+struct Reflog {
+    #[clap(subcommand)]
+    action: Option<ReflogAction>,
+}
+
+#[derive(Clap)]
+enum ReflogAction {
+    Show(ReflogShow),
+    Expire(ReflogExpire),
+}
+
+#[derive(Clap)]
+struct ReflogShow {
+    reference: Option<String>,
+}
+
+#[derive(Clap)]
+struct ReflogExpire {
+    #[clap(long)]
+    expire: Option<String>,
+    #[clap(long, takes_value = false)]
+    all: bool,
+    references: Vec<String>,
+}
+
+#[derive(Clap)]
+struct UpdateRef {
+    #[clap(short)]
+    message: Option<String>,
+    #[clap(short, takes_value = false)]
+    delete: bool,
+    #[clap(long, takes_value = false)]
+    no_deref: bool,
+    #[clap(long, takes_value = false)]
+    stdin: bool,
+    args: Vec<String>,
+}
+
+#[derive(Clap)]
+struct Branch {
+    #[clap(short, takes_value = false)]
+    delete: bool,
+    #[clap(short = "D", takes_value = false)]
+    force_delete: bool,
+    #[clap(short = "m", takes_value = false)]
+    rename: bool,
+    #[clap(short, long, takes_value = false)]
+    track: bool,
+    #[clap(short = "u", long)]
+    set_upstream_to: Option<String>,
+    #[clap(long, takes_value = false)]
+    unset_upstream: bool,
+    names: Vec<String>,
+}
+
+#[derive(Clap)]
+struct Tag {
+    #[clap(short, takes_value = false)]
+    annotate: bool,
+    #[clap(short)]
+    message: Option<String>,
+    #[clap(short, takes_value = false)]
+    delete: bool,
+    #[clap(short, takes_value = false)]
+    list: bool,
+    #[clap(short, takes_value = false)]
+    force: bool,
+    #[clap(long)]
+    sort: Option<String>,
+    names: Vec<String>,
+}
+
+#[derive(Clap)]
+struct Config {
+    #[clap(subcommand)]
+    action: ConfigAction,
+}
+
+#[derive(Clap)]
+enum ConfigAction {
+    Get(ConfigGet),
+    Set(ConfigSet),
+    Unset(ConfigUnset),
+    List(ConfigList),
+}
+
+#[derive(Clap)]
+struct ConfigScope {
+    #[clap(long, takes_value = false)]
+    system: bool,
+    #[clap(long, takes_value = false)]
+    global: bool,
+    #[clap(long, takes_value = false)]
+    local: bool,
+}
+
+impl ConfigScope {
+    fn scope(&self) -> Option<Scope> {
+        if self.system {
+            Some(Scope::System)
+        } else if self.global {
+            Some(Scope::Global)
+        } else if self.local {
+            Some(Scope::Local)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clap)]
+struct ConfigGet {
+    #[clap(flatten)]
+    scope: ConfigScope,
+    #[clap(long, takes_value = false)]
+    all: bool,
+    #[clap(long = "type")]
+    kind: Option<String>,
+    key: String,
+}
+
+#[derive(Clap)]
+struct ConfigSet {
+    #[clap(flatten)]
+    scope: ConfigScope,
+    #[clap(long, takes_value = false)]
+    append: bool,
+    #[clap(long, takes_value = false)]
+    all: bool,
+    key: String,
+    value: String,
+}
+
+#[derive(Clap)]
+struct ConfigUnset {
+    #[clap(flatten)]
+    scope: ConfigScope,
+    #[clap(long, takes_value = false)]
+    all: bool,
+    key: String,
+}
+
+#[derive(Clap)]
+struct ConfigList {
+    #[clap(flatten)]
+    scope: ConfigScope,
+    #[clap(long, takes_value = false)]
+    show_origin: bool,
+    #[clap(long, takes_value = false)]
+    show_scope: bool,
+}
+
+#[derive(Clap)]
+struct LsFiles {
+    #[clap(short, long, takes_value = false)]
+    stage: bool,
+}
+
+fn exit_code(error: &Error) -> i32 {
+    match error {
+        Error::InvalidArgument(_) => 2,
+        Error::NotFound(_) => 3,
+        Error::InvalidRef(_) => 4,
+        Error::Corrupt(_) => 5,
+        Error::Refused(_) => 6,
+        // EX_IOERR from sysexits.h
+        Error::Io(_) => 74,
+    }
+}
+
 fn main() {
     let opts: Opts = Opts::parse();
 
-    match opts.subcmd {
-        SubCommand::Init => init(),
-        SubCommand::Log(args) => log(args.hash),
+    let result = match opts.subcmd {
+        SubCommand::Init(args) => init(
+            args.object_format,
+            args.bare,
+            args.initial_branch,
+            args.template,
+            args.shared,
+        ),
+        SubCommand::Log(args) => log(args.hash, args.date, args.abbrev),
         SubCommand::CatFile(args) => cat_file(args.object),
         SubCommand::Checkout(args) => checkout(args.commit),
         SubCommand::HashObject(args) => hash_object(args.kind, args.file, args.write),
+        SubCommand::Gc => gc(),
+        SubCommand::PackRefs(args) => pack_refs(args.all),
+        SubCommand::RevParse(args) => rev_parse(args.revisions),
+        SubCommand::Reflog(args) => match args.action {
+            None => reflog_show(None),
+            Some(ReflogAction::Show(args)) => reflog_show(args.reference),
+            Some(ReflogAction::Expire(args)) => {
+                reflog_expire(args.references, args.all, args.expire)
+            }
+        },
+        SubCommand::UpdateRef(args) => update_ref(
+            args.args,
+            args.delete,
+            args.no_deref,
+            args.stdin,
+            args.message,
+        ),
+        SubCommand::Branch(args) => branch(
+            args.names,
+            args.delete,
+            args.force_delete,
+            args.rename,
+            args.track,
+            args.set_upstream_to,
+            args.unset_upstream,
+        ),
+        SubCommand::Tag(args) => tag(
+            args.names,
+            args.annotate,
+            args.message,
+            args.delete,
+            args.list,
+            args.force,
+            args.sort,
+        ),
+        SubCommand::Config(args) => match args.action {
+            ConfigAction::Get(args) => {
+                config_get(args.key, args.all, args.kind, args.scope.scope())
+            }
+            ConfigAction::Set(args) => config_set(
+                args.key,
+                args.value,
+                args.append,
+                args.all,
+                args.scope.scope(),
+            ),
+            ConfigAction::Unset(args) => config_unset(args.key, args.all, args.scope.scope()),
+            ConfigAction::List(args) => {
+                config_list(args.show_origin, args.show_scope, args.scope.scope())
+            }
+        },
+        SubCommand::LsFiles(args) => ls_files(args.stage),
+    };
+
+    if let Err(error) = result {
+        eprintln!("error: {}", error);
+        std::process::exit(exit_code(&error));
     }
 }